@@ -8,7 +8,10 @@ pub struct Chunk {
     lines: Vec<(usize, usize)>,
 }
 
+// Building chunks is left to the compiler, which doesn't emit any code yet, so for now only the
+// tests build them
 impl Chunk {
+    #[allow(dead_code)]
     pub fn new() -> Chunk {
         Chunk {
             code: Vec::new(),
@@ -17,22 +20,31 @@ impl Chunk {
         }
     }
 
+    #[allow(dead_code)]
     pub fn write_code(&mut self, instruction: OpCode, line: usize) {
+        let start = self.code.len();
         instruction.encode(&mut self.code);
-        if let Some((last_line, _)) = self.lines.last() {
-            if *last_line < line {
-                self.lines.push((line, self.code.len() - 1));
-            }
-        } else {
-            // first instruction
-            self.lines.push((line, 0))
+        if !matches!(self.lines.last(), Some((last_line, _)) if *last_line == line) {
+            self.lines.push((line, start));
         }
     }
 
-    /// Returns the index of the new constant
+    /// Returns the index of the constant, reusing an equal one that's already in the pool.
+    ///
+    /// NaN is never equal to anything, so every NaN gets a new entry. 0 and -0 are equal but
+    /// print differently, so they're kept apart. Functions always get a new entry.
+    #[allow(dead_code)]
     pub fn add_constant(&mut self, constant: Value) -> usize {
-        self.constants.push(constant);
-        self.constants.len() - 1
+        let existing = self.constants.iter().position(|c| match (c, &constant) {
+            (Value::Number(a), Value::Number(b)) => {
+                a == b && a.is_sign_negative() == b.is_sign_negative()
+            }
+            _ => false,
+        });
+        existing.unwrap_or_else(|| {
+            self.constants.push(constant);
+            self.constants.len() - 1
+        })
     }
 
     #[allow(dead_code)]
@@ -63,9 +75,12 @@ impl Chunk {
                 OpCode::LargeConstant(constant) => {
                     println!(
                         "{:-16} {} '{:?}'",
-                        "L_CONSTANT", constant, self.constants[constant as usize]
+                        "L_CONSTANT", constant, self.constants[constant]
                     );
                 }
+                OpCode::GetLocal(slot) => {
+                    println!("{:-16} {}", "GET_LOCAL", slot);
+                }
                 OpCode::Add => {
                     println!("ADD");
                 }
@@ -81,6 +96,9 @@ impl Chunk {
                 OpCode::Negate => {
                     println!("NEGATE");
                 }
+                OpCode::Call(arg_count) => {
+                    println!("{:-16} {}", "CALL", arg_count);
+                }
                 OpCode::Return => {
                     println!("RETURN");
                 }
@@ -92,19 +110,14 @@ impl Chunk {
         }
     }
 
-    #[allow(clippy::comparison_chain)]
+    /// `lines` is sorted by start instruction, so this is a binary search
     pub fn get_line(&self, offset: usize) -> usize {
-        let mut last_line = 0;
-        for (line, current_offset) in &self.lines {
-            if offset == *current_offset {
-                return *line;
-            } else if offset < *current_offset {
-                // we passed the correct offset
-                return last_line;
-            }
-            last_line = *line;
+        // Index of the first run that starts after the offset
+        let after = self.lines.partition_point(|(_, start)| *start <= offset);
+        match after {
+            0 => 0,
+            after => self.lines[after - 1].0,
         }
-        last_line
     }
 }
 
@@ -112,10 +125,78 @@ impl Chunk {
 pub enum OpCode {
     Constant(u8),
     LargeConstant(usize),
+    /// Pushes the value in the given slot of the current call frame
+    GetLocal(u8),
     Add,
     Subtract,
     Multiply,
     Divide,
     Negate,
+    /// Calls the function below its arguments on the stack with this many arguments
+    Call(u8),
     Return,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What get_line used to do, walking every run
+    fn linear_get_line(chunk: &Chunk, offset: usize) -> usize {
+        chunk
+            .lines
+            .iter()
+            .take_while(|(_, start)| *start <= offset)
+            .last()
+            .map_or(0, |(line, _)| *line)
+    }
+
+    #[test]
+    fn get_line_matches_linear_scan() {
+        let mut chunk = Chunk::new();
+        for line in 1..=1000 {
+            // A varying number of instructions of varying lengths per line, and some lines skipped
+            for i in 0..line % 4 {
+                let instruction = match i {
+                    0 => OpCode::Constant(0),
+                    1 => OpCode::LargeConstant(line),
+                    _ => OpCode::Add,
+                };
+                chunk.write_code(instruction, line * 2);
+            }
+        }
+        for offset in 0..chunk.code.len() {
+            assert_eq!(chunk.get_line(offset), linear_get_line(&chunk, offset));
+        }
+    }
+
+    #[test]
+    fn equal_constants_are_reused() {
+        let mut chunk = Chunk::new();
+        let first = chunk.add_constant(Value::Number(1.5));
+        let other = chunk.add_constant(Value::Number(2.0));
+        let second = chunk.add_constant(Value::Number(1.5));
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(chunk.constants.len(), 2);
+    }
+
+    #[test]
+    fn nan_is_never_reused() {
+        let mut chunk = Chunk::new();
+        let first = chunk.add_constant(Value::Number(f64::NAN));
+        let second = chunk.add_constant(Value::Number(f64::NAN));
+        assert_ne!(first, second);
+        assert_eq!(chunk.constants.len(), 2);
+    }
+
+    #[test]
+    fn zero_and_negative_zero_are_kept_apart() {
+        let mut chunk = Chunk::new();
+        let zero = chunk.add_constant(Value::Number(0.0));
+        let negative_zero = chunk.add_constant(Value::Number(-0.0));
+        assert_ne!(zero, negative_zero);
+        assert_eq!(chunk.add_constant(Value::Number(-0.0)), negative_zero);
+        assert_eq!(chunk.constants.len(), 2);
+    }
+}
@@ -1,6 +1,9 @@
 use std::io::{stdin, BufRead, Write};
 
-use crate::vm::VM;
+use crate::{
+    value::Value,
+    vm::{VMError, VM},
+};
 
 mod chunk;
 mod compiler;
@@ -27,23 +30,67 @@ fn main() {
 }
 
 fn repl() {
-    let stdin = stdin();
-    let stdin = stdin.lock();
-    print!("> ");
-    std::io::stdout().flush().unwrap();
-    for line in stdin.lines() {
-        if let Ok(line) = line {
-            VM::new(line).unwrap().run().unwrap();
-            print!("> ");
-            std::io::stdout().flush().unwrap();
-        } else {
-            println!("bye");
-            break;
+    if let Err(e) = run_lines(stdin().lock(), &mut std::io::stdout()) {
+        eprintln!("Error reading from stdin: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Runs every line of the input, writing the prompts and results to the output. Errors from the VM
+/// are reported and the next line runs. Stops at the end of the input or at the first error
+/// reading it.
+fn run_lines(input: impl BufRead, output: &mut impl Write) -> std::io::Result<()> {
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        match run_source(line?) {
+            Ok(value) => writeln!(output, "{value:?}")?,
+            Err(e) => eprintln!("{e}"),
         }
+        write!(output, "> ")?;
+        output.flush()?;
     }
+    writeln!(output, "bye")
 }
 
 fn run_file(filename: &str) {
     let source = std::fs::read_to_string(filename).unwrap();
-    VM::new(source).unwrap().run().unwrap();
+    match run_source(source) {
+        Ok(value) => println!("{value:?}"),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_source(source: String) -> Result<Value, VMError> {
+    VM::new(source, None)?.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn end_of_input_says_bye() {
+        let mut output = Vec::new();
+        run_lines(&b""[..], &mut output).unwrap();
+        assert_eq!(output, b"> bye\n");
+    }
+
+    #[test]
+    fn read_error_is_reported() {
+        let mut output = Vec::new();
+        let error = run_lines(&b"\xff\n"[..], &mut output).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(output, b"> ");
+    }
+
+    #[test]
+    fn vm_error_is_reported_and_the_next_line_runs() {
+        let mut output = Vec::new();
+        run_lines(&b"@\n@\n"[..], &mut output).unwrap();
+        assert_eq!(output, b"> > > bye\n");
+    }
 }
@@ -15,6 +15,23 @@ impl Scanner {
         }
     }
 
+    /// Scans the whole source, up to but not including the Eof token. A bad character has
+    /// already been consumed when it's reported, so scanning goes on to report the rest.
+    pub fn scan_all(&mut self) -> (Vec<CodeToken>, Vec<ScanError>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            match self.scan_token() {
+                Ok(CodeToken {
+                    token: Token::Eof, ..
+                }) => break,
+                Ok(token) => tokens.push(token),
+                Err(e) => errors.push(e),
+            }
+        }
+        (tokens, errors)
+    }
+
     pub fn scan_token(&mut self) -> Result<CodeToken, ScanError> {
         self.skip_whitespace();
         self.lexeme_start = self.current_char;
@@ -296,21 +313,50 @@ impl Token {
             "this" => Token::This,
             "var" => Token::Var,
             "while" => Token::While,
+            "true" => Token::Literal(Literal::Boolean(true)),
+            "false" => Token::Literal(Literal::Boolean(false)),
+            "nil" => Token::Literal(Literal::Nil),
             _ => return None,
         })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_every_invalid_character() {
+        let (tokens, errors) = Scanner::new("1 @ +\n# 2".to_string()).scan_all();
+        let tokens: Vec<_> = tokens.into_iter().map(|t| t.token).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Literal(Literal::Number(1.0)),
+                Token::Plus,
+                Token::Literal(Literal::Number(2.0)),
+            ]
+        );
+        let errors: Vec<_> = errors.iter().map(|e| (e.line, e.error)).collect();
+        assert_eq!(
+            errors,
+            [(1, "Unexpected character."), (2, "Unexpected character.")]
+        );
+    }
 
-    pub fn is_statement_start(&self) -> bool {
-        matches!(
-            self,
-            Token::Class
-                | Token::Fun
-                | Token::Var
-                | Token::For
-                | Token::If
-                | Token::While
-                | Token::Print
-                | Token::Return
-        )
+    #[test]
+    fn boolean_and_nil_are_literals() {
+        let (tokens, errors) = Scanner::new("true false nil truth".to_string()).scan_all();
+        let tokens: Vec<_> = tokens.into_iter().map(|t| t.token).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Literal(Literal::Boolean(true)),
+                Token::Literal(Literal::Boolean(false)),
+                Token::Literal(Literal::Nil),
+                Token::Identifier("truth".to_string()),
+            ]
+        );
+        assert!(errors.is_empty());
     }
 }
@@ -1 +1,26 @@
-pub type Value = f64;
+use std::{fmt, rc::Rc};
+
+use crate::chunk::Chunk;
+
+#[derive(Clone)]
+pub enum Value {
+    // Only the compiler's constants bring numbers into the VM, and it doesn't emit any yet
+    #[allow(dead_code)]
+    Number(f64),
+    Function(Rc<Function>),
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{n:?}"),
+            Value::Function(function) => write!(f, "<fn {}>", function.name),
+        }
+    }
+}
+
+pub struct Function {
+    pub name: String,
+    pub arity: u8,
+    pub chunk: Chunk,
+}
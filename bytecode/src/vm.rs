@@ -1,97 +1,294 @@
+use std::{fmt, rc::Rc};
+
 use crate::{
     chunk::{Chunk, OpCode},
     compiler::compile,
-    value::Value,
+    value::{Function, Value},
 };
 use encode_instruction::EncodeInstruction;
 
+/// The default stack size
 const STACK_MAX: usize = 256;
 
 pub struct VM {
-    chunk: Chunk,
+    frames: Vec<CallFrame>,
+    stack: Vec<Value>,
+    /// Pushing past this many values is a stack overflow
+    stack_max: usize,
+}
+
+struct CallFrame {
+    function: Rc<Function>,
     ip: usize,
-    stack: [Value; STACK_MAX],
-    stack_top: usize,
+    /// Index of the called function on the stack, its arguments are right above it
+    slots_base: usize,
 }
 
 macro_rules! binary_op {
     ($self:ident, $op:tt) => {{
         let b = $self.stack_pop();
         let a = $self.stack_pop();
-        $self.stack_push(a $op b);
+        match (a, b) {
+            (Value::Number(a), Value::Number(b)) => $self.stack_push(Value::Number(a $op b))?,
+            _ => return Err(VMError::Runtime("Operands must be numbers")),
+        }
     }};
 }
 
 impl VM {
-    pub fn new(source: String) -> Result<Self, ()> {
-        Ok(Self {
-            chunk: compile(source)?,
-            ip: 0,
-            stack: [Value::default(); STACK_MAX],
-            stack_top: 0,
-        })
+    /// `stack_size` defaults to STACK_MAX
+    pub fn new(source: String, stack_size: Option<usize>) -> Result<Self, VMError> {
+        let chunk = compile(source).map_err(|()| VMError::Compile)?;
+        Ok(Self::from_chunk(chunk, stack_size))
+    }
+
+    /// Runs an already compiled chunk as the top level script
+    pub fn from_chunk(chunk: Chunk, stack_size: Option<usize>) -> Self {
+        let stack_max = stack_size.unwrap_or(STACK_MAX);
+        let script = Rc::new(Function {
+            name: "script".to_string(),
+            arity: 0,
+            chunk,
+        });
+        let mut stack = Vec::with_capacity(stack_max);
+        stack.push(Value::Function(script.clone()));
+        Self {
+            frames: vec![CallFrame {
+                function: script,
+                ip: 0,
+                slots_base: 0,
+            }],
+            stack,
+            stack_max,
+        }
     }
 
-    pub fn run(&mut self) -> Result<(), VMError> {
+    /// Returns the value returned by the top level script
+    pub fn run(&mut self) -> Result<Value, VMError> {
         loop {
             #[cfg(feature = "tracing")]
             {
                 print!("          ");
-                for value in self.stack[0..self.stack_top].iter().rev() {
+                for value in self.stack.iter().rev() {
                     print!("[ {value:?} ]");
                 }
                 println!();
-                self.chunk.disassemble_instruction(self.ip);
+                let frame = self.frame();
+                frame.function.chunk.disassemble_instruction(frame.ip);
             }
 
             match self.read_instruction().expect("next instruction") {
                 OpCode::Constant(addr) => {
-                    let constant = self.chunk.constants[addr as usize];
-                    self.stack_push(constant);
+                    let constant = self.frame().function.chunk.constants[addr as usize].clone();
+                    self.stack_push(constant)?;
                 }
                 OpCode::LargeConstant(addr) => {
-                    let constant = self.chunk.constants[addr];
-                    self.stack_push(constant);
+                    let constant = self.frame().function.chunk.constants[addr].clone();
+                    self.stack_push(constant)?;
+                }
+                OpCode::GetLocal(slot) => {
+                    let value = self.stack[self.frame().slots_base + slot as usize].clone();
+                    self.stack_push(value)?;
                 }
                 OpCode::Add => binary_op!(self, +),
                 OpCode::Subtract => binary_op!(self, -),
                 OpCode::Multiply => binary_op!(self, *),
                 OpCode::Divide => binary_op!(self, /),
-                OpCode::Negate => {
-                    let v = self.stack_pop();
-                    self.stack_push(-v);
-                }
+                OpCode::Negate => match self.stack_pop() {
+                    Value::Number(n) => self.stack_push(Value::Number(-n))?,
+                    _ => return Err(VMError::Runtime("Operand must be a number")),
+                },
+                OpCode::Call(arg_count) => self.call(arg_count)?,
                 OpCode::Return => {
-                    println!("{:?}", self.stack_pop());
-                    return Ok(());
+                    let result = self.stack_pop();
+                    let frame = self.frames.pop().expect("a frame is running");
+                    // Drop the callee and its arguments, the caller's frame continues at its ip
+                    self.stack.truncate(frame.slots_base);
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.stack_push(result)?;
                 }
             }
         }
     }
 
+    fn call(&mut self, arg_count: u8) -> Result<(), VMError> {
+        let slots_base = self.stack.len() - 1 - arg_count as usize;
+        let Value::Function(function) = &self.stack[slots_base] else {
+            return Err(VMError::Runtime("Can only call functions"));
+        };
+        if function.arity != arg_count {
+            return Err(VMError::Runtime("Wrong number of arguments"));
+        }
+        self.frames.push(CallFrame {
+            function: function.clone(),
+            ip: 0,
+            slots_base,
+        });
+        Ok(())
+    }
+
     // Chunk util
 
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("a frame is running")
+    }
+
     fn read_instruction(&mut self) -> Option<OpCode> {
-        let (ans, len) = OpCode::decode(&self.chunk.code[self.ip..])?;
-        self.ip += len;
+        let frame = self.frames.last_mut()?;
+        let (ans, len) = OpCode::decode(&frame.function.chunk.code[frame.ip..])?;
+        frame.ip += len;
         Some(ans)
     }
 
     // Stack util
 
-    fn stack_push(&mut self, value: Value) {
-        self.stack[self.stack_top] = value;
-        self.stack_top += 1;
+    fn stack_push(&mut self, value: Value) -> Result<(), VMError> {
+        if self.stack.len() >= self.stack_max {
+            return Err(VMError::Runtime("Stack overflow"));
+        }
+        self.stack.push(value);
+        Ok(())
     }
 
     fn stack_pop(&mut self) -> Value {
-        self.stack_top -= 1;
-        self.stack[self.stack_top]
+        self.stack
+            .pop()
+            .expect("compiler emits balanced pushes and pops")
     }
 }
 
 #[derive(Debug)]
 pub enum VMError {
     Compile,
-    Runtime,
+    Runtime(&'static str),
+}
+
+impl fmt::Display for VMError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VMError::Compile => write!(f, "Compile error."),
+            VMError::Runtime(message) => write!(f, "Runtime error: {message}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `fun add(a, b) { return a + b; }`
+    fn add_function() -> Value {
+        let mut chunk = Chunk::new();
+        chunk.write_code(OpCode::GetLocal(1), 1);
+        chunk.write_code(OpCode::GetLocal(2), 1);
+        chunk.write_code(OpCode::Add, 1);
+        chunk.write_code(OpCode::Return, 1);
+        Value::Function(Rc::new(Function {
+            name: "add".to_string(),
+            arity: 2,
+            chunk,
+        }))
+    }
+
+    /// A script calling `function` with the given numbers, leaving the result on the stack
+    fn call_script(function: Value, args: &[f64]) -> Chunk {
+        let mut chunk = Chunk::new();
+        let function = chunk.add_constant(function);
+        chunk.write_code(OpCode::Constant(function as u8), 1);
+        for arg in args {
+            let arg = chunk.add_constant(Value::Number(*arg));
+            chunk.write_code(OpCode::Constant(arg as u8), 1);
+        }
+        chunk.write_code(OpCode::Call(args.len() as u8), 1);
+        chunk
+    }
+
+    fn run_script(mut chunk: Chunk) -> Result<Value, VMError> {
+        chunk.write_code(OpCode::Return, 2);
+        VM::from_chunk(chunk, None).run()
+    }
+
+    #[test]
+    fn call_adds_parameters() {
+        let mut chunk = call_script(add_function(), &[3.0, 4.0]);
+        chunk.write_code(OpCode::Return, 2);
+        let mut vm = VM::from_chunk(chunk, None);
+        assert!(matches!(vm.run(), Ok(Value::Number(n)) if n == 7.0));
+        // Returning dropped every frame's callee and arguments
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn return_resumes_the_caller() {
+        // add(1, 2) * 10, where the multiplication runs after the call returns
+        let mut chunk = call_script(add_function(), &[1.0, 2.0]);
+        let ten = chunk.add_constant(Value::Number(10.0));
+        chunk.write_code(OpCode::Constant(ten as u8), 1);
+        chunk.write_code(OpCode::Multiply, 1);
+        assert!(matches!(run_script(chunk), Ok(Value::Number(n)) if n == 30.0));
+    }
+
+    #[test]
+    fn wrong_argument_count() {
+        assert!(matches!(
+            run_script(call_script(add_function(), &[1.0])),
+            Err(VMError::Runtime("Wrong number of arguments"))
+        ));
+    }
+
+    #[test]
+    fn calling_a_number() {
+        assert!(matches!(
+            run_script(call_script(Value::Number(1.0), &[])),
+            Err(VMError::Runtime("Can only call functions"))
+        ));
+    }
+
+    #[test]
+    fn deep_pushes_overflow_the_stack() {
+        let mut chunk = Chunk::new();
+        let one = chunk.add_constant(Value::Number(1.0));
+        // The script itself takes the first slot, so the last push is one too many
+        for _ in 0..4 {
+            chunk.write_code(OpCode::Constant(one as u8), 1);
+        }
+        chunk.write_code(OpCode::Return, 1);
+        let mut vm = VM::from_chunk(chunk, Some(4));
+        assert!(matches!(vm.run(), Err(VMError::Runtime("Stack overflow"))));
+        assert_eq!(vm.stack.len(), 4);
+    }
+
+    #[test]
+    fn pushes_up_to_the_limit_are_fine() {
+        let mut chunk = Chunk::new();
+        let one = chunk.add_constant(Value::Number(1.0));
+        for _ in 0..3 {
+            chunk.write_code(OpCode::Constant(one as u8), 1);
+        }
+        chunk.write_code(OpCode::Return, 1);
+        assert!(matches!(
+            VM::from_chunk(chunk, Some(4)).run(),
+            Ok(Value::Number(n)) if n == 1.0
+        ));
+    }
+
+    #[test]
+    fn runaway_recursion_overflows_the_stack() {
+        // A function that calls itself forever, each call leaving itself on the stack
+        let mut chunk = Chunk::new();
+        chunk.write_code(OpCode::GetLocal(0), 1);
+        chunk.write_code(OpCode::Call(0), 1);
+        chunk.write_code(OpCode::Return, 1);
+        let recurse = Value::Function(Rc::new(Function {
+            name: "recurse".to_string(),
+            arity: 0,
+            chunk,
+        }));
+        assert!(matches!(
+            run_script(call_script(recurse, &[])),
+            Err(VMError::Runtime("Stack overflow"))
+        ));
+    }
 }
@@ -53,7 +53,6 @@ fn impl_encode_instruction(ast: &syn::DeriveInput) -> TokenStream {
 
 fn encode_variant(idx: u8, enum_ident: &Ident, variant: &Variant) -> impl ToTokens {
     let ident = &variant.ident;
-    let idx = idx as u8;
 
     match &variant.fields {
         Fields::Named(FieldsNamed { named, .. }) => {
@@ -90,7 +89,6 @@ fn encode_variant(idx: u8, enum_ident: &Ident, variant: &Variant) -> impl ToToke
 
 fn decode_variant(idx: u8, enum_ident: &Ident, variant: &Variant) -> impl ToTokens {
     let ident = &variant.ident;
-    let idx = idx as u8;
 
     match &variant.fields {
         Fields::Named(FieldsNamed { named, .. }) => {
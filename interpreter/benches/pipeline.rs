@@ -0,0 +1,104 @@
+//! Benchmarks for each stage of the interpreter.
+//!
+//! Run them with `cargo bench -p rlox --bench pipeline`, or only some of them by adding a filter,
+//! e.g. `cargo bench -p rlox --bench pipeline -- scan`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rlox::{interpreter::Interpreter, parser::Parser, resolver::Resolver, scanner::Scanner};
+
+/// A hundred lines of varied tokens
+fn large_source() -> String {
+    (0..100)
+        .map(|i| format!("var v{i} = \"string {i}\" + {i}.5 * (v{i} - 3) / 2; // comment\n"))
+        .collect()
+}
+
+fn nested_expression() -> String {
+    let depth = 100;
+    format!("{}1{};", "(1 + ".repeat(depth), ")".repeat(depth))
+}
+
+const FIB: &str = "
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+fib(20);
+";
+
+/// Reads of a variable declared many scopes further out
+const DEEP_READS: &str = "
+var total = 0;
+fun outer() {
+    var x = 1;
+    { { { { { { { { { {
+        for (var i = 0; i < 1000; i = i + 1) total = total + x;
+    } } } } } } } } } }
+}
+outer();
+";
+
+fn scan(c: &mut Criterion) {
+    let source = large_source();
+    c.bench_function("scan", |b| {
+        b.iter(|| Scanner::new(black_box(source.clone())).tokens().unwrap())
+    });
+}
+
+fn parse(c: &mut Criterion) {
+    let tokens = Scanner::new(nested_expression()).tokens().unwrap();
+    c.bench_function("parse nested expression", |b| {
+        b.iter(|| Parser::new(black_box(tokens.clone())).parse().unwrap())
+    });
+}
+
+/// Cloning a resolved program is cheap when function and loop bodies are shared
+fn clone_program(c: &mut Criterion) {
+    let source: String = (0..100)
+        .map(|i| {
+            format!(
+                "fun f{i}(n) {{ if (n < {i}) {{ print n; }} else {{ while (n > 0) n = n - 1; }} }}\n"
+            )
+        })
+        .collect();
+    let tokens = Scanner::new(source).tokens().unwrap();
+    let ast = Parser::new(tokens).parse().unwrap();
+    let ast = Resolver::new().resolve(ast).unwrap();
+    c.bench_function("clone program", |b| b.iter(|| black_box(&ast).clone()));
+}
+
+fn interpret(c: &mut Criterion) {
+    let tokens = Scanner::new(FIB.to_string()).tokens().unwrap();
+    let ast = Parser::new(tokens).parse().unwrap();
+    let ast = Resolver::new().resolve(ast).unwrap();
+    c.bench_function("interpret fib", |b| {
+        b.iter(|| {
+            Interpreter::new()
+                .interpret(black_box(ast.clone()))
+                .unwrap()
+        })
+    });
+}
+
+fn interpret_deep_reads(c: &mut Criterion) {
+    let tokens = Scanner::new(DEEP_READS.to_string()).tokens().unwrap();
+    let ast = Parser::new(tokens).parse().unwrap();
+    let ast = Resolver::new().resolve(ast).unwrap();
+    c.bench_function("interpret deep reads", |b| {
+        b.iter(|| {
+            Interpreter::new()
+                .interpret(black_box(ast.clone()))
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    scan,
+    parse,
+    clone_program,
+    interpret,
+    interpret_deep_reads
+);
+criterion_main!(benches);
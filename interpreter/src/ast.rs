@@ -1,10 +1,15 @@
+use std::rc::Rc;
+
 use crate::{
     error::Located,
-    token::{Literal, Symbol, Token},
+    token::{Keyword, Literal, Symbol, Token},
 };
 
 // Expressions
 
+/// The name of every function made by a Lambda expression
+pub const ANONYMOUS: &str = "anonymous";
+
 pub type GenericCodeExpression<V> = Located<Expression<V>>;
 pub type CodeExpression = Located<Expression<String>>;
 pub type ResolvedCodeExpression = Located<Expression<ResolvedVariable>>;
@@ -17,8 +22,34 @@ pub enum Expression<V> {
         Box<GenericCodeExpression<V>>,
     ),
     Call(Box<GenericCodeExpression<V>>, Vec<GenericCodeExpression<V>>),
+    /// `object.name`
+    Get(Box<GenericCodeExpression<V>>, String),
+    /// `object?.name`, which is nil when the object is
+    OptionalGet(Box<GenericCodeExpression<V>>, String),
+    /// `this` inside of a method, resolved like a variable
+    This(V),
+    /// `super.name` inside of a method. The variables are `super`, which is the superclass, and
+    /// `this`, which the method is bound to.
+    Super(V, V, String),
+    /// `fun (params) { body }`, a function without a name. The body and the captured variables are
+    /// like Function's.
+    Lambda(Vec<String>, Rc<Vec<GenericStatement<V>>>, Vec<V>),
+    /// `object.name = value`
+    Set(
+        Box<GenericCodeExpression<V>>,
+        String,
+        Box<GenericCodeExpression<V>>,
+    ),
     Grouping(Box<GenericCodeExpression<V>>),
+    Index(Box<GenericCodeExpression<V>>, Box<GenericCodeExpression<V>>),
+    List(Vec<GenericCodeExpression<V>>),
     Literal(Literal),
+    /// Key and value pairs
+    Map(Vec<(GenericCodeExpression<V>, GenericCodeExpression<V>)>),
+    /// `a, b, c` evaluates every expression in order and results in the last one
+    Sequence(Vec<GenericCodeExpression<V>>),
+    /// `(a, b, c)`, or the value of `return a, b, c;`
+    Tuple(Vec<GenericCodeExpression<V>>),
     Unary(UnaryOperator, Box<GenericCodeExpression<V>>),
     Variable(V),
     // TODO: I dont like assignment being an expression. I want it to be a statement.
@@ -53,9 +84,14 @@ pub enum BinaryOperator {
     Add,
     Subtract,
     Divide,
+    /// `a div b`, division rounded down. It's a keyword because `//` already starts a comment.
+    FloorDivide,
     Multiply,
+    /// `a ** b`, which binds tighter than unary minus and groups to the right
+    Power,
     Equals,
     NotEquals,
+    Is,
     Less,
     LessEquals,
     Greater,
@@ -70,13 +106,18 @@ impl BinaryOperator {
             Token::Symbol(Symbol::Plus) => Some(BinaryOperator::Add),
             Token::Symbol(Symbol::Minus) => Some(BinaryOperator::Subtract),
             Token::Symbol(Symbol::Slash) => Some(BinaryOperator::Divide),
+            Token::Keyword(Keyword::Div) => Some(BinaryOperator::FloorDivide),
             Token::Symbol(Symbol::Star) => Some(BinaryOperator::Multiply),
+            Token::Symbol(Symbol::StarStar) => Some(BinaryOperator::Power),
             Token::Symbol(Symbol::EqualEqual) => Some(BinaryOperator::Equals),
             Token::Symbol(Symbol::BangEqual) => Some(BinaryOperator::NotEquals),
+            Token::Keyword(Keyword::Is) => Some(BinaryOperator::Is),
             Token::Symbol(Symbol::Less) => Some(BinaryOperator::Less),
             Token::Symbol(Symbol::LessEqual) => Some(BinaryOperator::LessEquals),
             Token::Symbol(Symbol::Greater) => Some(BinaryOperator::Greater),
             Token::Symbol(Symbol::GreaterEqual) => Some(BinaryOperator::GreaterEquals),
+            Token::Keyword(Keyword::And) => Some(BinaryOperator::And),
+            Token::Keyword(Keyword::Or) => Some(BinaryOperator::Or),
             _ => None,
         }
     }
@@ -89,15 +130,191 @@ pub type ResolvedStatement = GenericStatement<ResolvedVariable>;
 #[derive(Debug, Clone)]
 pub enum GenericStatement<V> {
     Expression(GenericCodeExpression<V>),
-    Function(String, Vec<String>, Vec<GenericStatement<V>>),
+    /// The name is located at the `fun`, or at the name itself for methods. The body is shared with
+    /// every function value created from this declaration. The flag is set for a `fun*` generator.
+    ///
+    /// Last are the local variables from outside of the function that it uses, as seen from where
+    /// it's declared. The resolver fills them in, except for methods, whose class captures for them.
+    Function(
+        Located<String>,
+        Vec<String>,
+        Rc<Vec<GenericStatement<V>>>,
+        bool,
+        Vec<V>,
+    ),
+    /// The class name (located at the `class`), its superclass, its methods, which are all
+    /// Function statements, and the variables its methods capture, like Function's
+    Class(
+        Located<String>,
+        Option<GenericCodeExpression<V>>,
+        Vec<GenericStatement<V>>,
+        Vec<V>,
+    ),
     Print(GenericCodeExpression<V>),
-    Return(Option<GenericCodeExpression<V>>),
-    Var(String, Option<GenericCodeExpression<V>>),
-    While(GenericCodeExpression<V>, Box<GenericStatement<V>>),
+    /// Condition and an optional message
+    Assert(GenericCodeExpression<V>, Option<GenericCodeExpression<V>>),
+    /// Located at the `return`. The flag is set by the resolver when the value is a call that can
+    /// replace the current one, instead of nesting inside it.
+    Return(Located<Option<GenericCodeExpression<V>>>, bool),
+    Throw(GenericCodeExpression<V>),
+    /// The name is located at the `var`
+    Var(Located<String>, Option<GenericCodeExpression<V>>),
+    /// `var (a, b) = value;` declares a variable for every element of a tuple
+    VarTuple(Vec<String>, GenericCodeExpression<V>),
+    /// The condition, the body, and the increment of a `for` loop, which is kept apart from the
+    /// body so that `continue` doesn't skip it
+    While(
+        GenericCodeExpression<V>,
+        Box<GenericStatement<V>>,
+        Option<GenericCodeExpression<V>>,
+    ),
+    /// Like While, but the body runs once before the condition is checked
+    DoWhile(Box<GenericStatement<V>>, GenericCodeExpression<V>),
     Block(Vec<GenericStatement<V>>),
+    /// `label: { ... }`, a block that `break label;` leaves early
+    LabeledBlock(String, Vec<GenericStatement<V>>),
+    /// `break;` or `break label;`, located at the `break`
+    Break(Located<Option<String>>),
+    /// `continue;`, located at the `continue`
+    Continue((usize, usize)),
+    /// `yield value;`, only allowed in a generator
+    Yield(GenericCodeExpression<V>),
+    /// `foreach (name in iterable) body`. The body is shared with the generators it runs for
+    Foreach(String, GenericCodeExpression<V>, Rc<GenericStatement<V>>),
+    /// The try block, the name the error is caught into, and the catch block
+    TryCatch(Vec<GenericStatement<V>>, String, Vec<GenericStatement<V>>),
     If(
         GenericCodeExpression<V>,
         Box<GenericStatement<V>>,
         Option<Box<GenericStatement<V>>>,
     ),
 }
+
+impl<V> GenericStatement<V> {
+    /// The location of the keyword for declarations, `return`, `break` and `continue`, and of the
+    /// first expression for other statements. (0, 0) for a block with nothing in it.
+    pub fn location(&self) -> (usize, usize) {
+        match self {
+            GenericStatement::Expression(e)
+            | GenericStatement::Print(e)
+            | GenericStatement::Assert(e, _)
+            | GenericStatement::VarTuple(_, e)
+            | GenericStatement::Throw(e)
+            | GenericStatement::Yield(e)
+            | GenericStatement::Foreach(_, e, _)
+            | GenericStatement::While(e, ..)
+            | GenericStatement::DoWhile(_, e)
+            | GenericStatement::If(e, ..) => e.location,
+            GenericStatement::Break(b) => b.location,
+            GenericStatement::Return(r, _) => r.location,
+            GenericStatement::Var(name, _)
+            | GenericStatement::Function(name, ..)
+            | GenericStatement::Class(name, ..) => name.location,
+            GenericStatement::Continue(location) => *location,
+            GenericStatement::Block(b)
+            | GenericStatement::LabeledBlock(_, b)
+            | GenericStatement::TryCatch(b, ..) => b
+                .iter()
+                .map(|s| s.location())
+                .find(|l| *l != (0, 0))
+                .unwrap_or((0, 0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn locations(source: &str) -> Vec<(usize, usize)> {
+        let tokens = Scanner::new(source.to_string()).tokens().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        program.iter().map(|s| s.location()).collect()
+    }
+
+    #[test]
+    fn statement_locations() {
+        let source = "\
+if (a) print 1;
+while (b) {}
+print 1 + 2;
+  var x = 3;
+fun f() {}
+{ print 4; }
+{}
+return;
+";
+        assert_eq!(
+            locations(source),
+            [
+                (1, 5),
+                (2, 8),
+                (3, 9),
+                (4, 3),
+                (5, 1),
+                (6, 9),
+                (0, 0),
+                (8, 1)
+            ]
+        );
+    }
+
+    /// A token for every operator, which fails to compile when an operator is added without one
+    fn token_for(operator: &BinaryOperator) -> Token {
+        match operator {
+            BinaryOperator::Add => Token::Symbol(Symbol::Plus),
+            BinaryOperator::Subtract => Token::Symbol(Symbol::Minus),
+            BinaryOperator::Divide => Token::Symbol(Symbol::Slash),
+            BinaryOperator::FloorDivide => Token::Keyword(Keyword::Div),
+            BinaryOperator::Multiply => Token::Symbol(Symbol::Star),
+            BinaryOperator::Power => Token::Symbol(Symbol::StarStar),
+            BinaryOperator::Equals => Token::Symbol(Symbol::EqualEqual),
+            BinaryOperator::NotEquals => Token::Symbol(Symbol::BangEqual),
+            BinaryOperator::Is => Token::Keyword(Keyword::Is),
+            BinaryOperator::Less => Token::Symbol(Symbol::Less),
+            BinaryOperator::LessEquals => Token::Symbol(Symbol::LessEqual),
+            BinaryOperator::Greater => Token::Symbol(Symbol::Greater),
+            BinaryOperator::GreaterEquals => Token::Symbol(Symbol::GreaterEqual),
+            BinaryOperator::And => Token::Keyword(Keyword::And),
+            BinaryOperator::Or => Token::Keyword(Keyword::Or),
+        }
+    }
+
+    #[test]
+    fn every_binary_operator_comes_from_a_token() {
+        let operators = [
+            BinaryOperator::Add,
+            BinaryOperator::Subtract,
+            BinaryOperator::Divide,
+            BinaryOperator::FloorDivide,
+            BinaryOperator::Multiply,
+            BinaryOperator::Power,
+            BinaryOperator::Equals,
+            BinaryOperator::NotEquals,
+            BinaryOperator::Is,
+            BinaryOperator::Less,
+            BinaryOperator::LessEquals,
+            BinaryOperator::Greater,
+            BinaryOperator::GreaterEquals,
+            BinaryOperator::And,
+            BinaryOperator::Or,
+        ];
+        for operator in operators {
+            let parsed = BinaryOperator::from_token(token_for(&operator));
+            assert_eq!(format!("{parsed:?}"), format!("Some({operator:?})"));
+        }
+    }
+
+    #[test]
+    fn other_tokens_are_not_binary_operators() {
+        for token in [
+            Token::Symbol(Symbol::Bang),
+            Token::Symbol(Symbol::Equal),
+            Token::Keyword(Keyword::While),
+            Token::Eof,
+        ] {
+            assert!(BinaryOperator::from_token(token).is_none());
+        }
+    }
+}
@@ -0,0 +1,735 @@
+//! Saving resolved programs to bytes and loading them back, so a script that hasn't changed can
+//! skip scanning, parsing and resolving.
+//!
+//! The format is a header (magic, format version, hash of the source) followed by the statements,
+//! each node written as a one byte tag followed by its fields. Numbers are little endian, strings
+//! and lists are prefixed by their length.
+
+use std::{collections::HashSet, rc::Rc};
+
+use crate::{
+    ast::{
+        BinaryOperator, Expression, GenericStatement, ResolvedCodeExpression, ResolvedStatement,
+        ResolvedVariable, UnaryOperator,
+    },
+    error::Located,
+    token::Literal,
+};
+
+const MAGIC: &[u8; 4] = b"rlox";
+/// Bump whenever the format changes, or the same source starts to mean something else, so old
+/// caches are ignored instead of misread
+const VERSION: u8 = 9;
+
+/// Serializes a resolved program. `source` is the source it was resolved from.
+pub fn to_bytes(source: &str, program: &[ResolvedStatement]) -> Vec<u8> {
+    let mut writer = Writer { bytes: Vec::new() };
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u8(VERSION);
+    writer.bytes.extend_from_slice(&hash(source).to_le_bytes());
+    writer.statements(program);
+    writer.bytes
+}
+
+/// Loads a program saved by [`to_bytes`].
+///
+/// Returns none if the bytes aren't a valid cache, or if they were saved from a different source.
+pub fn from_bytes(source: &str, bytes: &[u8]) -> Option<Vec<ResolvedStatement>> {
+    let mut reader = Reader {
+        bytes,
+        strings: HashSet::new(),
+    };
+    if reader.take(MAGIC.len())? != MAGIC
+        || reader.u8()? != VERSION
+        || reader.u64()? != hash(source)
+    {
+        return None;
+    }
+    let program = reader.statements()?;
+    reader.bytes.is_empty().then_some(program)
+}
+
+/// FNV-1a, which unlike the std hashers is guaranteed to stay the same between Rust versions
+fn hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.bytes.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    fn location(&mut self, location: (usize, usize)) {
+        self.usize(location.0);
+        self.usize(location.1);
+    }
+
+    fn located_string(&mut self, value: &Located<String>) {
+        self.location(value.location);
+        self.string(&value.value);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn strings(&mut self, values: &[String]) {
+        self.usize(values.len());
+        for value in values {
+            self.string(value);
+        }
+    }
+
+    fn statements(&mut self, statements: &[ResolvedStatement]) {
+        self.usize(statements.len());
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &ResolvedStatement) {
+        match statement {
+            GenericStatement::Expression(e) => {
+                self.u8(0);
+                self.expression(e);
+            }
+            GenericStatement::Function(name, params, body, generator, captures) => {
+                self.u8(1);
+                self.located_string(name);
+                self.strings(params);
+                self.statements(body);
+                self.u8(*generator as u8);
+                self.variables(captures);
+            }
+            GenericStatement::Print(e) => {
+                self.u8(2);
+                self.expression(e);
+            }
+            GenericStatement::Assert(condition, message) => {
+                self.u8(3);
+                self.expression(condition);
+                self.optional_expression(message.as_ref());
+            }
+            GenericStatement::Return(e, tail) => {
+                self.u8(4);
+                self.location(e.location);
+                self.optional_expression(e.value.as_ref());
+                self.u8(*tail as u8);
+            }
+            GenericStatement::Throw(e) => {
+                self.u8(5);
+                self.expression(e);
+            }
+            GenericStatement::Var(name, e) => {
+                self.u8(6);
+                self.located_string(name);
+                self.optional_expression(e.as_ref());
+            }
+            GenericStatement::VarTuple(names, e) => {
+                self.u8(12);
+                self.strings(names);
+                self.expression(e);
+            }
+            GenericStatement::Class(name, superclass, methods, captures) => {
+                self.u8(15);
+                self.located_string(name);
+                self.optional_expression(superclass.as_ref());
+                self.statements(methods);
+                self.variables(captures);
+            }
+            GenericStatement::LabeledBlock(label, b) => {
+                self.u8(13);
+                self.string(label);
+                self.statements(b);
+            }
+            GenericStatement::Break(b) => {
+                self.u8(14);
+                self.location(b.location);
+                match &b.value {
+                    Some(label) => {
+                        self.u8(1);
+                        self.string(label);
+                    }
+                    None => self.u8(0),
+                }
+            }
+            GenericStatement::While(condition, body, increment) => {
+                self.u8(7);
+                self.expression(condition);
+                self.statement(body);
+                self.optional_expression(increment.as_ref());
+            }
+            GenericStatement::Continue(location) => {
+                self.u8(16);
+                self.location(*location);
+            }
+            GenericStatement::Yield(e) => {
+                self.u8(17);
+                self.expression(e);
+            }
+            GenericStatement::Foreach(name, iterable, body) => {
+                self.u8(18);
+                self.string(name);
+                self.expression(iterable);
+                self.statement(body);
+            }
+            GenericStatement::DoWhile(body, condition) => {
+                self.u8(8);
+                self.statement(body);
+                self.expression(condition);
+            }
+            GenericStatement::Block(b) => {
+                self.u8(9);
+                self.statements(b);
+            }
+            GenericStatement::TryCatch(body, name, handler) => {
+                self.u8(10);
+                self.statements(body);
+                self.string(name);
+                self.statements(handler);
+            }
+            GenericStatement::If(condition, then_branch, else_branch) => {
+                self.u8(11);
+                self.expression(condition);
+                self.statement(then_branch);
+                match else_branch {
+                    Some(e) => {
+                        self.u8(1);
+                        self.statement(e);
+                    }
+                    None => self.u8(0),
+                }
+            }
+        }
+    }
+
+    fn optional_expression(&mut self, expression: Option<&ResolvedCodeExpression>) {
+        match expression {
+            Some(e) => {
+                self.u8(1);
+                self.expression(e);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn expressions(&mut self, expressions: &[ResolvedCodeExpression]) {
+        self.usize(expressions.len());
+        for expression in expressions {
+            self.expression(expression);
+        }
+    }
+
+    fn expression(&mut self, expression: &ResolvedCodeExpression) {
+        self.location(expression.location);
+        match &expression.value {
+            Expression::Binary(left, operator, right) => {
+                self.u8(0);
+                self.expression(left);
+                self.u8(binary_operator_tag(operator));
+                self.expression(right);
+            }
+            Expression::Call(callee, args) => {
+                self.u8(1);
+                self.expression(callee);
+                self.expressions(args);
+            }
+            Expression::Grouping(e) => {
+                self.u8(2);
+                self.expression(e);
+            }
+            Expression::Index(list, index) => {
+                self.u8(3);
+                self.expression(list);
+                self.expression(index);
+            }
+            Expression::List(elements) => {
+                self.u8(4);
+                self.expressions(elements);
+            }
+            Expression::Literal(literal) => {
+                self.u8(5);
+                self.literal(literal);
+            }
+            Expression::Sequence(expressions) => {
+                self.u8(6);
+                self.expressions(expressions);
+            }
+            Expression::Tuple(elements) => {
+                self.u8(13);
+                self.expressions(elements);
+            }
+            Expression::OptionalGet(object, name) => {
+                self.u8(14);
+                self.expression(object);
+                self.string(name);
+            }
+            Expression::This(v) => {
+                self.u8(15);
+                self.variable(v);
+            }
+            Expression::Super(v, this, method) => {
+                self.u8(16);
+                self.variable(v);
+                self.variable(this);
+                self.string(method);
+            }
+            Expression::Lambda(params, body, captures) => {
+                self.u8(17);
+                self.strings(params);
+                self.statements(body);
+                self.variables(captures);
+            }
+            Expression::Unary(operator, e) => {
+                self.u8(7);
+                self.u8(match operator {
+                    UnaryOperator::Minus => 0,
+                    UnaryOperator::Not => 1,
+                });
+                self.expression(e);
+            }
+            Expression::Map(entries) => {
+                self.u8(10);
+                self.usize(entries.len());
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::Get(object, name) => {
+                self.u8(11);
+                self.expression(object);
+                self.string(name);
+            }
+            Expression::Set(object, name, value) => {
+                self.u8(12);
+                self.expression(object);
+                self.string(name);
+                self.expression(value);
+            }
+            Expression::Variable(v) => {
+                self.u8(8);
+                self.variable(v);
+            }
+            Expression::Assign(v, e) => {
+                self.u8(9);
+                self.variable(v);
+                self.expression(e);
+            }
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal) {
+        match literal {
+            Literal::String(s) => {
+                self.u8(0);
+                self.string(s);
+            }
+            Literal::Number(n) => {
+                self.u8(1);
+                self.bytes.extend_from_slice(&n.to_bits().to_le_bytes());
+            }
+            Literal::Boolean(b) => {
+                self.u8(2);
+                self.u8(*b as u8);
+            }
+            Literal::Nil => self.u8(3),
+        }
+    }
+
+    fn variable(&mut self, variable: &ResolvedVariable) {
+        self.string(&variable.name);
+        match variable.hops {
+            Some(hops) => {
+                self.u8(1);
+                self.usize(hops);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn variables(&mut self, variables: &[ResolvedVariable]) {
+        self.usize(variables.len());
+        for variable in variables {
+            self.variable(variable);
+        }
+    }
+}
+
+const BINARY_OPERATORS: [BinaryOperator; 15] = [
+    BinaryOperator::Add,
+    BinaryOperator::Subtract,
+    BinaryOperator::Divide,
+    BinaryOperator::Multiply,
+    BinaryOperator::Equals,
+    BinaryOperator::NotEquals,
+    BinaryOperator::Is,
+    BinaryOperator::Less,
+    BinaryOperator::LessEquals,
+    BinaryOperator::Greater,
+    BinaryOperator::GreaterEquals,
+    BinaryOperator::And,
+    BinaryOperator::Or,
+    BinaryOperator::FloorDivide,
+    BinaryOperator::Power,
+];
+
+/// The index of the operator in BINARY_OPERATORS
+fn binary_operator_tag(operator: &BinaryOperator) -> u8 {
+    match operator {
+        BinaryOperator::Add => 0,
+        BinaryOperator::Subtract => 1,
+        BinaryOperator::Divide => 2,
+        BinaryOperator::Multiply => 3,
+        BinaryOperator::Equals => 4,
+        BinaryOperator::NotEquals => 5,
+        BinaryOperator::Is => 6,
+        BinaryOperator::Less => 7,
+        BinaryOperator::LessEquals => 8,
+        BinaryOperator::Greater => 9,
+        BinaryOperator::GreaterEquals => 10,
+        BinaryOperator::And => 11,
+        BinaryOperator::Or => 12,
+        BinaryOperator::FloorDivide => 13,
+        BinaryOperator::Power => 14,
+    }
+}
+
+/// Every read returns none if the bytes run out or hold something that can't be there
+struct Reader<'a> {
+    bytes: &'a [u8],
+    /// String literals are interned again, the same way the scanner does it
+    strings: HashSet<Rc<str>>,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        self.u64()?.try_into().ok()
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn location(&mut self) -> Option<(usize, usize)> {
+        Some((self.usize()?, self.usize()?))
+    }
+
+    fn located_string(&mut self) -> Option<Located<String>> {
+        Some(Located {
+            location: self.location()?,
+            value: self.string()?,
+        })
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.usize()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn strings(&mut self) -> Option<Vec<String>> {
+        // Every element takes up at least one byte, which keeps a corrupt length from allocating
+        // a huge vector up front
+        let len = self.usize()?;
+        let mut values = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            values.push(self.string()?);
+        }
+        Some(values)
+    }
+
+    fn statements(&mut self) -> Option<Vec<ResolvedStatement>> {
+        let len = self.usize()?;
+        let mut statements = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            statements.push(self.statement()?);
+        }
+        Some(statements)
+    }
+
+    fn statement(&mut self) -> Option<ResolvedStatement> {
+        Some(match self.u8()? {
+            0 => GenericStatement::Expression(self.expression()?),
+            1 => GenericStatement::Function(
+                self.located_string()?,
+                self.strings()?,
+                Rc::new(self.statements()?),
+                self.bool()?,
+                self.variables()?,
+            ),
+            2 => GenericStatement::Print(self.expression()?),
+            3 => GenericStatement::Assert(self.expression()?, self.optional_expression()?),
+            4 => GenericStatement::Return(
+                Located {
+                    location: self.location()?,
+                    value: self.optional_expression()?,
+                },
+                self.bool()?,
+            ),
+            5 => GenericStatement::Throw(self.expression()?),
+            6 => GenericStatement::Var(self.located_string()?, self.optional_expression()?),
+            7 => GenericStatement::While(
+                self.expression()?,
+                Box::new(self.statement()?),
+                self.optional_expression()?,
+            ),
+            8 => GenericStatement::DoWhile(Box::new(self.statement()?), self.expression()?),
+            9 => GenericStatement::Block(self.statements()?),
+            10 => {
+                GenericStatement::TryCatch(self.statements()?, self.string()?, self.statements()?)
+            }
+            11 => GenericStatement::If(
+                self.expression()?,
+                Box::new(self.statement()?),
+                match self.bool()? {
+                    true => Some(Box::new(self.statement()?)),
+                    false => None,
+                },
+            ),
+            12 => GenericStatement::VarTuple(self.strings()?, self.expression()?),
+            13 => GenericStatement::LabeledBlock(self.string()?, self.statements()?),
+            14 => GenericStatement::Break(Located {
+                location: self.location()?,
+                value: match self.bool()? {
+                    true => Some(self.string()?),
+                    false => None,
+                },
+            }),
+            15 => GenericStatement::Class(
+                self.located_string()?,
+                self.optional_expression()?,
+                self.statements()?,
+                self.variables()?,
+            ),
+            16 => GenericStatement::Continue(self.location()?),
+            17 => GenericStatement::Yield(self.expression()?),
+            18 => GenericStatement::Foreach(
+                self.string()?,
+                self.expression()?,
+                Rc::new(self.statement()?),
+            ),
+            _ => return None,
+        })
+    }
+
+    fn optional_expression(&mut self) -> Option<Option<ResolvedCodeExpression>> {
+        match self.bool()? {
+            true => Some(Some(self.expression()?)),
+            false => Some(None),
+        }
+    }
+
+    fn expressions(&mut self) -> Option<Vec<ResolvedCodeExpression>> {
+        let len = self.usize()?;
+        let mut expressions = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            expressions.push(self.expression()?);
+        }
+        Some(expressions)
+    }
+
+    fn expression(&mut self) -> Option<ResolvedCodeExpression> {
+        let location = self.location()?;
+        let value = match self.u8()? {
+            0 => Expression::Binary(
+                Box::new(self.expression()?),
+                BINARY_OPERATORS.get(self.u8()? as usize)?.clone(),
+                Box::new(self.expression()?),
+            ),
+            1 => Expression::Call(Box::new(self.expression()?), self.expressions()?),
+            2 => Expression::Grouping(Box::new(self.expression()?)),
+            3 => Expression::Index(Box::new(self.expression()?), Box::new(self.expression()?)),
+            4 => Expression::List(self.expressions()?),
+            5 => Expression::Literal(self.literal()?),
+            6 => Expression::Sequence(self.expressions()?),
+            7 => Expression::Unary(
+                match self.u8()? {
+                    0 => UnaryOperator::Minus,
+                    1 => UnaryOperator::Not,
+                    _ => return None,
+                },
+                Box::new(self.expression()?),
+            ),
+            8 => Expression::Variable(self.variable()?),
+            9 => Expression::Assign(self.variable()?, Box::new(self.expression()?)),
+            10 => {
+                let len = self.usize()?;
+                let mut entries = Vec::with_capacity(len.min(self.bytes.len()));
+                for _ in 0..len {
+                    entries.push((self.expression()?, self.expression()?));
+                }
+                Expression::Map(entries)
+            }
+            11 => Expression::Get(Box::new(self.expression()?), self.string()?),
+            12 => Expression::Set(
+                Box::new(self.expression()?),
+                self.string()?,
+                Box::new(self.expression()?),
+            ),
+            13 => Expression::Tuple(self.expressions()?),
+            14 => Expression::OptionalGet(Box::new(self.expression()?), self.string()?),
+            15 => Expression::This(self.variable()?),
+            16 => Expression::Super(self.variable()?, self.variable()?, self.string()?),
+            17 => Expression::Lambda(
+                self.strings()?,
+                Rc::new(self.statements()?),
+                self.variables()?,
+            ),
+            _ => return None,
+        };
+        Some(Located { location, value })
+    }
+
+    fn literal(&mut self) -> Option<Literal> {
+        Some(match self.u8()? {
+            0 => {
+                let string = self.string()?;
+                let interned = match self.strings.get(string.as_str()) {
+                    Some(interned) => interned.clone(),
+                    None => {
+                        let interned: Rc<str> = string.into();
+                        self.strings.insert(interned.clone());
+                        interned
+                    }
+                };
+                Literal::String(interned)
+            }
+            1 => Literal::Number(f64::from_bits(self.u64()?)),
+            2 => Literal::Boolean(self.bool()?),
+            3 => Literal::Nil,
+            _ => return None,
+        })
+    }
+
+    fn variable(&mut self) -> Option<ResolvedVariable> {
+        Some(ResolvedVariable {
+            name: self.string()?,
+            hops: match self.bool()? {
+                true => Some(self.usize()?),
+                false => None,
+            },
+        })
+    }
+
+    fn variables(&mut self) -> Option<Vec<ResolvedVariable>> {
+        let len = self.usize()?;
+        let mut variables = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            variables.push(self.variable()?);
+        }
+        Some(variables)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    /// Uses every kind of statement and expression
+    const PROGRAM: &str = r#"
+var list = [1, "two", nil, true, false];
+var map = {"a": 1, "b": [3]};
+var (first, second) = (1, 2);
+fun pair(a, b) { return a, b; }
+fun* numbers(n) { var i = 0; while (i < n) { yield i; i = i + 1; } }
+class Base {
+  init(x) { this.x = x; }
+  get() { return this.x; }
+}
+class Derived < Base {
+  get() { return super.get() * 2 ** 3 div 1; }
+}
+fun outer() {
+  var count = 0;
+  fun inc() { count = count + 1; return inc(); }
+  var lambda = fun (y) { return count + y; };
+  return lambda;
+}
+{
+  var a = -list[0];
+  a = !list[3];
+  var b = a == 1 or a != 2 and a is nil;
+  a = 0;
+  print a <= 1 and a >= 2 or a < 3 or a > 4;
+  print Derived(1)?.x;
+  Derived(2).x = @"raw\n";
+}
+outer: {
+  for (var i = 0; i < 3; i = i + 1) {
+    if (i == 1) continue; else if (i == 2) break outer;
+  }
+}
+do print 1 - 1 / 1; while (false);
+foreach (n in numbers(3)) print n;
+try { throw "x"; } catch (e) { assert e == "x", "message"; assert true; }
+(list, map);
+"#;
+
+    fn resolve(source: &str, comma_operator: bool) -> Vec<ResolvedStatement> {
+        let tokens = Scanner::new(source.to_string()).tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        parser.comma_operator = comma_operator;
+        Resolver::new().resolve(parser.parse().unwrap()).unwrap()
+    }
+
+    /// Statements don't implement PartialEq, but their Debug output shows every field
+    fn assert_round_trips(source: &str, program: &[ResolvedStatement]) {
+        let loaded = from_bytes(source, &to_bytes(source, program)).expect("cache loads");
+        assert_eq!(format!("{loaded:#?}"), format!("{program:#?}"));
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_round_trips(PROGRAM, &resolve(PROGRAM, false));
+        let sequence = "var a; a = 1, 2; print (a, 3);";
+        assert_round_trips(sequence, &resolve(sequence, true));
+    }
+
+    #[test]
+    fn different_source_is_rejected() {
+        let bytes = to_bytes(PROGRAM, &resolve(PROGRAM, false));
+        assert!(from_bytes(PROGRAM, &bytes).is_some());
+        assert!(from_bytes(&format!("{PROGRAM} "), &bytes).is_none());
+    }
+
+    #[test]
+    fn other_versions_and_truncated_caches_are_rejected() {
+        let mut bytes = to_bytes(PROGRAM, &resolve(PROGRAM, false));
+        assert!(from_bytes(PROGRAM, &bytes[..bytes.len() - 1]).is_none());
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert!(from_bytes(PROGRAM, &bytes).is_none());
+    }
+}
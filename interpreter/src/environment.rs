@@ -1,11 +1,39 @@
 use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 
-use crate::{ast::ResolvedVariable, error::RuntimeErrorKind, value::Value};
+use crate::{
+    ast::ResolvedVariable,
+    error::RuntimeErrorKind,
+    value::{Map, Value},
+};
 
 #[derive(Debug)]
 pub struct Environment {
     enclosing: Option<Rc<RefCell<Environment>>>,
-    values: BTreeMap<String, Value>,
+    values: BTreeMap<String, Slot>,
+}
+
+/// Where a variable's value is kept
+#[derive(Debug)]
+enum Slot {
+    Value(Value),
+    /// A variable that a closure has captured, shared with that closure
+    Captured(Rc<RefCell<Value>>),
+}
+
+impl Slot {
+    fn get(&self) -> Value {
+        match self {
+            Slot::Value(value) => value.clone(),
+            Slot::Captured(cell) => cell.borrow().clone(),
+        }
+    }
+
+    fn set(&mut self, value: Value) {
+        match self {
+            Slot::Value(slot) => *slot = value,
+            Slot::Captured(cell) => *cell.borrow_mut() = value,
+        }
+    }
 }
 
 impl Environment {
@@ -23,68 +51,303 @@ impl Environment {
         }))
     }
 
-    pub fn pop(&self) -> Option<Rc<RefCell<Environment>>> {
-        self.enclosing.clone()
+    pub fn names(&self) -> Vec<String> {
+        self.values.keys().cloned().collect()
+    }
+
+    /// A copy of the variables in this scope only
+    pub fn snapshot(&self) -> Map {
+        self.values
+            .iter()
+            .map(|(name, slot)| (name.as_str().into(), slot.get()))
+            .collect()
+    }
+
+    /// Puts back the variables of an earlier snapshot, dropping any defined since
+    pub fn restore(&mut self, snapshot: Map) {
+        self.values = snapshot
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), Slot::Value(value)))
+            .collect();
+    }
+
+    /// Every variable in this scope and the ones enclosing it, innermost scope first
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        let mut visited = vec![self as *const Environment];
+        self.dump_values(&mut out, 0);
+
+        let mut enclosing = self.enclosing.clone();
+        let mut depth = 1;
+        while let Some(env) = enclosing {
+            // Scopes are created inside existing ones so there shouldn't be a cycle, but a
+            // debugging aid shouldn't hang if there is one
+            if visited.contains(&(env.as_ptr() as *const Environment)) {
+                out += "(cycle)\n";
+                break;
+            }
+            visited.push(env.as_ptr());
+            env.borrow().dump_values(&mut out, depth);
+            enclosing = env.borrow().enclosing.clone();
+            depth += 1;
+        }
+        out
+    }
+
+    fn dump_values(&self, out: &mut String, depth: usize) {
+        let kind = if self.enclosing.is_none() {
+            " (global)"
+        } else {
+            ""
+        };
+        *out += &format!("scope {depth}{kind}:\n");
+        for (name, slot) in &self.values {
+            *out += &format!("  {name} = {}\n", slot.get());
+        }
     }
 
     pub fn define(&mut self, name: String, value: Value) {
-        self.values.insert(name, value);
+        self.values.insert(name, Slot::Value(value));
+    }
+
+    /// Defines a variable in the closure of a function or class, sharing the cell of the variable
+    /// it captures
+    pub fn define_captured(&mut self, name: String, cell: Rc<RefCell<Value>>) {
+        self.values.insert(name, Slot::Captured(cell));
     }
 
-    pub fn get(&self, variable: ResolvedVariable) -> Result<Value, RuntimeErrorKind> {
-        match variable.hops {
-            Some(0) => self.values.get(&variable.name).cloned(),
+    /// Like define, but redefining a variable in the same scope is an error
+    pub fn define_checked(&mut self, name: String, value: Value) -> Result<(), RuntimeErrorKind> {
+        if self.values.contains_key(&name) {
+            return Err(RuntimeErrorKind::Redefinition(name));
+        }
+        self.values.insert(name, Slot::Value(value));
+        Ok(())
+    }
+
+    pub fn get(&self, variable: &ResolvedVariable) -> Result<Value, RuntimeErrorKind> {
+        self.get_at(&variable.name, variable.hops)
+    }
+
+    /// Looks up a name without having to build a new ResolvedVariable for every hop
+    fn get_at(&self, name: &str, hops: Option<usize>) -> Result<Value, RuntimeErrorKind> {
+        match hops {
+            Some(0) => match self.values.get(name) {
+                Some(slot) => Ok(slot.get()),
+                None => Err(RuntimeErrorKind::Misresolved(name.to_string())),
+            },
             Some(h) => self
-                .enclosing
-                .as_ref()
-                .expect("correctly calculated hops")
+                .resolved_enclosing(name)?
                 .borrow()
-                .get(ResolvedVariable {
-                    hops: Some(h - 1),
-                    name: variable.name.clone(),
-                })
-                .ok(),
+                .get_at(name, Some(h - 1)),
             None => {
                 if let Some(e) = &self.enclosing {
-                    e.borrow().get(variable.clone()).ok()
+                    e.borrow().get_at(name, None)
                 } else {
-                    self.values.get(&variable.name).cloned()
+                    self.values
+                        .get(name)
+                        .map(Slot::get)
+                        .ok_or_else(|| RuntimeErrorKind::UndefinedVariable(name.to_string()))
                 }
             }
         }
-        .ok_or(RuntimeErrorKind::UndefinedVariable(variable.name))
     }
 
     pub fn assign(
         &mut self,
-        variable: ResolvedVariable,
+        variable: &ResolvedVariable,
         value: Value,
     ) -> Result<(), RuntimeErrorKind> {
-        match variable.hops {
-            Some(0) => {
-                self.values.insert(variable.name, value);
-            }
+        self.assign_at(&variable.name, variable.hops, value)
+    }
+
+    fn assign_at(
+        &mut self,
+        name: &str,
+        hops: Option<usize>,
+        value: Value,
+    ) -> Result<(), RuntimeErrorKind> {
+        match hops {
+            Some(0) => match self.values.get_mut(name) {
+                Some(slot) => slot.set(value),
+                None => return Err(RuntimeErrorKind::Misresolved(name.to_string())),
+            },
             Some(h) => {
-                self.enclosing
-                    .as_ref()
-                    .expect("correctly calculated hops")
+                self.resolved_enclosing(name)?
                     .borrow_mut()
-                    .assign(
-                        ResolvedVariable {
-                            hops: Some(h - 1),
-                            name: variable.name,
-                        },
-                        value,
-                    )?;
+                    .assign_at(name, Some(h - 1), value)?;
             }
             None => {
                 if let Some(e) = &self.enclosing {
-                    e.borrow_mut().assign(variable, value)?;
+                    e.borrow_mut().assign_at(name, None, value)?;
+                } else if let Some(slot) = self.values.get_mut(name) {
+                    slot.set(value);
                 } else {
-                    self.values.insert(variable.name, value);
+                    self.values.insert(name.to_string(), Slot::Value(value));
                 }
             }
         }
         Ok(())
     }
+
+    /// The cell of a local variable, which it's moved into the first time it's captured
+    pub fn capture(
+        &mut self,
+        variable: &ResolvedVariable,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeErrorKind> {
+        let Some(hops) = variable.hops else {
+            // Globals are looked up by name, so they're never captured
+            return Err(RuntimeErrorKind::Misresolved(variable.name.clone()));
+        };
+        self.capture_at(&variable.name, hops)
+    }
+
+    fn capture_at(
+        &mut self,
+        name: &str,
+        hops: usize,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeErrorKind> {
+        if hops > 0 {
+            return self
+                .resolved_enclosing(name)?
+                .borrow_mut()
+                .capture_at(name, hops - 1);
+        }
+        let slot = self
+            .values
+            .get_mut(name)
+            .ok_or_else(|| RuntimeErrorKind::Misresolved(name.to_string()))?;
+        Ok(match slot {
+            Slot::Captured(cell) => cell.clone(),
+            Slot::Value(value) => {
+                let cell = Rc::new(RefCell::new(std::mem::replace(value, Value::Nil)));
+                *slot = Slot::Captured(cell.clone());
+                cell
+            }
+        })
+    }
+
+    /// The enclosing environment of a variable that the resolver says is further up.
+    /// A variable that isn't where the resolver said it would be is a resolver bug, which is
+    /// reported as such rather than panicking.
+    fn resolved_enclosing(
+        &self,
+        name: &str,
+    ) -> Result<&Rc<RefCell<Environment>>, RuntimeErrorKind> {
+        self.enclosing
+            .as_ref()
+            .ok_or_else(|| RuntimeErrorKind::Misresolved(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(name: &str, hops: usize) -> ResolvedVariable {
+        ResolvedVariable {
+            name: name.to_string(),
+            hops: Some(hops),
+        }
+    }
+
+    /// A global scope, with a scope defining `x` inside it and an empty scope inside that
+    fn nested() -> Rc<RefCell<Environment>> {
+        let globals = Environment::new();
+        let outer = Environment::new_inside(globals);
+        outer
+            .borrow_mut()
+            .define("x".to_string(), Value::Number(1.0));
+        Environment::new_inside(outer)
+    }
+
+    #[test]
+    fn correctly_resolved_nested_variable() {
+        let inner = nested();
+        assert_eq!(
+            inner.borrow().get(&variable("x", 1)).unwrap(),
+            Value::Number(1.0)
+        );
+        inner
+            .borrow_mut()
+            .assign(&variable("x", 1), Value::Number(2.0))
+            .unwrap();
+        assert_eq!(
+            inner.borrow().get(&variable("x", 1)).unwrap(),
+            Value::Number(2.0)
+        );
+    }
+
+    #[test]
+    fn misresolved_variable() {
+        let inner = nested();
+        for hops in [0, 2, 3] {
+            let x = variable("x", hops);
+            assert!(matches!(
+                inner.borrow().get(&x),
+                Err(RuntimeErrorKind::Misresolved(name)) if name == "x"
+            ));
+            assert!(matches!(
+                inner.borrow_mut().assign(&x, Value::Nil),
+                Err(RuntimeErrorKind::Misresolved(name)) if name == "x"
+            ));
+        }
+    }
+
+    #[test]
+    fn dump_shows_every_scope() {
+        let globals = Environment::new();
+        globals
+            .borrow_mut()
+            .define("g".to_string(), Value::Number(1.0));
+        let local = Environment::new_inside(globals);
+        local
+            .borrow_mut()
+            .define("l".to_string(), Value::String("two".into()));
+        assert_eq!(
+            local.borrow().dump(),
+            "scope 0:\n  l = two\nscope 1 (global):\n  g = 1\n"
+        );
+    }
+
+    #[test]
+    fn captured_variable_is_shared() {
+        let inner = nested();
+        let cell = inner.borrow_mut().capture(&variable("x", 1)).unwrap();
+        let closure = Environment::new_inside(Environment::new());
+        closure.borrow_mut().define_captured("x".to_string(), cell);
+        closure
+            .borrow_mut()
+            .assign(&variable("x", 0), Value::Number(2.0))
+            .unwrap();
+        assert_eq!(
+            inner.borrow().get(&variable("x", 1)).unwrap(),
+            Value::Number(2.0)
+        );
+        // Capturing again hands out the same cell
+        let again = inner.borrow_mut().capture(&variable("x", 1)).unwrap();
+        *again.borrow_mut() = Value::Number(3.0);
+        assert_eq!(
+            closure.borrow().get(&variable("x", 0)).unwrap(),
+            Value::Number(3.0)
+        );
+    }
+
+    #[test]
+    fn define_checked_rejects_an_existing_name() {
+        let scope = Environment::new();
+        let mut scope = scope.borrow_mut();
+        scope
+            .define_checked("x".to_string(), Value::Number(1.0))
+            .unwrap();
+        assert!(matches!(
+            scope.define_checked("x".to_string(), Value::Nil),
+            Err(RuntimeErrorKind::Redefinition(name)) if name == "x"
+        ));
+        // The first definition is kept
+        assert_eq!(scope.get(&variable("x", 0)).unwrap(), Value::Number(1.0));
+        // Unchecked define replaces it
+        scope.define("x".to_string(), Value::Nil);
+        assert_eq!(scope.get(&variable("x", 0)).unwrap(), Value::Nil);
+    }
 }
@@ -1,8 +1,8 @@
-use std::{error::Error, fmt::Display, io};
+use std::{error::Error, fmt::Display, io, rc::Rc};
 
 use crate::{
-    token::Token,
-    value::{Type, Value},
+    token::{Keyword, Token},
+    value::{LoxCallable, Type, Value},
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -29,6 +29,17 @@ impl LoxError {
             | LoxError::Runtime(Located { location, .. }) => Some(*location),
         }
     }
+
+    /// The error without its location
+    pub fn message(&self) -> String {
+        match self {
+            LoxError::Io(e) => e.to_string(),
+            LoxError::Tokenize(Located { value, .. }) => value.to_string(),
+            LoxError::Parse(Located { value, .. }) => value.to_string(),
+            LoxError::Resolve(Located { value, .. }) => value.to_string(),
+            LoxError::Runtime(Located { value, .. }) => value.to_string(),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -50,19 +61,39 @@ pub enum TokenizeErrorKind {
     InvalidStartOfToken(char),
     #[error("unterminated string")]
     UnterminatedString,
+    /// Located at the opening `/*`
+    #[error("unterminated block comment")]
+    UnterminatedComment,
+    #[error("unknown escape sequence `\\{}`", .0.escape_default())]
+    InvalidEscape(char),
+    #[error("`\\x` must be followed by two hex digits, at most 7F")]
+    InvalidHexEscape,
+    #[error("`\\u{{...}}` must contain the hex code point of a unicode scalar value")]
+    InvalidUnicodeEscape,
+    #[error("indentation mixes tabs and spaces")]
+    MixedIndentation,
 }
 
 pub type ParseError = Located<ParseErrorKind>;
 #[derive(Debug, thiserror::Error)]
 pub enum ParseErrorKind {
-    #[error("unexpected token {0:?}, expected {1:?}")]
+    #[error("unexpected token `{0}`, expected `{1}`")]
     UnexpectedToken(Token, Token),
     #[error("invalid expression")]
     InvalidExpression,
+    #[error("expected an expression, found `{0}` (statements can't be used as expressions)")]
+    ExpectedExpressionFoundStatement(Token),
     #[error("invalid assignment target")]
     InvalidLvalue,
+    #[error("the right side of `|>` must be something that can be called")]
+    PipeIntoNonCallable,
     #[error("too many arguments (maximum is {0})")]
     TooManyArguments(usize),
+    #[error("the body of `{0}` isn't in braces")]
+    UnbracedBody(Keyword),
+    /// A declaration would only live for the single statement, so it needs braces around it
+    #[error("a declaration can't be the body of `{0}` without braces")]
+    DeclarationNotAllowed(Keyword),
 }
 
 pub type ResolveError = Located<ResolveErrorKind>;
@@ -74,23 +105,136 @@ pub enum ResolveErrorKind {
     VariableRedeclaration,
     #[error("can't return from top-level code")]
     TopLevelReturn,
+    #[error("undefined variable `{0}`")]
+    UndefinedGlobal(String),
+    #[error("assignment used as a condition (wrap it in parentheses if this is intended)")]
+    AssignmentInCondition,
+    #[error("variable `{0}` is never used (prefix it with `_` if this is intended)")]
+    UnusedVariable(String),
+    #[error("can't use `{0}` outside of a loop")]
+    JumpOutsideLoop(Keyword),
+    #[error("no enclosing block is labeled `{0}`")]
+    UndefinedLabel(String),
+    #[error("can't use `this` outside of a class")]
+    ThisOutsideClass,
+    #[error("can't return a value from an initializer")]
+    ReturnValueFromInitializer,
+    #[error("can't use `super` outside of a class")]
+    SuperOutsideClass,
+    #[error("can't use `super` in a class with no superclass")]
+    SuperInClassWithNoSuperclass,
+    #[error("class `{0}` can't inherit from itself")]
+    CyclicInheritance(String),
+    #[error("can't use `yield` outside of a generator")]
+    YieldOutsideGenerator,
+    #[error("can't return a value from a generator")]
+    ReturnValueFromGenerator,
 }
 
 pub type RuntimeError = Located<RuntimeErrorKind>;
 #[derive(Debug, thiserror::Error)]
 pub enum RuntimeErrorKind {
-    #[error("expected type {0:?}, got {1:?}")]
-    TypeError(Type, Type),
-    #[error("expected types {0:?}, got {1:?}")]
-    TypeErrorMultiple(Vec<Type>, Type),
+    /// The expected type and the value that was there instead
+    #[error("expected type {0:?}, got {}", describe(.1))]
+    TypeError(Type, Value),
+    #[error("expected types {0:?}, got {}", describe(.1))]
+    TypeErrorMultiple(Vec<Type>, Value),
     #[error("undefined variable `{0}`")]
     UndefinedVariable(String),
-    #[error("wrong number of arguments: got {0}, expected {1}")]
-    WrongArgsNum(usize, usize),
+    #[error("already a variable named `{0}` in this scope")]
+    Redefinition(String),
+    #[error("internal error: variable `{0}` isn't in the scope it was resolved to")]
+    Misresolved(String),
+    #[error("wrong number of arguments to `{0}`: got {1}, expected {2}")]
+    WrongArgsNum(String, usize, usize),
+    #[error("expected a whole number, got {0}")]
+    NotAnInteger(f64),
+    #[error("expected a non-negative number, got {0}")]
+    Negative(f64),
+    #[error("{0} doesn't fit in a byte (0 to 255)")]
+    ByteOutOfRange(f64),
+    /// The path and what went wrong with it
+    #[error("can't access `{0}`: {1}")]
+    Io(String, std::io::Error),
+    #[error("assertion failed{}", .0.as_ref().map(|m| format!(": {m}")).unwrap_or_default())]
+    AssertionFailed(Option<String>),
+    /// Raised by a throw statement
+    #[error("{0}")]
+    UserThrown(Value),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("comparison with NaN")]
+    NanComparison,
+    #[error("index {0} is out of bounds for length {1}")]
+    IndexOutOfBounds(f64, usize),
+    #[error("key `{0}` is not in the map")]
+    MissingKey(Rc<str>),
+    #[error("instance has no property `{0}`")]
+    UndefinedProperty(String),
+    #[error("{0:?} has no method `{1}`")]
+    UnknownMethod(Type, String),
+    #[error("range is too long (maximum length is {0})")]
+    RangeTooLong(usize),
+    #[error("string is too long (maximum length is {0} bytes)")]
+    StringTooLong(usize),
+    #[error("memory limit of {0} bytes exceeded")]
+    MemoryLimitExceeded(usize),
+    /// How many variables there are and how many elements the tuple has
+    #[error("can't unpack a tuple of {1} elements into {0} variables")]
+    TupleLengthMismatch(usize, usize),
 
     /// not actually an error
     #[error("RETURNING, YOU SHOULD NEVER SEE THIS")]
     Returning(Value),
+    /// not actually an error, unwinds up to the block with this label, or the innermost loop
+    #[error("BREAKING, YOU SHOULD NEVER SEE THIS")]
+    Breaking(Option<String>),
+    /// not actually an error, unwinds up to the innermost loop which then goes on to the next
+    /// iteration
+    #[error("CONTINUING, YOU SHOULD NEVER SEE THIS")]
+    Continuing,
+    /// not actually an error either, the function being returned from should call this instead
+    #[error("TAIL CALLING, YOU SHOULD NEVER SEE THIS")]
+    TailCall(LoxCallable, Vec<Value>),
+    /// not actually an error, carries whatever stopped a foreach body out through the generator
+    /// it runs for, past that generator's own loops and try blocks. None when the body broke out
+    #[error("LEAVING GENERATOR, YOU SHOULD NEVER SEE THIS")]
+    LeavingGenerator(Option<Box<RuntimeError>>),
+}
+
+/// How many characters of a value an error shows
+const MAX_PREVIEW_LENGTH: usize = 20;
+
+/// The type of a value along with a short preview of it, like `String ("hello")`
+fn describe(value: &Value) -> String {
+    // The type of nil already says everything there is to say
+    if let Value::Nil = value {
+        return format!("{:?}", value.value_type());
+    }
+    let full = value.to_display_string();
+    let mut preview: String = full.chars().take(MAX_PREVIEW_LENGTH).collect();
+    if preview.len() < full.len() {
+        preview += "...";
+    }
+    match value {
+        Value::String(_) => format!("{:?} (\"{preview}\")", value.value_type()),
+        Value::Char(_) => format!("{:?} ('{preview}')", value.value_type()),
+        _ => format!("{:?} ({preview})", value.value_type()),
+    }
+}
+
+impl RuntimeErrorKind {
+    /// Sentinels that use the error path to unwind, rather than actual errors
+    pub fn is_control_flow(&self) -> bool {
+        matches!(
+            self,
+            RuntimeErrorKind::Returning(_)
+                | RuntimeErrorKind::TailCall(..)
+                | RuntimeErrorKind::Breaking(_)
+                | RuntimeErrorKind::Continuing
+                | RuntimeErrorKind::LeavingGenerator(_)
+        )
+    }
 }
 
 impl<E: Error> Display for Located<E> {
@@ -1,24 +1,84 @@
 use std::{
     cell::RefCell,
+    cmp::Ordering,
+    collections::BTreeMap,
+    io::Write,
     rc::Rc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
     ast::{
         BinaryOperator, Expression, ResolvedCodeExpression, ResolvedStatement, ResolvedVariable,
-        UnaryOperator,
+        UnaryOperator, ANONYMOUS,
     },
     environment::Environment,
-    error::{RuntimeError, RuntimeErrorKind, WithLocation},
-    token::Literal,
-    value::{LoxCallable, Type, Value},
+    error::{Located, RuntimeError, RuntimeErrorKind, WithLocation},
+    value::{Generator, LoxCallable, LoxClass, Type, Value},
 };
 
 pub type RuntimeResult<T> = Result<T, RuntimeError>;
 
+/// The longest list range() will build
+const MAX_RANGE_LENGTH: usize = 1 << 24;
+/// The longest string (in bytes) that repeating one with `*` will build
+const MAX_STRING_LENGTH: usize = 1 << 24;
+/// Natives whose result depends only on their arguments, so calls to them can be done ahead of time
+const PURE_NATIVES: &[&str] = &[
+    "bool",
+    "debug",
+    "gcd",
+    "group_digits",
+    "is_callable",
+    "is_native",
+    "lcm",
+    "pow",
+    "pretty",
+];
+
+/// Where the values of a running generator go: the foreach that runs it
+struct YieldTarget {
+    /// The environment of the foreach statement itself
+    environment: Rc<RefCell<Environment>>,
+    variable: String,
+    body: Rc<ResolvedStatement>,
+}
+
+type TraceHook = Box<dyn FnMut((usize, usize))>;
+type PrintHook = Box<dyn FnMut(&Value)>;
+
 pub struct Interpreter {
     pub environment: Rc<RefCell<Environment>>,
+    globals: Rc<RefCell<Environment>>,
+    trace: Option<TraceHook>,
+    print: Option<PrintHook>,
+    /// Where printed values are written when there's no print hook
+    output: Box<dyn Write>,
+
+    /// Raise an error when dividing by zero instead of producing Inf or NaN
+    pub check_division_by_zero: bool,
+    /// Raise an error when `<`, `<=`, `>` or `>=` has a NaN operand instead of producing false
+    pub check_nan_comparison: bool,
+    /// Don't run assert statements at all, not even their conditions
+    pub skip_assertions: bool,
+    /// Roughly how many bytes the program may allocate for strings, lists and maps over the whole
+    /// run. Freed values aren't subtracted, so this limits total work as much as memory.
+    pub memory_limit: Option<usize>,
+    /// When a top level statement fails, put the globals back the way they were before it. Values
+    /// changed in place, like a list that was pushed to, stay changed.
+    pub rollback_failed_statements: bool,
+    /// Bytes counted against memory_limit so far
+    allocated: usize,
+    /// State of the xorshift64* generator behind random(). Never zero.
+    random_state: u64,
+    /// One for every foreach that is running a generator, innermost last
+    yield_targets: Vec<YieldTarget>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
@@ -28,7 +88,7 @@ impl Interpreter {
             "debug".into(),
             Value::Callable(LoxCallable::NativeFunction(
                 "debug".into(),
-                1,
+                Some(1),
                 Rc::new(Box::new(debug)),
             )),
         );
@@ -36,217 +96,1094 @@ impl Interpreter {
             "clock".into(),
             Value::Callable(LoxCallable::NativeFunction(
                 "clock".into(),
-                0,
+                Some(0),
                 Rc::new(Box::new(clock)),
             )),
         );
-        Interpreter {
-            environment: globals,
+        globals.borrow_mut().define(
+            "bool".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "bool".into(),
+                Some(1),
+                Rc::new(Box::new(bool)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "is_callable".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "is_callable".into(),
+                Some(1),
+                Rc::new(Box::new(is_callable)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "is_native".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "is_native".into(),
+                Some(1),
+                Rc::new(Box::new(is_native)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "bytes".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "bytes".into(),
+                Some(1),
+                Rc::new(Box::new(bytes)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "range".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "range".into(),
+                Some(2),
+                Rc::new(Box::new(range)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "pretty".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "pretty".into(),
+                Some(1),
+                Rc::new(Box::new(pretty)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "keys".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "keys".into(),
+                Some(1),
+                Rc::new(Box::new(keys)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "values".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "values".into(),
+                Some(1),
+                Rc::new(Box::new(values)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "sort".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "sort".into(),
+                Some(1),
+                Rc::new(Box::new(sort)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "sort_by".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "sort_by".into(),
+                Some(2),
+                Rc::new(Box::new(sort_by)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "compose".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "compose".into(),
+                Some(2),
+                Rc::new(Box::new(compose)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "puts".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "puts".into(),
+                Some(1),
+                Rc::new(Box::new(puts)),
+            )),
+        );
+        // Only reachable when the scanner treats `print` as an identifier
+        globals.borrow_mut().define(
+            "print".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "print".into(),
+                None,
+                Rc::new(Box::new(print)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "group_digits".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "group_digits".into(),
+                Some(1),
+                Rc::new(Box::new(group_digits)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "dump_env".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "dump_env".into(),
+                Some(0),
+                Rc::new(Box::new(dump_env)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "globals".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "globals".into(),
+                Some(0),
+                Rc::new(Box::new(globals_map)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "pow".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "pow".into(),
+                Some(2),
+                Rc::new(Box::new(pow)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "gcd".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "gcd".into(),
+                Some(2),
+                Rc::new(Box::new(gcd)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "lcm".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "lcm".into(),
+                Some(2),
+                Rc::new(Box::new(lcm)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "random".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "random".into(),
+                Some(0),
+                Rc::new(Box::new(random)),
+            )),
+        );
+        globals.borrow_mut().define(
+            "seed".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "seed".into(),
+                Some(1),
+                Rc::new(Box::new(seed)),
+            )),
+        );
+        let mut interpreter = Interpreter {
+            environment: globals.clone(),
+            globals,
+            trace: None,
+            print: None,
+            output: Box::new(std::io::stdout()),
+            check_division_by_zero: false,
+            check_nan_comparison: false,
+            skip_assertions: false,
+            memory_limit: None,
+            rollback_failed_statements: false,
+            allocated: 0,
+            random_state: 1,
+            yield_targets: Vec::new(),
+        };
+        // Programs that don't call seed() get a different sequence every run
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        interpreter.seed_random(now.as_nanos() as u64);
+        interpreter
+    }
+
+    /// Restarts the sequence random() gives. The same seed always gives the same sequence.
+    pub fn seed_random(&mut self, seed: u64) {
+        // splitmix64, so that similar seeds still start far apart
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        self.random_state = z.max(1);
+    }
+
+    /// A number in [0, 1)
+    fn next_random(&mut self) -> f64 {
+        let mut x = self.random_state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.random_state = x;
+        // The top 53 bits are as many as an f64 can hold exactly
+        (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Natives with side effects outside of printing, only available when asked for
+    pub fn define_io_natives(&mut self) {
+        self.globals.borrow_mut().define(
+            "read_bytes".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "read_bytes".into(),
+                Some(1),
+                Rc::new(Box::new(read_bytes)),
+            )),
+        );
+        self.globals.borrow_mut().define(
+            "write_bytes".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "write_bytes".into(),
+                Some(2),
+                Rc::new(Box::new(write_bytes)),
+            )),
+        );
+        self.globals.borrow_mut().define(
+            "sleep".into(),
+            Value::Callable(LoxCallable::NativeFunction(
+                "sleep".into(),
+                Some(1),
+                Rc::new(Box::new(sleep)),
+            )),
+        );
+    }
+
+    pub fn global_names(&self) -> Vec<String> {
+        self.globals.borrow().names()
+    }
+
+    /// The pure natives that are still bound to their global name, since a script may have
+    /// replaced some of them by now
+    pub fn pure_natives(&self) -> BTreeMap<String, LoxCallable> {
+        let globals = self.globals.borrow();
+        PURE_NATIVES
+            .iter()
+            .filter_map(|&name| {
+                let variable = ResolvedVariable {
+                    name: name.to_string(),
+                    hops: None,
+                };
+                match globals.get(&variable) {
+                    Ok(Value::Callable(native @ LoxCallable::NativeFunction(..)))
+                        if native.name() == name =>
+                    {
+                        Some((variable.name, native))
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Called with the location of every statement right before it's executed
+    pub fn set_trace(&mut self, trace: TraceHook) {
+        self.trace = Some(trace);
+    }
+
+    /// Called with every value that's printed, by the print statement, `print()` or `puts()`,
+    /// instead of writing it to the output
+    pub fn set_print_hook(&mut self, print: PrintHook) {
+        self.print = Some(print);
+    }
+
+    /// Where printed values are written when there's no print hook, stdout by default
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Gives a printed value to the print hook, or writes it to the output followed by `end`
+    fn print_value(&mut self, value: &Value, end: &str) -> Result<(), RuntimeErrorKind> {
+        match &mut self.print {
+            Some(print) => {
+                print(value);
+                Ok(())
+            }
+            None => write!(self.output, "{}{end}", value.to_display_string())
+                // Without a newline a line buffered stdout wouldn't show it yet
+                .and_then(|_| self.output.flush())
+                .map_err(|e| RuntimeErrorKind::Io("output".to_string(), e)),
         }
     }
 
-    pub fn interpret(&mut self, program: Vec<ResolvedStatement>) -> RuntimeResult<()> {
-        for statement in program {
-            self.execute(statement)?;
+    /// Counts an allocation against the memory limit
+    fn allocate(&mut self, bytes: usize) -> Result<(), RuntimeErrorKind> {
+        self.allocated = self.allocated.saturating_add(bytes);
+        match self.memory_limit {
+            Some(limit) if self.allocated > limit => {
+                Err(RuntimeErrorKind::MemoryLimitExceeded(limit))
+            }
+            _ => Ok(()),
         }
-        Ok(())
     }
 
-    fn execute(&mut self, statement: ResolvedStatement) -> RuntimeResult<()> {
+    /// Results in the value of the last statement if it's an expression statement, or nil otherwise
+    pub fn interpret(&mut self, program: Vec<ResolvedStatement>) -> RuntimeResult<Value> {
+        let Some((last, rest)) = program.split_last() else {
+            return Ok(Value::Nil);
+        };
+        for statement in rest {
+            self.atomically(|interpreter| interpreter.execute(statement))?;
+        }
+        self.atomically(|interpreter| match last {
+            ResolvedStatement::Expression(expr) => {
+                interpreter.trace_statement(last);
+                interpreter.evaluate(expr)
+            }
+            statement => interpreter.execute(statement).map(|_| Value::Nil),
+        })
+    }
+
+    /// Runs a top level statement, see rollback_failed_statements
+    fn atomically<T>(
+        &mut self,
+        statement: impl FnOnce(&mut Self) -> RuntimeResult<T>,
+    ) -> RuntimeResult<T> {
+        if !self.rollback_failed_statements {
+            return statement(self);
+        }
+        let snapshot = self.globals.borrow().snapshot();
+        let result = statement(self);
+        if result.is_err() {
+            self.globals.borrow_mut().restore(snapshot);
+        }
+        result
+    }
+
+    fn trace_statement(&mut self, statement: &ResolvedStatement) {
+        if let Some(trace) = &mut self.trace {
+            let location = statement.location();
+            if location != (0, 0) {
+                trace(location);
+            }
+        }
+    }
+
+    fn execute(&mut self, statement: &ResolvedStatement) -> RuntimeResult<()> {
+        self.trace_statement(statement);
+
         match statement {
             ResolvedStatement::Expression(expr) => self.evaluate(expr).map(|_| ()),
             ResolvedStatement::Print(expr) => {
-                println!("{}", self.evaluate(expr)?);
-                Ok(())
+                let value = self.evaluate(expr)?;
+                self.print_value(&value, "\n").with_location(expr.location)
+            }
+            ResolvedStatement::Assert(condition, message) => {
+                self.execute_assert(condition, message)
             }
             ResolvedStatement::Var(name, value) => self.execute_statement_var(name, value),
+            ResolvedStatement::VarTuple(names, value) => self.execute_var_tuple(names, value),
             ResolvedStatement::Block(b) => self.execute_block_statement(b),
+            ResolvedStatement::LabeledBlock(label, b) => match self.execute_block_statement(b) {
+                Err(RuntimeError {
+                    value: RuntimeErrorKind::Breaking(Some(l)),
+                    ..
+                }) if l == *label => Ok(()),
+                res => res,
+            },
+            ResolvedStatement::Break(b) => Err(RuntimeError {
+                location: b.location,
+                value: RuntimeErrorKind::Breaking(b.value.clone()),
+            }),
+            ResolvedStatement::Continue(location) => Err(RuntimeError {
+                location: *location,
+                value: RuntimeErrorKind::Continuing,
+            }),
+            ResolvedStatement::TryCatch(body, name, handler) => {
+                self.execute_try(body, name, handler)
+            }
             ResolvedStatement::If(condition, then_branch, else_branch) => {
-                self.execute_if(condition, *then_branch, else_branch)
+                self.execute_if(condition, then_branch, else_branch.as_deref())
+            }
+            ResolvedStatement::While(condition, body, increment) => {
+                self.execute_while(condition, body, increment.as_ref())
+            }
+            ResolvedStatement::DoWhile(body, condition) => self.execute_do_while(body, condition),
+            ResolvedStatement::Foreach(variable, iterable, body) => {
+                self.execute_foreach(variable, iterable, body)
+            }
+            ResolvedStatement::Yield(expr) => self.execute_yield(expr),
+            ResolvedStatement::Function(name, params, body, generator, captures) => {
+                self.execute_fun(name, params, body, *generator, captures)
+            }
+            ResolvedStatement::Class(name, superclass, methods, captures) => {
+                self.execute_class(name, superclass.as_ref(), methods, captures)
+            }
+            ResolvedStatement::Return(expr, tail) => self.execute_return(&expr.value, *tail),
+            ResolvedStatement::Throw(expr) => {
+                let value = self.evaluate(expr)?;
+                Err(RuntimeError {
+                    location: expr.location,
+                    value: RuntimeErrorKind::UserThrown(value),
+                })
             }
-            ResolvedStatement::While(condition, body) => self.execute_while(condition, *body),
-            ResolvedStatement::Function(name, params, body) => self.execute_fun(name, params, body),
-            ResolvedStatement::Return(expr) => self.execute_return(expr),
         }
     }
 
     fn execute_statement_var(
         &mut self,
-        name: String,
-        value: Option<ResolvedCodeExpression>,
+        name: &Located<String>,
+        value: &Option<ResolvedCodeExpression>,
     ) -> Result<(), RuntimeError> {
         let value = if let Some(e) = value {
             self.evaluate(e)?
         } else {
             Value::Nil
         };
-        self.environment.borrow_mut().define(name, value);
+
+        self.define_variable(&name.value, value, name.location)
+    }
+
+    fn execute_var_tuple(
+        &mut self,
+        names: &[String],
+        value: &ResolvedCodeExpression,
+    ) -> RuntimeResult<()> {
+        let location = value.location;
+        let values = self.evaluate(value)?.into_tuple().with_location(location)?;
+        if values.len() != names.len() {
+            return Err(RuntimeError {
+                location,
+                value: RuntimeErrorKind::TupleLengthMismatch(names.len(), values.len()),
+            });
+        }
+        for (name, value) in names.iter().zip(values.iter()) {
+            self.define_variable(name, value.clone(), location)?;
+        }
+        Ok(())
+    }
+
+    fn define_variable(
+        &mut self,
+        name: &str,
+        value: Value,
+        location: (usize, usize),
+    ) -> RuntimeResult<()> {
+        // Globals can be redefined, which the REPL relies on. Anywhere else the resolver already
+        // forbids it, this is in case it's bypassed
+        let mut environment = self.environment.borrow_mut();
+        if Rc::ptr_eq(&self.environment, &self.globals) {
+            environment.define(name.to_string(), value);
+        } else {
+            environment
+                .define_checked(name.to_string(), value)
+                .with_location(location)?;
+        }
 
         Ok(())
     }
 
     pub fn execute_block_statement(
         &mut self,
-        statements: Vec<ResolvedStatement>,
+        statements: &[ResolvedStatement],
     ) -> RuntimeResult<()> {
-        self.environment = Environment::new_inside(self.environment.clone());
+        let inner = Environment::new_inside(self.environment.clone());
+        let outer = std::mem::replace(&mut self.environment, inner);
 
         let res = self.execute_block(statements);
 
-        self.environment = self.environment.clone().borrow().pop().unwrap();
+        // Restore the outer environment even when the block is unwinding because of an error,
+        // a `return` included, so the caller always continues in the scope it started in
+        self.environment = outer;
         res
     }
 
-    fn execute_block(&mut self, statements: Vec<ResolvedStatement>) -> RuntimeResult<()> {
+    fn execute_block(&mut self, statements: &[ResolvedStatement]) -> RuntimeResult<()> {
         for statement in statements {
             self.execute(statement)?;
         }
         Ok(())
     }
 
+    fn execute_try(
+        &mut self,
+        body: &[ResolvedStatement],
+        name: &str,
+        handler: &[ResolvedStatement],
+    ) -> RuntimeResult<()> {
+        let error = match self.execute_block_statement(body) {
+            Err(e) if !e.value.is_control_flow() => e,
+            res => return res,
+        };
+
+        // Thrown values are caught as they are, other errors as their message
+        let caught = match error.value {
+            RuntimeErrorKind::UserThrown(value) => value,
+            e => Value::String(e.to_string().into()),
+        };
+        let inner = Environment::new_inside(self.environment.clone());
+        inner.borrow_mut().define(name.to_string(), caught);
+        let outer = std::mem::replace(&mut self.environment, inner);
+        let res = self.execute_block(handler);
+        self.environment = outer;
+        res
+    }
+
     fn execute_if(
         &mut self,
-        condition: ResolvedCodeExpression,
-        then_branch: ResolvedStatement,
-        else_branch: Option<Box<ResolvedStatement>>,
+        condition: &ResolvedCodeExpression,
+        then_branch: &ResolvedStatement,
+        else_branch: Option<&ResolvedStatement>,
     ) -> RuntimeResult<()> {
         let location = condition.location;
         let condition = self.evaluate(condition)?;
         if condition.into_boolean().with_location(location)? {
             self.execute(then_branch)?;
         } else if let Some(else_branch) = else_branch {
-            self.execute(*else_branch)?;
+            self.execute(else_branch)?;
         }
         Ok(())
     }
 
     fn execute_while(
         &mut self,
-        condition: ResolvedCodeExpression,
-        body: ResolvedStatement,
+        condition: &ResolvedCodeExpression,
+        body: &ResolvedStatement,
+        increment: Option<&ResolvedCodeExpression>,
     ) -> RuntimeResult<()> {
         while self
-            .evaluate(condition.clone())?
+            .evaluate(condition)?
             .into_boolean()
             .with_location(condition.location)?
         {
-            self.execute(body.clone())?;
+            if !self.execute_loop_body(body)? {
+                break;
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
         }
         Ok(())
     }
 
-    fn execute_fun(
+    fn execute_do_while(
         &mut self,
-        name: String,
-        params: Vec<String>,
-        body: Vec<ResolvedStatement>,
+        body: &ResolvedStatement,
+        condition: &ResolvedCodeExpression,
     ) -> RuntimeResult<()> {
-        let function = Value::Callable(LoxCallable::LoxFunction {
-            name: name.clone(),
-            params,
-            body,
-            closure: self.environment.clone(),
-        });
-        self.environment.borrow_mut().define(name, function);
-        Ok(())
+        loop {
+            if !self.execute_loop_body(body)? {
+                return Ok(());
+            }
+            if !self
+                .evaluate(condition)?
+                .into_boolean()
+                .with_location(condition.location)?
+            {
+                return Ok(());
+            }
+        }
     }
 
-    fn execute_return(&mut self, expression: Option<ResolvedCodeExpression>) -> RuntimeResult<()> {
-        let value = expression
-            .map(|e| self.evaluate(e))
-            .transpose()?
-            .unwrap_or(Value::Nil);
-        Err(RuntimeError {
-            location: (0, 0),
-            value: RuntimeErrorKind::Returning(value),
-        })
+    /// Runs one iteration of a loop. Returns false if the loop should stop because of a `break`.
+    fn execute_loop_body(&mut self, body: &ResolvedStatement) -> RuntimeResult<bool> {
+        match self.execute(body) {
+            Err(RuntimeError {
+                value: RuntimeErrorKind::Breaking(None),
+                ..
+            }) => Ok(false),
+            Err(RuntimeError {
+                value: RuntimeErrorKind::Continuing,
+                ..
+            })
+            | Ok(()) => Ok(true),
+            Err(e) => Err(e),
+        }
     }
 
-    pub fn evaluate(&mut self, expression: ResolvedCodeExpression) -> RuntimeResult<Value> {
-        let loc = expression.location;
-        match expression.value {
-            Expression::Literal(l) => Ok(self.evaluate_literal(l)),
-            Expression::Assign(v, e) => self.evaluate_assign(loc, v, *e),
-            Expression::Grouping(e) => self.evaluate(*e),
-            Expression::Unary(o, r) => self.evaluate_unary(loc, o, *r),
-            Expression::Binary(l, o, r) => self.evaluate_binary(loc, *l, o, *r),
-            Expression::Variable(v) => self.environment.borrow().get(v).with_location(loc),
-            Expression::Call(c, a) => self.evaluate_call(loc, *c, a),
+    /// A new scope inside the globals with the variables a function or class captures from the
+    /// current scope, sharing their cells
+    fn closure(
+        &mut self,
+        captures: &[ResolvedVariable],
+    ) -> Result<Rc<RefCell<Environment>>, RuntimeErrorKind> {
+        let closure = Environment::new_inside(self.globals.clone());
+        for variable in captures {
+            let cell = self.environment.borrow_mut().capture(variable)?;
+            closure
+                .borrow_mut()
+                .define_captured(variable.name.clone(), cell);
         }
+        Ok(closure)
     }
 
-    fn evaluate_assign(
+    /// Defines a function or class, whose closure is made after its name is declared so that it
+    /// can capture itself
+    fn define_with_closure(
         &mut self,
-        location: (usize, usize),
-        variable: ResolvedVariable,
-        expression: ResolvedCodeExpression,
-    ) -> RuntimeResult<Value> {
-        let value = self.evaluate(expression)?;
+        name: &Located<String>,
+        captures: &[ResolvedVariable],
+        value: impl FnOnce(Rc<RefCell<Environment>>) -> Value,
+    ) -> RuntimeResult<()> {
         self.environment
             .borrow_mut()
-            .assign(variable, value.clone())
-            .with_location(location)?;
-        Ok(value)
+            .define(name.value.clone(), Value::Nil);
+        let closure = self.closure(captures).with_location(name.location)?;
+        let value = value(closure);
+        let variable = ResolvedVariable {
+            name: name.value.clone(),
+            hops: Some(0),
+        };
+        self.environment
+            .borrow_mut()
+            .assign(&variable, value)
+            .with_location(name.location)
     }
 
-    fn evaluate_literal(&self, literal: Literal) -> Value {
-        match literal {
-            Literal::String(s) => Value::String(s),
-            Literal::Number(n) => Value::Number(n),
-            Literal::Boolean(b) => Value::Boolean(b),
-            Literal::Nil => Value::Nil,
+    fn execute_foreach(
+        &mut self,
+        variable: &str,
+        iterable: &ResolvedCodeExpression,
+        body: &Rc<ResolvedStatement>,
+    ) -> RuntimeResult<()> {
+        let environment = self.environment.clone();
+        let elements: Rc<[Value]> = match self.evaluate(iterable)? {
+            // Elements the body adds to or removes from the list don't change what it goes over
+            Value::List(l) => l.borrow().iter().cloned().collect(),
+            Value::Tuple(t) => t,
+            Value::Generator(generator) => {
+                return self.execute_foreach_generator(variable, iterable, body, &generator)
+            }
+            v => {
+                return Err(RuntimeError {
+                    location: iterable.location,
+                    value: RuntimeErrorKind::TypeErrorMultiple(
+                        vec![Type::List, Type::Tuple, Type::Generator],
+                        v,
+                    ),
+                })
+            }
+        };
+        for element in elements.iter() {
+            if !self.execute_foreach_body(environment.clone(), variable, element.clone(), body)? {
+                break;
+            }
         }
+        Ok(())
     }
 
-    fn evaluate_unary(
+    /// Runs the generator's body, which runs the foreach body at every `yield`. Whatever stops the
+    /// foreach body comes back out wrapped in LeavingGenerator.
+    fn execute_foreach_generator(
         &mut self,
-        location: (usize, usize),
-        o: UnaryOperator,
-        r: ResolvedCodeExpression,
-    ) -> RuntimeResult<Value> {
-        let right = self.evaluate(r)?;
+        variable: &str,
+        iterable: &ResolvedCodeExpression,
+        body: &Rc<ResolvedStatement>,
+        generator: &Generator,
+    ) -> RuntimeResult<()> {
+        self.yield_targets.push(YieldTarget {
+            environment: self.environment.clone(),
+            variable: variable.to_string(),
+            body: body.clone(),
+        });
+        let result =
+            generator
+                .function
+                .clone()
+                .call(self, generator.args.clone(), iterable.location);
+        self.yield_targets.pop();
+        match result {
+            Ok(_) => Ok(()),
+            Err(RuntimeError {
+                value: RuntimeErrorKind::LeavingGenerator(reason),
+                ..
+            }) => match reason {
+                None => Ok(()),
+                Some(e) => Err(*e),
+            },
+            Err(e) => Err(e),
+        }
+    }
 
-        Ok(match o {
-            UnaryOperator::Minus => Value::Number(-right.into_number().with_location(location)?),
-            UnaryOperator::Not => Value::Boolean(!right.into_boolean().with_location(location)?),
-        })
+    /// Runs the body of a foreach for one element, in a new scope inside `environment`
+    fn execute_foreach_body(
+        &mut self,
+        environment: Rc<RefCell<Environment>>,
+        variable: &str,
+        element: Value,
+        body: &ResolvedStatement,
+    ) -> RuntimeResult<bool> {
+        let scope = Environment::new_inside(environment);
+        scope.borrow_mut().define(variable.to_string(), element);
+        let outer = std::mem::replace(&mut self.environment, scope);
+        let res = self.execute_loop_body(body);
+        self.environment = outer;
+        res
     }
 
-    fn evaluate_binary(
+    fn execute_yield(&mut self, expression: &ResolvedCodeExpression) -> RuntimeResult<()> {
+        let value = self.evaluate(expression)?;
+        // The foreach body runs outside of this generator, so a generator it runs in turn belongs
+        // to a foreach further out
+        let target = self
+            .yield_targets
+            .pop()
+            .expect("the resolver only allows yield in generators, which only foreach runs");
+        let res = self.execute_foreach_body(
+            target.environment.clone(),
+            &target.variable,
+            value,
+            &target.body,
+        );
+        self.yield_targets.push(target);
+        match res {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(RuntimeError {
+                location: expression.location,
+                value: RuntimeErrorKind::LeavingGenerator(None),
+            }),
+            Err(e) => Err(RuntimeError {
+                location: e.location,
+                value: RuntimeErrorKind::LeavingGenerator(Some(Box::new(e))),
+            }),
+        }
+    }
+
+    fn execute_fun(
         &mut self,
-        location: (usize, usize),
-        left: ResolvedCodeExpression,
-        operator: BinaryOperator,
-        right: ResolvedCodeExpression,
-    ) -> RuntimeResult<Value> {
-        let left = self.evaluate(left)?;
-        let right = self.evaluate(right)?;
+        name: &Located<String>,
+        params: &[String],
+        body: &Rc<Vec<ResolvedStatement>>,
+        generator: bool,
+        captures: &[ResolvedVariable],
+    ) -> RuntimeResult<()> {
+        self.define_with_closure(name, captures, |closure| {
+            let mut function = LoxCallable::LoxFunction {
+                name: name.value.clone(),
+                params: params.to_vec(),
+                body: body.clone(),
+                closure,
+            };
+            if generator {
+                function = LoxCallable::GeneratorFunction(Rc::new(function));
+            }
+            Value::Callable(function)
+        })
+    }
 
-        // budget try-catch
-        let res: Result<Value, RuntimeErrorKind> = (|| {
-            let res = match operator {
-                // Math
-                BinaryOperator::Subtract => {
-                    Value::Number(left.into_number()? - right.into_number()?)
-                }
-                BinaryOperator::Divide => Value::Number(left.into_number()? / right.into_number()?),
-                BinaryOperator::Multiply => {
-                    Value::Number(left.into_number()? * right.into_number()?)
+    fn execute_class(
+        &mut self,
+        name: &Located<String>,
+        superclass: Option<&ResolvedCodeExpression>,
+        methods: &[ResolvedStatement],
+        captures: &[ResolvedVariable],
+    ) -> RuntimeResult<()> {
+        let superclass = match superclass {
+            Some(s) => Some(self.evaluate(s)?.into_class().with_location(s.location)?),
+            None => None,
+        };
+        self.define_with_closure(name, captures, |closure| {
+            // Methods of a subclass see `super` in a scope of its own, as the resolver expects
+            let closure = match &superclass {
+                Some(superclass) => {
+                    let scope = Environment::new_inside(closure);
+                    scope
+                        .borrow_mut()
+                        .define("super".to_string(), Value::Class(superclass.clone()));
+                    scope
                 }
-                // Comparison
-                BinaryOperator::Less => Value::Boolean(left.into_number()? < right.into_number()?),
-                BinaryOperator::LessEquals => {
-                    Value::Boolean(left.into_number()? <= right.into_number()?)
+                None => closure,
+            };
+            let methods = methods
+                .iter()
+                .filter_map(|method| match method {
+                    ResolvedStatement::Function(name, params, body, ..) => Some((
+                        name.value.clone(),
+                        LoxCallable::LoxFunction {
+                            name: name.value.clone(),
+                            params: params.clone(),
+                            body: body.clone(),
+                            closure: closure.clone(),
+                        },
+                    )),
+                    _ => None,
+                })
+                .collect();
+            Value::Class(Rc::new(LoxClass {
+                name: name.value.clone(),
+                superclass,
+                methods,
+            }))
+        })
+    }
+
+    fn execute_assert(
+        &mut self,
+        condition: &ResolvedCodeExpression,
+        message: &Option<ResolvedCodeExpression>,
+    ) -> RuntimeResult<()> {
+        if self.skip_assertions || self.evaluate(condition)?.is_truthy() {
+            return Ok(());
+        }
+        let message = message
+            .as_ref()
+            .map(|m| self.evaluate(m))
+            .transpose()?
+            .map(|m| m.to_string());
+        Err(RuntimeError {
+            location: condition.location,
+            value: RuntimeErrorKind::AssertionFailed(message),
+        })
+    }
+
+    fn execute_return(
+        &mut self,
+        expression: &Option<ResolvedCodeExpression>,
+        tail: bool,
+    ) -> RuntimeResult<()> {
+        // A call in tail position reuses the current call instead of nesting another one inside it
+        if let (
+            Some(ResolvedCodeExpression {
+                location,
+                value: Expression::Call(callee, args),
+            }),
+            true,
+        ) = (expression, tail)
+        {
+            let (callee, args) = self.prepare_call(*location, callee, args)?;
+            return Err(RuntimeError {
+                location: *location,
+                value: RuntimeErrorKind::TailCall(callee, args),
+            });
+        }
+
+        let value = expression
+            .as_ref()
+            .map(|e| self.evaluate(e))
+            .transpose()?
+            .unwrap_or(Value::Nil);
+        Err(RuntimeError {
+            location: (0, 0),
+            value: RuntimeErrorKind::Returning(value),
+        })
+    }
+
+    pub fn evaluate(&mut self, expression: &ResolvedCodeExpression) -> RuntimeResult<Value> {
+        let loc = expression.location;
+        match &expression.value {
+            Expression::Literal(l) => Ok(l.into()),
+            Expression::List(e) => self.evaluate_list(loc, e),
+            Expression::Map(e) => self.evaluate_map(loc, e),
+            Expression::Sequence(e) => self.evaluate_sequence(e),
+            Expression::Tuple(e) => self.evaluate_tuple(loc, e),
+            Expression::Assign(v, e) => self.evaluate_assign(loc, v, e),
+            Expression::Grouping(e) => self.evaluate(e),
+            Expression::Unary(o, r) => self.evaluate_unary(loc, o, r),
+            Expression::Binary(l, o, r) => self.evaluate_binary(loc, l, o, r),
+            Expression::Variable(v) | Expression::This(v) => {
+                self.environment.borrow().get(v).with_location(loc)
+            }
+            Expression::Call(c, a) => self.evaluate_call(loc, c, a),
+            Expression::Index(t, i) => self.evaluate_index(loc, t, i),
+            Expression::Get(o, n) => {
+                let object = self.evaluate(o)?;
+                get_field(&object, n).with_location(loc)
+            }
+            Expression::OptionalGet(o, n) => match self.evaluate(o)? {
+                Value::Nil => Ok(Value::Nil),
+                object => get_field(&object, n).with_location(loc),
+            },
+            Expression::Set(o, n, v) => self.evaluate_set(loc, o, n, v),
+            Expression::Super(v, this, n) => self.evaluate_super(loc, v, this, n),
+            Expression::Lambda(params, body, captures) => {
+                Ok(Value::Callable(LoxCallable::LoxFunction {
+                    name: ANONYMOUS.to_string(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: self.closure(captures).with_location(loc)?,
+                }))
+            }
+        }
+    }
+
+    fn evaluate_assign(
+        &mut self,
+        location: (usize, usize),
+        variable: &ResolvedVariable,
+        expression: &ResolvedCodeExpression,
+    ) -> RuntimeResult<Value> {
+        let value = self.evaluate(expression)?;
+        self.environment
+            .borrow_mut()
+            .assign(variable, value.clone())
+            .with_location(location)?;
+        Ok(value)
+    }
+
+    /// `super.name`, bound to the instance the current method was called on
+    fn evaluate_super(
+        &mut self,
+        location: (usize, usize),
+        variable: &ResolvedVariable,
+        this: &ResolvedVariable,
+        name: &str,
+    ) -> RuntimeResult<Value> {
+        let superclass = self
+            .environment
+            .borrow()
+            .get(variable)
+            .and_then(Value::into_class)
+            .with_location(location)?;
+        let instance = self
+            .environment
+            .borrow()
+            .get(this)
+            .with_location(location)?;
+        match superclass.find_method(name) {
+            Some(method) => Ok(Value::Callable(method.bind(instance))),
+            None => Err(RuntimeError {
+                location,
+                value: RuntimeErrorKind::UndefinedProperty(name.into()),
+            }),
+        }
+    }
+
+    fn evaluate_set(
+        &mut self,
+        location: (usize, usize),
+        object: &ResolvedCodeExpression,
+        name: &str,
+        value: &ResolvedCodeExpression,
+    ) -> RuntimeResult<Value> {
+        let fields = match self.evaluate(object)? {
+            Value::Instance(instance) => instance,
+            object => {
+                let map = object.into_map().with_location(location)?;
+                let value = self.evaluate(value)?;
+                map.borrow_mut().insert(name.into(), value.clone());
+                return Ok(value);
+            }
+        };
+        let value = self.evaluate(value)?;
+        fields
+            .fields
+            .borrow_mut()
+            .insert(name.into(), value.clone());
+        Ok(value)
+    }
+
+    fn evaluate_list(
+        &mut self,
+        location: (usize, usize),
+        elements: &[ResolvedCodeExpression],
+    ) -> RuntimeResult<Value> {
+        let mut values = Vec::with_capacity(elements.len());
+        for element in elements {
+            values.push(self.evaluate(element)?);
+        }
+        self.allocate(values.len() * std::mem::size_of::<Value>())
+            .with_location(location)?;
+        Ok(Value::List(Rc::new(RefCell::new(values))))
+    }
+
+    fn evaluate_tuple(
+        &mut self,
+        location: (usize, usize),
+        elements: &[ResolvedCodeExpression],
+    ) -> RuntimeResult<Value> {
+        let values = elements
+            .iter()
+            .map(|e| self.evaluate(e))
+            .collect::<RuntimeResult<Rc<[Value]>>>()?;
+        self.allocate(values.len() * std::mem::size_of::<Value>())
+            .with_location(location)?;
+        Ok(Value::Tuple(values))
+    }
+
+    fn evaluate_map(
+        &mut self,
+        location: (usize, usize),
+        entries: &[(ResolvedCodeExpression, ResolvedCodeExpression)],
+    ) -> RuntimeResult<Value> {
+        let mut map = BTreeMap::new();
+        for (key, value) in entries {
+            let key_location = key.location;
+            let key = self
+                .evaluate(key)?
+                .into_string()
+                .with_location(key_location)?;
+            map.insert(key, self.evaluate(value)?);
+        }
+        self.allocate(map.len() * std::mem::size_of::<(Rc<str>, Value)>())
+            .with_location(location)?;
+        Ok(Value::Map(Rc::new(RefCell::new(map))))
+    }
+
+    fn evaluate_sequence(
+        &mut self,
+        expressions: &[ResolvedCodeExpression],
+    ) -> RuntimeResult<Value> {
+        let mut value = Value::Nil;
+        for expression in expressions {
+            value = self.evaluate(expression)?;
+        }
+        Ok(value)
+    }
+
+    fn evaluate_unary(
+        &mut self,
+        location: (usize, usize),
+        o: &UnaryOperator,
+        r: &ResolvedCodeExpression,
+    ) -> RuntimeResult<Value> {
+        let right = self.evaluate(r)?;
+
+        Ok(match o {
+            UnaryOperator::Minus => Value::Number(-right.into_number().with_location(location)?),
+            UnaryOperator::Not => Value::Boolean(!right.into_boolean().with_location(location)?),
+        })
+    }
+
+    fn evaluate_binary(
+        &mut self,
+        location: (usize, usize),
+        left: &ResolvedCodeExpression,
+        operator: &BinaryOperator,
+        right: &ResolvedCodeExpression,
+    ) -> RuntimeResult<Value> {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+
+        // budget try-catch
+        let res: Result<Value, RuntimeErrorKind> = (|| {
+            let res = match operator {
+                // Math
+                BinaryOperator::Subtract => {
+                    Value::Number(left.into_number()? - right.into_number()?)
+                }
+                BinaryOperator::Divide | BinaryOperator::FloorDivide => {
+                    let (left, right) = (left.into_number()?, right.into_number()?);
+                    if self.check_division_by_zero && right == 0.0 {
+                        return Err(RuntimeErrorKind::DivisionByZero);
+                    }
+                    match operator {
+                        BinaryOperator::FloorDivide => Value::Number((left / right).floor()),
+                        _ => Value::Number(left / right),
+                    }
                 }
-                BinaryOperator::Greater => {
-                    Value::Boolean(left.into_number()? > right.into_number()?)
+                BinaryOperator::Multiply => match (&left, &right) {
+                    (Value::String(s), count @ Value::Number(_))
+                    | (count @ Value::Number(_), Value::String(s)) => {
+                        let count = count.clone().into_usize_index()?;
+                        self.allocate(s.len().saturating_mul(count))?;
+                        Value::String(repeat_string(s, count)?.into())
+                    }
+                    // Left is fine (if it's a string), right must be wrong
+                    (Value::String(_), _) => {
+                        return Err(RuntimeErrorKind::TypeError(Type::Number, right.clone()))
+                    }
+                    _ => Value::Number(left.into_number()? * right.into_number()?),
+                },
+                BinaryOperator::Power => {
+                    Value::Number(left.into_number()?.powf(right.into_number()?))
                 }
-                BinaryOperator::GreaterEquals => {
-                    Value::Boolean(left.into_number()? >= right.into_number()?)
+                // Comparison
+                BinaryOperator::Less
+                | BinaryOperator::LessEquals
+                | BinaryOperator::Greater
+                | BinaryOperator::GreaterEquals => {
+                    let ordering = left.partial_compare(&right)?;
+                    if self.check_nan_comparison && ordering.is_none() {
+                        return Err(RuntimeErrorKind::NanComparison);
+                    }
+                    Value::Boolean(compare(operator, ordering))
                 }
                 // Equality
                 BinaryOperator::Equals => Value::Boolean(left == right),
                 BinaryOperator::NotEquals => Value::Boolean(left != right),
+                BinaryOperator::Is => Value::Boolean(left.is_identical(&right)),
                 // Logical - short circuiting
                 BinaryOperator::And => {
                     let left = left.into_boolean()?;
@@ -269,23 +1206,32 @@ impl Interpreter {
                 // Add
                 BinaryOperator::Add => match (&left, &right) {
                     (Value::Number(l), Value::Number(r)) => Value::Number(l + r),
-                    (Value::String(l), Value::Number(r)) => {
-                        Value::String(l.to_string() + &r.to_string())
+                    // Chars join with strings and each other the same way strings do
+                    (Value::String(_), Value::Number(_))
+                    | (Value::Number(_), Value::String(_))
+                    | (Value::String(_) | Value::Char(_), Value::String(_) | Value::Char(_)) => {
+                        let joined = left.to_display_string() + &right.to_display_string();
+                        self.allocate(joined.len())?;
+                        Value::String(joined.into())
                     }
-                    (Value::Number(l), Value::String(r)) => Value::String(l.to_string() + &r),
-                    (Value::String(l), Value::String(r)) => Value::String(l.to_string() + &r),
                     (Value::Number(_) | Value::String(_), _) => {
                         // Left is fine, right must be wrong
                         return Err(RuntimeErrorKind::TypeErrorMultiple(
                             vec![Type::Number, Type::String],
-                            right.value_type(),
+                            right.clone(),
+                        ));
+                    }
+                    (Value::Char(_), _) => {
+                        return Err(RuntimeErrorKind::TypeErrorMultiple(
+                            vec![Type::String, Type::Char],
+                            right.clone(),
                         ));
                     }
                     _ => {
                         // Left must be wrong
                         return Err(RuntimeErrorKind::TypeErrorMultiple(
                             vec![Type::Number, Type::String],
-                            left.value_type(),
+                            left.clone(),
                         ));
                     }
                 },
@@ -295,33 +1241,240 @@ impl Interpreter {
         res.with_location(location)
     }
 
+    fn evaluate_index(
+        &mut self,
+        location: (usize, usize),
+        target: &ResolvedCodeExpression,
+        index: &ResolvedCodeExpression,
+    ) -> RuntimeResult<Value> {
+        let target = self.evaluate(target)?;
+        let index = self.evaluate(index)?;
+
+        match target {
+            Value::Map(m) => {
+                let key = index.into_string().with_location(location)?;
+                let value = m.borrow().get(&key).cloned();
+                value.ok_or(RuntimeError {
+                    location,
+                    value: RuntimeErrorKind::MissingKey(key),
+                })
+            }
+            Value::String(s) => {
+                let index = index.into_whole_number().with_location(location)?;
+                let length = s.chars().count();
+                let position = resolve_index(index, length).with_location(location)?;
+                Ok(Value::Char(s.chars().nth(position).unwrap()))
+            }
+            Value::List(l) => {
+                let index = index.into_whole_number().with_location(location)?;
+                let l = l.borrow();
+                let position = resolve_index(index, l.len()).with_location(location)?;
+                Ok(l[position].clone())
+            }
+            Value::Bytes(b) => {
+                let index = index.into_whole_number().with_location(location)?;
+                let position = resolve_index(index, b.len()).with_location(location)?;
+                Ok(Value::Number(b[position] as f64))
+            }
+            t => Err(RuntimeError {
+                location,
+                value: RuntimeErrorKind::TypeErrorMultiple(
+                    vec![Type::String, Type::List, Type::Map, Type::Bytes],
+                    t,
+                ),
+            }),
+        }
+    }
+
     fn evaluate_call(
         &mut self,
         location: (usize, usize),
-        callee: ResolvedCodeExpression,
-        args_expressions: Vec<ResolvedCodeExpression>,
+        callee: &ResolvedCodeExpression,
+        args_expressions: &[ResolvedCodeExpression],
     ) -> RuntimeResult<Value> {
-        let callee = self
-            .evaluate(callee)?
-            .into_callable()
-            .with_location(location)?;
+        let (callee, args) = self.prepare_call(location, callee, args_expressions)?;
+        callee.call(self, args, location)
+    }
 
+    /// Evaluates the callee and arguments of a call and checks that they fit together
+    fn prepare_call(
+        &mut self,
+        location: (usize, usize),
+        callee: &ResolvedCodeExpression,
+        args_expressions: &[ResolvedCodeExpression],
+    ) -> RuntimeResult<(LoxCallable, Vec<Value>)> {
         let mut args = Vec::new();
+        let callee = match &callee.value {
+            // `object.method(...)` gets the object itself as its first argument, except for
+            // instances, whose methods get it as `this` instead
+            Expression::Get(object, name) => {
+                let object = self.evaluate(object)?;
+                let method = match &object {
+                    Value::String(_) => string_method(name)
+                        .map(Value::Callable)
+                        .ok_or_else(|| RuntimeErrorKind::UnknownMethod(Type::String, name.clone())),
+                    Value::Bytes(_) => bytes_method(name)
+                        .map(Value::Callable)
+                        .ok_or_else(|| RuntimeErrorKind::UnknownMethod(Type::Bytes, name.clone())),
+                    _ => get_field(&object, name),
+                }
+                .with_location(callee.location)?;
+                if !matches!(object, Value::Instance(_)) {
+                    args.push(object);
+                }
+                method
+            }
+            _ => self.evaluate(callee)?,
+        }
+        .into_callable()
+        .with_location(location)?;
+
         for arg in args_expressions {
             args.push(self.evaluate(arg)?);
         }
 
-        if args.len() != callee.arity() {
-            return Err(RuntimeError {
-                location,
-                value: RuntimeErrorKind::WrongArgsNum(args.len(), callee.arity()),
-            });
+        if let Some(arity) = callee.arity() {
+            if args.len() != arity {
+                return Err(RuntimeError {
+                    location,
+                    value: RuntimeErrorKind::WrongArgsNum(
+                        callee.name().to_string(),
+                        args.len(),
+                        arity,
+                    ),
+                });
+            }
         }
 
-        callee.call(self, args, location)
+        Ok((callee, args))
     }
 }
 
+/// `object.name`, which maps and instances have. A field of an instance hides a method of the
+/// same name.
+fn get_field(object: &Value, name: &str) -> Result<Value, RuntimeErrorKind> {
+    if let Value::Instance(instance) = object {
+        if let Some(value) = instance.fields.borrow().get(name) {
+            return Ok(value.clone());
+        }
+        return match instance.class.find_method(name) {
+            Some(method) => Ok(Value::Callable(method.bind(object.clone()))),
+            None => Err(RuntimeErrorKind::UndefinedProperty(name.into())),
+        };
+    }
+    let map = object.clone().into_map()?;
+    let value = map.borrow().get(name).cloned();
+    value.ok_or_else(|| RuntimeErrorKind::MissingKey(name.into()))
+}
+
+/// The built in methods of strings, which get the string as their first argument
+fn string_method(name: &str) -> Option<LoxCallable> {
+    let method = match name {
+        "upper" => string_upper,
+        "lower" => string_lower,
+        "len" => string_len,
+        "trim" => string_trim,
+        _ => return None,
+    };
+    Some(LoxCallable::NativeFunction(
+        name.into(),
+        Some(1),
+        Rc::new(Box::new(method)),
+    ))
+}
+
+/// The built in methods of bytes, which get the bytes as their first argument
+fn bytes_method(name: &str) -> Option<LoxCallable> {
+    let method = match name {
+        "len" => bytes_len,
+        _ => return None,
+    };
+    Some(LoxCallable::NativeFunction(
+        name.into(),
+        Some(1),
+        Rc::new(Box::new(method)),
+    ))
+}
+
+fn bytes_len(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let [value] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum("len".into(), args.len(), 1));
+    };
+    Ok(Value::Number(value.clone().into_bytes()?.len() as f64))
+}
+
+/// The string a string method was called on
+fn receiver_string(name: &str, args: &[Value]) -> Result<Rc<str>, RuntimeErrorKind> {
+    let [value] = args else {
+        return Err(RuntimeErrorKind::WrongArgsNum(name.into(), args.len(), 1));
+    };
+    value.clone().into_string()
+}
+
+fn string_upper(
+    interpreter: &mut Interpreter,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeErrorKind> {
+    let string = receiver_string("upper", &args)?.to_uppercase();
+    interpreter.allocate(string.len())?;
+    Ok(Value::String(string.into()))
+}
+
+fn string_lower(
+    interpreter: &mut Interpreter,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeErrorKind> {
+    let string = receiver_string("lower", &args)?.to_lowercase();
+    interpreter.allocate(string.len())?;
+    Ok(Value::String(string.into()))
+}
+
+/// In characters, not bytes
+fn string_len(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let string = receiver_string("len", &args)?;
+    Ok(Value::Number(string.chars().count() as f64))
+}
+
+fn string_trim(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let string = receiver_string("trim", &args)?;
+    interpreter.allocate(string.trim().len())?;
+    Ok(Value::String(string.trim().into()))
+}
+
+/// One of the ordering operators. Unordered values (NaN) fail every comparison.
+fn compare(operator: &BinaryOperator, ordering: Option<Ordering>) -> bool {
+    let Some(ordering) = ordering else {
+        return false;
+    };
+    match operator {
+        BinaryOperator::Less => ordering.is_lt(),
+        BinaryOperator::LessEquals => ordering.is_le(),
+        BinaryOperator::Greater => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    }
+}
+
+/// `string * count`
+fn repeat_string(string: &str, count: usize) -> Result<String, RuntimeErrorKind> {
+    if count.saturating_mul(string.len()) > MAX_STRING_LENGTH {
+        return Err(RuntimeErrorKind::StringTooLong(MAX_STRING_LENGTH));
+    }
+    Ok(string.repeat(count))
+}
+
+/// Turns a whole index (negative ones count from the end) into a position inside the bounds
+fn resolve_index(index: f64, length: usize) -> Result<usize, RuntimeErrorKind> {
+    let position = if index < 0.0 {
+        length as f64 + index
+    } else {
+        index
+    };
+    if position < 0.0 || position >= length as f64 {
+        return Err(RuntimeErrorKind::IndexOutOfBounds(index, length));
+    }
+    Ok(position as usize)
+}
+
 fn clock(_interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
     Ok(Value::Number(
         SystemTime::now()
@@ -331,10 +1484,693 @@ fn clock(_interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, Run
     ))
 }
 
+fn random(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    Ok(Value::Number(interpreter.next_random()))
+}
+
+/// Makes random() give the same sequence every time for the same whole number
+fn seed(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum("seed".into(), args.len(), 1));
+    };
+    let seed = value.clone().into_whole_number()?;
+    interpreter.seed_random(seed as i64 as u64);
+    Ok(Value::Nil)
+}
+
+/// Pauses for the given number of milliseconds
+fn sleep(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "sleep".into(),
+            args.len(),
+            1,
+        ));
+    };
+    let ms = value.clone().into_usize_index()?;
+
+    std::thread::sleep(Duration::from_millis(ms as u64));
+    Ok(Value::Nil)
+}
+
+/// The whole contents of a file
+fn read_bytes(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let [path] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "read_bytes".into(),
+            args.len(),
+            1,
+        ));
+    };
+    let path = path.clone().into_string()?;
+    let contents = std::fs::read(&*path).map_err(|e| RuntimeErrorKind::Io(path.to_string(), e))?;
+    interpreter.allocate(contents.len())?;
+    Ok(Value::Bytes(contents.into()))
+}
+
+/// Replaces the contents of a file, creating it if it doesn't exist
+fn write_bytes(
+    _interpreter: &mut Interpreter,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeErrorKind> {
+    let [path, contents] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "write_bytes".into(),
+            args.len(),
+            2,
+        ));
+    };
+    let path = path.clone().into_string()?;
+    let contents = contents.clone().into_bytes()?;
+    std::fs::write(&*path, contents).map_err(|e| RuntimeErrorKind::Io(path.to_string(), e))?;
+    Ok(Value::Nil)
+}
+
+/// Describes every variable visible from where it's called
+fn dump_env(interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    Ok(Value::String(
+        interpreter.environment.borrow().dump().into(),
+    ))
+}
+
+/// Every global variable and its value, as a map. Changing the map doesn't change the variables.
+fn globals_map(
+    interpreter: &mut Interpreter,
+    _args: Vec<Value>,
+) -> Result<Value, RuntimeErrorKind> {
+    let map = interpreter.globals.borrow().snapshot();
+    interpreter.allocate(map.len() * std::mem::size_of::<(Rc<str>, Value)>())?;
+    Ok(Value::Map(Rc::new(RefCell::new(map))))
+}
+
+fn pow(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let [base, exponent] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum("pow".into(), args.len(), 2));
+    };
+    let base = base.clone().into_number()?;
+    let exponent = exponent.clone().into_number()?;
+    Ok(Value::Number(base.powf(exponent)))
+}
+
+/// Greatest common divisor of two whole numbers, never negative
+fn gcd(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let [a, b] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum("gcd".into(), args.len(), 2));
+    };
+    let a = a.clone().into_whole_number()?;
+    let b = b.clone().into_whole_number()?;
+    Ok(Value::Number(whole_gcd(a, b)))
+}
+
+/// Least common multiple of two whole numbers, never negative
+fn lcm(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let [a, b] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum("lcm".into(), args.len(), 2));
+    };
+    let a = a.clone().into_whole_number()?;
+    let b = b.clone().into_whole_number()?;
+    let gcd = whole_gcd(a, b);
+    if gcd == 0.0 {
+        return Ok(Value::Number(0.0));
+    }
+    Ok(Value::Number((a / gcd * b).abs()))
+}
+
+/// `%` is exact for whole floats, so Euclid's algorithm works on them directly
+fn whole_gcd(a: f64, b: f64) -> f64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0.0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Formats a number with commas between groups of three digits, like `1,234,567.5`
+fn group_digits(
+    _interpreter: &mut Interpreter,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "group_digits".into(),
+            args.len(),
+            1,
+        ));
+    };
+    let n = value.clone().into_number()?;
+    if !n.is_finite() {
+        return Ok(Value::String(value.to_display_string().into()));
+    }
+
+    // Display for f64 never uses an exponent, so this is always plain digits
+    let formatted = n.abs().to_string();
+    let (integer, fraction) = match formatted.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (formatted.as_str(), None),
+    };
+
+    let mut grouped = String::new();
+    if n.is_sign_negative() && n != 0.0 {
+        grouped.push('-');
+    }
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        grouped.push('.');
+        grouped += fraction;
+    }
+    Ok(Value::String(grouped.into()))
+}
+
 fn debug(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
     let &[value] = &args.as_slice() else {
-        return Err(RuntimeErrorKind::WrongArgsNum(args.len(), 1));
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "debug".into(),
+            args.len(),
+            1,
+        ));
+    };
+
+    Ok(Value::String(format!("{:?}", value).into()))
+}
+
+fn bool(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum("bool".into(), args.len(), 1));
+    };
+
+    Ok(Value::Boolean(value.is_truthy()))
+}
+
+/// Bytes made from a list of numbers from 0 to 255
+fn bytes(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let [list] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "bytes".into(),
+            args.len(),
+            1,
+        ));
+    };
+    let list = list.clone().into_list()?;
+    let bytes = list
+        .borrow()
+        .iter()
+        .map(|value| {
+            let n = value.clone().into_whole_number()?;
+            match n {
+                0.0..=255.0 => Ok(n as u8),
+                n => Err(RuntimeErrorKind::ByteOutOfRange(n)),
+            }
+        })
+        .collect::<Result<Rc<[u8]>, _>>()?;
+    interpreter.allocate(bytes.len())?;
+    Ok(Value::Bytes(bytes))
+}
+
+/// Whether the value can be called, which classes can too
+fn is_callable(
+    _interpreter: &mut Interpreter,
+    args: Vec<Value>,
+) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "is_callable".into(),
+            args.len(),
+            1,
+        ));
+    };
+
+    Ok(Value::Boolean(matches!(
+        value,
+        Value::Callable(_) | Value::Class(_)
+    )))
+}
+
+/// Whether the value is a function built into the interpreter
+fn is_native(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "is_native".into(),
+            args.len(),
+            1,
+        ));
+    };
+
+    Ok(Value::Boolean(matches!(
+        value,
+        Value::Callable(LoxCallable::NativeFunction(..))
+    )))
+}
+
+fn range(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let [start, end] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "range".into(),
+            args.len(),
+            2,
+        ));
+    };
+    let start = start.clone().into_whole_number()?;
+    let end = end.clone().into_whole_number()?;
+
+    let length = (end - start).max(0.0);
+    if length > MAX_RANGE_LENGTH as f64 {
+        return Err(RuntimeErrorKind::RangeTooLong(MAX_RANGE_LENGTH));
+    }
+    interpreter.allocate(length as usize * std::mem::size_of::<Value>())?;
+    let values = (0..length as usize)
+        .map(|i| Value::Number(start + i as f64))
+        .collect();
+    Ok(Value::List(Rc::new(RefCell::new(values))))
+}
+
+/// The keys of a map as a list of strings, in sorted order
+fn keys(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum("keys".into(), args.len(), 1));
+    };
+
+    let map = value.clone().into_map()?;
+    let keys = map.borrow().keys().cloned().map(Value::String).collect();
+    Ok(Value::List(Rc::new(RefCell::new(keys))))
+}
+
+/// The values of a map as a list, in the order of their keys
+fn values(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "values".into(),
+            args.len(),
+            1,
+        ));
+    };
+
+    let map = value.clone().into_map()?;
+    let values = map.borrow().values().cloned().collect();
+    Ok(Value::List(Rc::new(RefCell::new(values))))
+}
+
+/// A sorted copy of a list, ordered the same way `<` orders values
+fn sort(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let &[list] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum("sort".into(), args.len(), 1));
+    };
+
+    let values = list.clone().into_list()?.borrow().clone();
+    let sorted = merge_sort(values, &mut |a, b| {
+        a.partial_compare(b)?.ok_or(RuntimeErrorKind::NanComparison)
+    })?;
+    Ok(Value::List(Rc::new(RefCell::new(sorted))))
+}
+
+/// A sorted copy of a list, ordered by a function that takes two elements and returns a number
+/// that's negative, zero or positive when the first is less than, equal to or greater than the
+/// second
+fn sort_by(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let [list, comparator] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "sort_by".into(),
+            args.len(),
+            2,
+        ));
+    };
+
+    let values = list.clone().into_list()?.borrow().clone();
+    let comparator = comparator.clone().into_callable()?;
+    if let Some(arity) = comparator.arity().filter(|&arity| arity != 2) {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            comparator.name().to_string(),
+            2,
+            arity,
+        ));
+    }
+    let sorted = merge_sort(values, &mut |a, b| {
+        let result = comparator
+            .clone()
+            .call(interpreter, vec![a.clone(), b.clone()], (0, 0))
+            .map_err(|e| e.value)?
+            .into_number()?;
+        Ok(result.partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+    })?;
+    Ok(Value::List(Rc::new(RefCell::new(sorted))))
+}
+
+/// `compose(f, g)(x)` is `f(g(x))`
+fn compose(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let [outer, inner] = args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "compose".into(),
+            args.len(),
+            2,
+        ));
+    };
+
+    let outer = outer.clone().into_callable()?;
+    let inner = inner.clone().into_callable()?;
+    if let Some(arity) = outer.arity().filter(|&arity| arity != 1) {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            outer.name().to_string(),
+            1,
+            arity,
+        ));
+    }
+    Ok(Value::Callable(LoxCallable::Composed {
+        outer: Rc::new(outer),
+        inner: Rc::new(inner),
+    }))
+}
+
+/// A stable sort that stops at the first error. The std sorts need a comparison that can't fail
+/// (and may panic if it isn't consistent, which a Lox comparator doesn't have to be).
+fn merge_sort(
+    mut values: Vec<Value>,
+    compare: &mut impl FnMut(&Value, &Value) -> Result<Ordering, RuntimeErrorKind>,
+) -> Result<Vec<Value>, RuntimeErrorKind> {
+    if values.len() <= 1 {
+        return Ok(values);
+    }
+    let right = values.split_off(values.len() / 2);
+    let left = merge_sort(values, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Only take from the right when it's strictly less, to keep equal elements in order
+        if compare(r, l)?.is_lt() {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+fn pretty(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum(
+            "pretty".into(),
+            args.len(),
+            1,
+        ));
+    };
+
+    Ok(Value::String(value.pretty_print(0).into()))
+}
+
+/// Prints all of its arguments separated by spaces, like the print statement does with one
+fn print(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    // A single value goes to the print hook as is, like it would from the statement
+    let value = match <[Value; 1]>::try_from(args) {
+        Ok([value]) => value,
+        Err(args) => Value::String(
+            args.iter()
+                .map(Value::to_display_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+                .into(),
+        ),
     };
+    interpreter.print_value(&value, "\n")?;
+    Ok(Value::Nil)
+}
+
+/// Prints its argument like the print statement, but without starting a new line
+fn puts(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+    let &[value] = &args.as_slice() else {
+        return Err(RuntimeErrorKind::WrongArgsNum("puts".into(), args.len(), 1));
+    };
+
+    interpreter.print_value(value, "")?;
+    Ok(Value::Nil)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    /// Runs `source` like a REPL line, returning the value of its final expression
+    fn run(source: &str, interpreter: &mut Interpreter) -> Value {
+        let tokens = Scanner::new(source.to_string()).tokens().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut resolver = Resolver::new();
+        resolver.define_globals(interpreter.global_names());
+        let ast = resolver.resolve(ast).unwrap();
+        interpreter.interpret(ast).unwrap()
+    }
+
+    /// Runs `source` and returns the value of the global `result`
+    fn result_of(source: &str) -> Value {
+        let mut interpreter = Interpreter::new();
+        run(source, &mut interpreter);
+        let result = ResolvedVariable {
+            name: "result".to_string(),
+            hops: None,
+        };
+        let value = interpreter.globals.borrow().get(&result).unwrap();
+        value
+    }
 
-    Ok(Value::String(format!("{:?}", value)))
+    #[test]
+    fn closure_assignment_is_visible_outside() {
+        let source = "
+var result;
+fun f() {
+  var x = 1;
+  fun set() { x = 2; }
+  set();
+  result = x;
+}
+f();
+";
+        assert_eq!(result_of(source), Value::Number(2.0));
+    }
+
+    #[test]
+    fn closure_sees_assignments_made_after_capture() {
+        let source = "
+var result;
+fun f() {
+  var x = 1;
+  fun get() { return x; }
+  x = 2;
+  result = get();
+}
+f();
+";
+        assert_eq!(result_of(source), Value::Number(2.0));
+    }
+
+    #[test]
+    fn closures_share_a_captured_variable() {
+        let source = "
+var increment;
+var read;
+{
+  var count = 0;
+  fun inc() { count = count + 1; }
+  fun get() { return count; }
+  increment = inc;
+  read = get;
+}
+increment();
+increment();
+var result = read();
+";
+        assert_eq!(result_of(source), Value::Number(2.0));
+    }
+
+    #[test]
+    fn each_call_captures_fresh_variables() {
+        let source = "
+fun counter() {
+  var n = 0;
+  fun next() { n = n + 1; return n; }
+  return next;
+}
+var a = counter();
+var b = counter();
+a();
+a();
+var result = a() * 10 + b();
+";
+        assert_eq!(result_of(source), Value::Number(31.0));
+    }
+
+    #[test]
+    fn nested_closures_capture_through_the_enclosing_function() {
+        let source = "
+var result;
+fun outer() {
+  var x = 1;
+  fun middle() {
+    fun inner() { x = x + 1; }
+    return inner;
+  }
+  middle()();
+  result = x;
+}
+outer();
+";
+        assert_eq!(result_of(source), Value::Number(2.0));
+    }
+
+    #[test]
+    fn local_function_can_recurse() {
+        let source = "
+var result;
+{
+  fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+  }
+  result = fib(10);
+}
+";
+        assert_eq!(result_of(source), Value::Number(55.0));
+    }
+
+    #[test]
+    fn method_captures_variable_of_enclosing_function() {
+        let source = "
+var result;
+fun make() {
+  var calls = 0;
+  class Counter {
+    bump() { calls = calls + 1; return calls; }
+  }
+  var counter = Counter();
+  counter.bump();
+  counter.bump();
+  result = calls;
+}
+make();
+";
+        assert_eq!(result_of(source), Value::Number(2.0));
+    }
+
+    #[test]
+    fn function_in_method_captures_this_and_super() {
+        let source = "
+class A {
+  name() { return \"A\"; }
+}
+class B < A {
+  init() { this.suffix = \"!\"; }
+  greet() {
+    fun inner() { return super.name() + this.suffix; }
+    return inner();
+  }
+}
+var result = B().greet();
+";
+        assert_eq!(result_of(source), Value::from("A!"));
+    }
+
+    #[test]
+    fn lambda_captures_its_environment() {
+        let source = "
+fun counter() {
+  var n = 0;
+  return fun () { n = n + 1; return n; };
+}
+var c = counter();
+c();
+var result = c();
+";
+        assert_eq!(result_of(source), Value::Number(2.0));
+    }
+
+    #[test]
+    fn lambda_passed_straight_to_a_function() {
+        let source = "
+fun apply(f, x) { return f(x); }
+var k = 3;
+var result = apply(fun (x) { return x * k; }, 2);
+";
+        assert_eq!(result_of(source), Value::Number(6.0));
+    }
+
+    #[test]
+    fn lambda_in_method_captures_this_and_super() {
+        let source = "
+class A {
+  name() { return \"A\"; }
+}
+class B < A {
+  init() { this.suffix = \"!\"; }
+  greeter() {
+    return fun () { return super.name() + this.suffix; };
+  }
+}
+var result = B().greeter()();
+";
+        assert_eq!(result_of(source), Value::from("A!"));
+    }
+
+    #[test]
+    fn local_generator_captures_enclosing_variable() {
+        let source = "
+fun multiples(step) {
+  fun* gen() {
+    var i = 1;
+    while (i <= 3) { yield i * step; i = i + 1; }
+  }
+  return gen;
+}
+var result = 0;
+foreach (x in multiples(5)()) result = result + x;
+";
+        assert_eq!(result_of(source), Value::Number(30.0));
+    }
+
+    #[test]
+    fn redefined_function_recurses_into_the_new_definition() {
+        let mut interpreter = Interpreter::new();
+        run(
+            "fun f(n) { if (n > 0) return f(n - 1); return \"old\"; }",
+            &mut interpreter,
+        );
+        run("var g = f;", &mut interpreter);
+        run(
+            "fun f(n) { if (n > 0) return f(n - 1); return \"new\"; }",
+            &mut interpreter,
+        );
+        assert_eq!(run("f(3);", &mut interpreter), Value::from("new"));
+        // The old function looks `f` up by name too, so it ends up in the new one
+        assert_eq!(run("g(3);", &mut interpreter), Value::from("new"));
+        assert_eq!(run("g(0);", &mut interpreter), Value::from("old"));
+    }
+
+    #[test]
+    fn chained_map_fields_and_calls() {
+        let mut interpreter = Interpreter::new();
+        let source = "
+var inner = {\"c\": \"found\"};
+fun b(self) { return inner; }
+var obj = {\"a\": {\"b\": b}};
+obj.a.b().c;
+";
+        assert_eq!(run(source, &mut interpreter), Value::from("found"));
+    }
+
+    #[test]
+    fn chained_string_methods() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            run(
+                "\"  Mixed \".trim().lower().upper().len();",
+                &mut interpreter
+            ),
+            Value::Number(5.0)
+        );
+    }
 }
@@ -0,0 +1,396 @@
+//! Writing the AST out as JSON for external tools. Done by hand, since it's small enough to not
+//! be worth a dependency.
+//!
+//! Every node is an object with a `kind` (the variant name), a `location` (`[line, column]`, or
+//! null when unknown), and its fields by name.
+
+use std::fmt::Write;
+
+use crate::{
+    ast::{
+        BinaryOperator, Expression, GenericCodeExpression, GenericStatement, ResolvedVariable,
+        UnaryOperator,
+    },
+    token::Literal,
+};
+
+pub trait ToJson {
+    fn write_json(&self, out: &mut String);
+}
+
+pub fn to_json(value: &impl ToJson) -> String {
+    let mut out = String::new();
+    value.write_json(&mut out);
+    out
+}
+
+/// An AST node, with its fields in the given order
+fn node(out: &mut String, kind: &str, location: (usize, usize), fields: &[(&str, &dyn ToJson)]) {
+    out.push_str("{\"kind\":");
+    kind.write_json(out);
+    out.push_str(",\"location\":");
+    if location == (0, 0) {
+        out.push_str("null");
+    } else {
+        let _ = write!(out, "[{},{}]", location.0, location.1);
+    }
+    for (name, value) in fields {
+        out.push(',');
+        name.write_json(out);
+        out.push(':');
+        value.write_json(out);
+    }
+    out.push('}');
+}
+
+impl ToJson for str {
+    fn write_json(&self, out: &mut String) {
+        out.push('"');
+        for c in self.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if (c as u32) < 0x20 => {
+                    let _ = write!(out, "\\u{:04x}", c as u32);
+                }
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+}
+
+impl ToJson for &str {
+    fn write_json(&self, out: &mut String) {
+        (**self).write_json(out);
+    }
+}
+
+impl ToJson for String {
+    fn write_json(&self, out: &mut String) {
+        self.as_str().write_json(out);
+    }
+}
+
+impl ToJson for usize {
+    fn write_json(&self, out: &mut String) {
+        let _ = write!(out, "{self}");
+    }
+}
+
+impl ToJson for bool {
+    fn write_json(&self, out: &mut String) {
+        let _ = write!(out, "{self}");
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Some(value) => value.write_json(out),
+            None => out.push_str("null"),
+        }
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Box<T> {
+    fn write_json(&self, out: &mut String) {
+        (**self).write_json(out);
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn write_json(&self, out: &mut String) {
+        out.push('[');
+        for (i, value) in self.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            value.write_json(out);
+        }
+        out.push(']');
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn write_json(&self, out: &mut String) {
+        self.as_slice().write_json(out);
+    }
+}
+
+impl ToJson for ResolvedVariable {
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"name\":");
+        self.name.write_json(out);
+        out.push_str(",\"hops\":");
+        self.hops.write_json(out);
+        out.push('}');
+    }
+}
+
+impl ToJson for Literal {
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Literal::String(s) => s.as_ref().write_json(out),
+            // JSON has no NaN or infinity, and the scanner never produces them anyway
+            Literal::Number(n) if n.is_finite() => {
+                let _ = write!(out, "{n}");
+            }
+            Literal::Number(_) => out.push_str("null"),
+            Literal::Boolean(b) => b.write_json(out),
+            Literal::Nil => out.push_str("null"),
+        }
+    }
+}
+
+impl ToJson for BinaryOperator {
+    fn write_json(&self, out: &mut String) {
+        format!("{self:?}").write_json(out);
+    }
+}
+
+impl ToJson for UnaryOperator {
+    fn write_json(&self, out: &mut String) {
+        format!("{self:?}").write_json(out);
+    }
+}
+
+/// A key and value pair of a map literal
+struct MapEntry<'a, V>(&'a GenericCodeExpression<V>, &'a GenericCodeExpression<V>);
+
+impl<V: ToJson> ToJson for MapEntry<'_, V> {
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"key\":");
+        self.0.write_json(out);
+        out.push_str(",\"value\":");
+        self.1.write_json(out);
+        out.push('}');
+    }
+}
+
+impl<V: ToJson> ToJson for GenericCodeExpression<V> {
+    fn write_json(&self, out: &mut String) {
+        let location = self.location;
+        match &self.value {
+            Expression::Binary(left, operator, right) => node(
+                out,
+                "Binary",
+                location,
+                &[("left", left), ("operator", operator), ("right", right)],
+            ),
+            Expression::Call(callee, arguments) => node(
+                out,
+                "Call",
+                location,
+                &[("callee", callee), ("arguments", arguments)],
+            ),
+            Expression::Get(object, name) => {
+                node(out, "Get", location, &[("object", object), ("name", name)])
+            }
+            Expression::OptionalGet(object, name) => node(
+                out,
+                "OptionalGet",
+                location,
+                &[("object", object), ("name", name)],
+            ),
+            Expression::Set(object, name, value) => node(
+                out,
+                "Set",
+                location,
+                &[("object", object), ("name", name), ("value", value)],
+            ),
+            Expression::Grouping(e) => node(out, "Grouping", location, &[("expression", e)]),
+            Expression::Index(target, index) => node(
+                out,
+                "Index",
+                location,
+                &[("target", target), ("index", index)],
+            ),
+            Expression::List(elements) => node(out, "List", location, &[("elements", elements)]),
+            Expression::Literal(literal) => node(out, "Literal", location, &[("value", literal)]),
+            Expression::Map(entries) => {
+                let entries: Vec<_> = entries.iter().map(|(k, v)| MapEntry(k, v)).collect();
+                node(out, "Map", location, &[("entries", &entries)])
+            }
+            Expression::Sequence(expressions) => {
+                node(out, "Sequence", location, &[("expressions", expressions)])
+            }
+            Expression::Tuple(elements) => node(out, "Tuple", location, &[("elements", elements)]),
+            Expression::Unary(operator, e) => node(
+                out,
+                "Unary",
+                location,
+                &[("operator", operator), ("expression", e)],
+            ),
+            Expression::Variable(v) => node(out, "Variable", location, &[("variable", v)]),
+            Expression::This(v) => node(out, "This", location, &[("variable", v)]),
+            Expression::Lambda(params, body, captures) => node(
+                out,
+                "Lambda",
+                location,
+                &[
+                    ("params", params),
+                    ("body", &**body),
+                    ("captures", captures),
+                ],
+            ),
+            Expression::Super(v, this, method) => node(
+                out,
+                "Super",
+                location,
+                &[("variable", v), ("this", this), ("method", method)],
+            ),
+            Expression::Assign(v, e) => {
+                node(out, "Assign", location, &[("variable", v), ("value", e)])
+            }
+        }
+    }
+}
+
+impl<V: ToJson> ToJson for GenericStatement<V> {
+    fn write_json(&self, out: &mut String) {
+        let location = self.location();
+        match self {
+            GenericStatement::Expression(e) => {
+                node(out, "Expression", location, &[("expression", e)])
+            }
+            GenericStatement::Function(name, params, body, generator, captures) => node(
+                out,
+                "Function",
+                location,
+                &[
+                    ("name", &name.value),
+                    ("params", params),
+                    ("body", &**body),
+                    ("generator", generator),
+                    ("captures", captures),
+                ],
+            ),
+            GenericStatement::Class(name, superclass, methods, captures) => node(
+                out,
+                "Class",
+                location,
+                &[
+                    ("name", &name.value),
+                    ("superclass", superclass),
+                    ("methods", methods),
+                    ("captures", captures),
+                ],
+            ),
+            GenericStatement::Print(e) => node(out, "Print", location, &[("expression", e)]),
+            GenericStatement::Assert(condition, message) => node(
+                out,
+                "Assert",
+                location,
+                &[("condition", condition), ("message", message)],
+            ),
+            GenericStatement::Return(e, tail) => node(
+                out,
+                "Return",
+                location,
+                &[("value", &e.value), ("tail", tail)],
+            ),
+            GenericStatement::Throw(e) => node(out, "Throw", location, &[("value", e)]),
+            GenericStatement::Var(name, e) => node(
+                out,
+                "Var",
+                location,
+                &[("name", &name.value), ("initializer", e)],
+            ),
+            GenericStatement::VarTuple(names, e) => node(
+                out,
+                "VarTuple",
+                location,
+                &[("names", names), ("initializer", e)],
+            ),
+            GenericStatement::While(condition, body, increment) => node(
+                out,
+                "While",
+                location,
+                &[
+                    ("condition", condition),
+                    ("body", body),
+                    ("increment", increment),
+                ],
+            ),
+            GenericStatement::DoWhile(body, condition) => node(
+                out,
+                "DoWhile",
+                location,
+                &[("body", body), ("condition", condition)],
+            ),
+            GenericStatement::Block(b) => node(out, "Block", location, &[("statements", b)]),
+            GenericStatement::LabeledBlock(label, b) => node(
+                out,
+                "LabeledBlock",
+                location,
+                &[("label", label), ("statements", b)],
+            ),
+            GenericStatement::Break(b) => node(out, "Break", location, &[("label", &b.value)]),
+            GenericStatement::Continue(_) => node(out, "Continue", location, &[]),
+            GenericStatement::Yield(e) => node(out, "Yield", location, &[("value", e)]),
+            GenericStatement::Foreach(name, iterable, body) => node(
+                out,
+                "Foreach",
+                location,
+                &[("name", name), ("iterable", iterable), ("body", &**body)],
+            ),
+            GenericStatement::TryCatch(body, name, handler) => node(
+                out,
+                "TryCatch",
+                location,
+                &[("body", body), ("name", name), ("handler", handler)],
+            ),
+            GenericStatement::If(condition, then_branch, else_branch) => node(
+                out,
+                "If",
+                location,
+                &[
+                    ("condition", condition),
+                    ("then", then_branch),
+                    ("else", else_branch),
+                ],
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn program_json(source: &str) -> String {
+        let tokens = Scanner::new(source.to_string()).tokens().unwrap();
+        to_json(&Parser::new(tokens).parse().unwrap())
+    }
+
+    #[test]
+    fn binary_expression_statement() {
+        assert_eq!(
+            program_json("1 + 2;"),
+            concat!(
+                r#"[{"kind":"Expression","location":[1,3],"expression":"#,
+                r#"{"kind":"Binary","location":[1,3],"#,
+                r#""left":{"kind":"Literal","location":[1,1],"value":1},"#,
+                r#""operator":"Add","#,
+                r#""right":{"kind":"Literal","location":[1,5],"value":2}}}]"#,
+            )
+        );
+    }
+
+    #[test]
+    fn strings_are_escaped_and_unknown_locations_are_null() {
+        assert_eq!(
+            program_json(r#"{} print "a\"\n";"#),
+            concat!(
+                r#"[{"kind":"Block","location":null,"statements":[]},"#,
+                r#"{"kind":"Print","location":[1,10],"expression":"#,
+                r#"{"kind":"Literal","location":[1,10],"value":"a\"\n"}}]"#,
+            )
+        );
+    }
+}
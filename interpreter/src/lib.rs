@@ -0,0 +1,14 @@
+//! The tree-walking Lox interpreter, usable as a library by embedders and benchmarks
+
+pub mod ast;
+pub mod cache;
+pub mod environment;
+pub mod error;
+pub mod interpreter;
+pub mod json;
+pub mod optimizer;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+pub mod token;
+pub mod value;
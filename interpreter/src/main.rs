@@ -1,115 +1,467 @@
 use std::{
     env::args,
-    io::{stdin, BufRead, Write},
-    iter::repeat,
+    fmt::Display,
+    io::{stderr, stdin, BufRead, IsTerminal, Write},
 };
 
 use anyhow::{Context, Result};
-use error::LoxError;
-use interpreter::Interpreter;
-
-mod ast;
-mod environment;
-mod error;
-mod interpreter;
-mod parser;
-mod resolver;
-mod scanner;
-mod token;
-mod value;
+use rlox::{
+    ast::Statement,
+    cache,
+    error::{Located, LoxError, ParseError, ParseErrorKind},
+    interpreter::Interpreter,
+    json, optimizer, parser, resolver, scanner,
+    token::{Symbol, Token},
+};
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum Color {
+    /// Only when stderr is a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Default)]
+struct Options {
+    /// Print the line of every statement as it's executed
+    trace: bool,
+    /// Turn on all of the opt-in correctness checks:
+    /// - using a global that's never defined (resolve time)
+    /// - an assignment as the condition of an `if` or `while` (resolve time)
+    /// - a local variable that's never read (resolve time)
+    /// - division by zero (runtime)
+    /// - ordering comparisons with NaN (runtime)
+    strict: bool,
+    /// Make `print` a native function instead of a statement
+    print_as_function: bool,
+    /// Define natives that interact with the outside world, like `sleep`
+    allow_io: bool,
+    /// Skip assert statements entirely
+    no_assertions: bool,
+    /// Whether errors are colored
+    color: Color,
+    /// Save the resolved program next to the script, and reuse it while the script is unchanged.
+    /// Ignored together with the flags that change how a script is resolved.
+    cache: bool,
+    /// Print the variables every function captures from outside of it
+    dump_captures: bool,
+    /// Roughly how many bytes of strings, lists and maps a program may allocate in total
+    memory_limit: Option<usize>,
+    /// Print the parsed program as JSON instead of running it
+    ast_json: bool,
+    /// Warn about `if`, `else`, `for` and `while` bodies that aren't in braces
+    lint_braces: bool,
+    /// Parse `a, b` as the comma operator instead of `(a, b)` being a tuple
+    comma_operator: bool,
+    /// Warn about lines whose indentation mixes tabs and spaces
+    lint_indent: bool,
+    /// How many columns a tab advances to in error locations, 1 if not given
+    tab_width: Option<usize>,
+}
+
+impl Options {
+    fn tab_width(&self) -> usize {
+        self.tab_width.unwrap_or(1)
+    }
+
+    fn use_color(&self) -> bool {
+        match self.color {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => std::env::var_os("NO_COLOR").is_none() && stderr().is_terminal(),
+        }
+    }
+}
 
 fn main() -> Result<()> {
-    anyhow::ensure!(args().len() <= 2, "Too many arguments given");
+    let mut options = Options::default();
+    let mut filename = None;
+    let mut eval = None;
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => options.trace = true,
+            "--strict" => options.strict = true,
+            "--print-as-function" => options.print_as_function = true,
+            "--allow-io" => options.allow_io = true,
+            "--no-assertions" => options.no_assertions = true,
+            "--cache" => options.cache = true,
+            "--dump-captures" => options.dump_captures = true,
+            "--ast-json" => options.ast_json = true,
+            "--lint-braces" => options.lint_braces = true,
+            "--comma-operator" => options.comma_operator = true,
+            "--lint-indent" => options.lint_indent = true,
+            "--color" => {
+                options.color = match args.next().as_deref() {
+                    Some("auto") => Color::Auto,
+                    Some("always") => Color::Always,
+                    Some("never") => Color::Never,
+                    _ => anyhow::bail!("`--color` expects `auto`, `always` or `never`"),
+                }
+            }
+            "--tab-width" => {
+                let width = args.next().and_then(|width| width.parse().ok());
+                let width = width.filter(|&width| width > 0);
+                options.tab_width = Some(width.context("`--tab-width` expects a positive number")?);
+            }
+            "--memory-limit" => {
+                let limit = args.next().and_then(|limit| limit.parse().ok());
+                options.memory_limit =
+                    Some(limit.context("`--memory-limit` expects a number of bytes")?);
+            }
+            "--eval" => {
+                anyhow::ensure!(eval.is_none(), "`--eval` can only be given once");
+                eval = Some(args.next().context("`--eval` expects the code to run")?);
+            }
+            flag if flag.starts_with("--") => anyhow::bail!("Unknown flag `{flag}`"),
+            _ => {
+                anyhow::ensure!(filename.is_none(), "Too many arguments given");
+                filename = Some(arg);
+            }
+        }
+    }
 
-    match args().nth(1) {
-        Some(filename) => run_file(filename),
-        None => run_prompt(),
+    match (filename, eval) {
+        (Some(_), Some(_)) => anyhow::bail!("`--eval` can't be used together with a file"),
+        (Some(filename), None) => run_file(filename, &options),
+        (None, Some(source)) => {
+            run_source(source, None, &options);
+            Ok(())
+        }
+        (None, None) => run_prompt(&options),
     }?;
 
     Ok(())
 }
 
-fn run_prompt() -> Result<()> {
-    let mut interpreter = interpreter::Interpreter::new();
+fn make_interpreter(options: &Options) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    if options.trace {
+        interpreter.set_trace(Box::new(|(line, _)| eprintln!("[trace] line {line}")));
+    }
+    if options.allow_io {
+        interpreter.define_io_natives();
+    }
+    interpreter.skip_assertions = options.no_assertions;
+    interpreter.memory_limit = options.memory_limit;
+    interpreter.check_division_by_zero = options.strict;
+    interpreter.check_nan_comparison = options.strict;
+    interpreter
+}
 
-    let stdin = stdin();
-    let stdin = stdin.lock();
-    print!("> ");
-    std::io::stdout().flush().unwrap();
-    for line in stdin.lines() {
-        if let Ok(line) = line {
-            if let Err(errs) = run(line, &mut interpreter, true) {
-                for err in errs {
-                    eprintln!("{}", err);
-                }
-            };
-            print!("> ");
-            std::io::stdout().flush().unwrap();
-        } else {
-            println!("End of input. Goodbye!");
-            break;
-        }
+fn make_resolver(options: &Options, interpreter: &Interpreter) -> resolver::Resolver {
+    let mut resolver = resolver::Resolver::new();
+    resolver.define_globals(interpreter.global_names());
+    resolver.check_undefined_globals = options.strict;
+    resolver.check_assignment_in_condition = options.strict;
+    resolver.check_unused_variables = options.strict;
+    resolver
+}
+
+fn run_prompt(options: &Options) -> Result<()> {
+    let mut interpreter = make_interpreter(options);
+    // A failed line shouldn't leave half of its changes behind
+    interpreter.rollback_failed_statements = true;
+    repl(
+        stdin().lock(),
+        &mut std::io::stdout(),
+        &mut interpreter,
+        options,
+    )
+}
+
+/// Runs every line of the input, writing the prompts to the output. Reaching the end of the
+/// input says goodbye, failing to read from it is an error.
+fn repl(
+    input: impl BufRead,
+    output: &mut impl Write,
+    interpreter: &mut Interpreter,
+    options: &Options,
+) -> Result<()> {
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line.context("read from stdin")?;
+        if line.trim() == ":env" {
+            write!(output, "{}", interpreter.environment.borrow().dump())?;
+        } else if let Err(errs) = run(line, interpreter, options, true, None) {
+            for err in errs {
+                eprintln!("{}", format_error(&err, options.use_color()));
+            }
+        };
+        write!(output, "> ")?;
+        output.flush()?;
     }
+    writeln!(output, "End of input. Goodbye!")?;
+    Ok(())
+}
+
+fn run_file(filename: String, options: &Options) -> Result<()> {
+    let source = std::fs::read_to_string(&filename).context("read source file")?;
+    // A cached script isn't parsed again, so it wouldn't be linted either
+    let cache_path = (options.cache
+        && !options.strict
+        && !options.print_as_function
+        && !options.ast_json
+        && !options.dump_captures
+        && !options.lint_braces
+        && !options.comma_operator
+        && !options.lint_indent
+        && options.tab_width.is_none())
+    .then(|| format!("{filename}.cache"));
+    run_source(source, cache_path, options);
     Ok(())
 }
 
-fn run_file(filename: String) -> Result<()> {
-    let mut interpreter = interpreter::Interpreter::new();
-    let source = std::fs::read_to_string(filename).context("read source file")?;
-    if let Err(errs) = run(source.clone(), &mut interpreter, false) {
+/// Runs a whole program, reporting any errors along with the line they happened on
+fn run_source(source: String, cache_path: Option<String>, options: &Options) {
+    let mut interpreter = make_interpreter(options);
+    let cached = cache_path
+        .as_ref()
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|bytes| cache::from_bytes(&source, &bytes));
+    let result = match cached {
+        Some(program) => interpreter
+            .interpret(program)
+            .map(|_| ())
+            .map_err(|e| vec![e.into()]),
+        None => run(
+            source.clone(),
+            &mut interpreter,
+            options,
+            false,
+            cache_path.as_deref(),
+        ),
+    };
+    if let Err(errs) = result {
+        let color = options.use_color();
         for err in errs {
-            eprintln!("{}", err);
-            if let Some((line, col)) = err.location() {
-                let line_text = source
-                    .split('\n')
-                    .nth(line - 1)
-                    .expect("find error line in source code");
-                eprintln!("{line_text}");
-                let padding = repeat(' ').take(col - 1).collect::<String>();
-                eprintln!("{padding}^");
+            eprintln!("{}", format_error(&err, color));
+            // Some errors don't know where they happened and report (0, 0)
+            let line_text = err.location().and_then(|(line, col)| {
+                Some((source_lines(&source).nth(line.checked_sub(1)?)?, col))
+            });
+            if let Some((line_text, col)) = line_text {
+                // Tabs are expanded the same way the scanner counted them, so the caret lines up
+                eprintln!("{}", expand_tabs(line_text, options.tab_width()));
+                let padding = " ".repeat(col.saturating_sub(1));
+                if color {
+                    eprintln!("{padding}{RED}^{RESET}");
+                } else {
+                    eprintln!("{padding}^");
+                }
             }
         }
     }
-    Ok(())
+}
+
+fn format_error(err: &LoxError, color: bool) -> String {
+    if !color {
+        return err.to_string();
+    }
+    match err.location() {
+        Some((line, col)) => format!(
+            "{BOLD}[{line}:{col}]{RESET} {RED}Error:{RESET} {}",
+            err.message()
+        ),
+        None => err.to_string(),
+    }
+}
+
+fn format_warning(warning: &Located<impl Display>, color: bool) -> String {
+    let (line, col) = warning.location;
+    match color {
+        true => format!(
+            "{BOLD}[{line}:{col}]{RESET} {YELLOW}Warning:{RESET} {}",
+            warning.value
+        ),
+        false => format!("[{line}:{col}] Warning: {}", warning.value),
+    }
+}
+
+/// Replaces every tab with spaces up to the next tab stop
+fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let stop = scanner::next_tab_stop(column, tab_width);
+            expanded.extend(std::iter::repeat_n(' ', stop - column));
+            column = stop;
+        } else {
+            expanded.push(c);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+/// Splits on the same line breaks the scanner counts: `\n`, `\r\n` and a lone `\r`
+fn source_lines(source: &str) -> impl Iterator<Item = &str> {
+    source
+        .split('\n')
+        .flat_map(|line| line.strip_suffix('\r').unwrap_or(line).split('\r'))
 }
 
 fn run(
     source: String,
     interpreter: &mut Interpreter,
+    options: &Options,
     allow_single_expression: bool,
+    cache_path: Option<&str>,
 ) -> Result<(), Vec<LoxError>> {
-    let mut scanner = scanner::Scanner::new(source);
-    let tokens = scanner
-        .tokens()
-        .map_err(|e| e.into_iter().map(Into::into).collect::<Vec<LoxError>>())?;
+    let mut scanner = scanner::Scanner::new(source.clone());
+    scanner.print_as_function = options.print_as_function;
+    scanner.tab_width = options.tab_width();
+    scanner.lint_indent = options.lint_indent;
+    let (tokens, warnings) = scanner.tokens_with_warnings();
+    for warning in warnings {
+        eprintln!("{}", format_warning(&warning, options.use_color()));
+    }
+    let tokens = tokens.map_err(|e| e.into_iter().map(Into::into).collect::<Vec<LoxError>>())?;
 
-    if allow_single_expression {
-        // Try to parse as a single expression
-        let parser = parser::Parser::new(tokens.clone());
-        let expr = parser
-            .parse_expression()
-            .map_err(|e| e.into_iter().map(Into::into).collect::<Vec<LoxError>>());
-        if let Ok(expr) = expr {
-            let mut resolver = resolver::Resolver::new();
-            let expr = resolver
-                .resolve_expr(expr)
-                .map_err(|e| e.into_iter().map(Into::into).collect::<Vec<LoxError>>())?;
-            let value = interpreter.evaluate(expr).map_err(|e| vec![e.into()])?;
-            println!("{}", value);
-            return Ok(());
+    // Keep the tokens around in case this turns out to be a bare expression rather than a program
+    let expression_tokens = allow_single_expression.then(|| tokens.clone());
+    // Whether this is a bare expression whose value should be printed
+    let mut parser = parser::Parser::new(tokens);
+    parser.lint_braces = options.lint_braces;
+    parser.comma_operator = options.comma_operator;
+    let (result, warnings) = parser.parse_with_warnings();
+    for warning in warnings {
+        eprintln!("{}", format_warning(&warning, options.use_color()));
+    }
+    let (ast, print_value) = match result {
+        Ok(ast) => (ast, false),
+        Err(errors) => {
+            // A program that's only missing its final `;` might be an expression to print instead,
+            // any other error is reported as is
+            let expr = expression_tokens
+                .filter(|_| missing_final_semicolon(&errors))
+                .and_then(|tokens| {
+                    let mut parser = parser::Parser::new(tokens);
+                    parser.comma_operator = options.comma_operator;
+                    parser.parse_expression().ok()
+                });
+            match expr {
+                Some(expr) => (vec![Statement::Expression(expr)], true),
+                None => return Err(errors.into_iter().map(Into::into).collect()),
+            }
         }
-        // Try to parse as a program now
+    };
+    if options.ast_json {
+        println!("{}", json::to_json(&ast));
+        return Ok(());
     }
-
-    let parser = parser::Parser::new(tokens);
-    let ast = parser
-        .parse()
-        .map_err(|e| e.into_iter().map(Into::into).collect::<Vec<LoxError>>())?;
-    let mut resolver = resolver::Resolver::new();
+    let mut resolver = make_resolver(options, interpreter);
     let ast = resolver
         .resolve(ast)
         .map_err(|e| e.into_iter().map(Into::into).collect::<Vec<LoxError>>())?;
+    if options.dump_captures {
+        for (function, captures) in &resolver.captures {
+            let captures = match captures.is_empty() {
+                true => "(none)".to_string(),
+                false => captures.iter().cloned().collect::<Vec<_>>().join(", "),
+            };
+            eprintln!("[captures] {function}: {captures}");
+        }
+    }
+    let ast = optimizer::optimize(ast, interpreter.pure_natives());
+    if let Some(path) = cache_path {
+        // Failing to save the cache only means the next run resolves the script again
+        let _ = std::fs::write(path, cache::to_bytes(&source, &ast));
+    }
 
-    interpreter.interpret(ast).map_err(|e| vec![e.into()])?;
+    let value = interpreter.interpret(ast).map_err(|e| vec![e.into()])?;
+    if print_value {
+        println!("{value:#}");
+    }
     Ok(())
 }
+
+fn missing_final_semicolon(errors: &[ParseError]) -> bool {
+    matches!(
+        errors,
+        [ParseError {
+            value: ParseErrorKind::UnexpectedToken(Token::Eof, Token::Symbol(Symbol::Semicolon)),
+            ..
+        }]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the REPL wrote for the given input, or the error it stopped with
+    fn repl_output(input: &[u8]) -> Result<String> {
+        let mut output = Vec::new();
+        repl(
+            input,
+            &mut output,
+            &mut Interpreter::new(),
+            &Options::default(),
+        )?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn end_of_input_says_goodbye() {
+        assert_eq!(
+            repl_output(b"var a = 1;\nvar b = a;\n").unwrap(),
+            "> > > End of input. Goodbye!\n",
+        );
+    }
+
+    #[test]
+    fn read_error_is_reported() {
+        let error = repl_output(b"var a = 1;\n\xff\n").unwrap_err();
+        assert_eq!(error.to_string(), "read from stdin");
+        let cause = error.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(cause.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    /// Runs one REPL line, returning the messages of the errors it failed with
+    fn run_line(source: &str, interpreter: &mut Interpreter) -> Vec<String> {
+        run(
+            source.to_string(),
+            interpreter,
+            &Options::default(),
+            true,
+            None,
+        )
+        .err()
+        .unwrap_or_default()
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+    }
+
+    #[test]
+    fn bare_expression_is_evaluated() {
+        let mut interpreter = Interpreter::new();
+        assert!(run_line("var x = 1;", &mut interpreter).is_empty());
+        // An assignment as a bare expression still assigns
+        assert!(run_line("x = 5", &mut interpreter).is_empty());
+        assert!(run_line("assert x == 5;", &mut interpreter).is_empty());
+    }
+
+    #[test]
+    fn statement_missing_its_semicolon_reports_the_statement_error() {
+        let errors = run_line("var x = 1", &mut Interpreter::new());
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("`;`"), "{errors:?}");
+    }
+
+    #[test]
+    fn syntax_error_before_the_end_is_not_swallowed() {
+        let errors = run_line("1 + ) 2", &mut Interpreter::new());
+        assert_eq!(errors, ["[1:5] Error: invalid expression"]);
+    }
+}
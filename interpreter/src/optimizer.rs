@@ -0,0 +1,498 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{Expression, ResolvedCodeExpression, ResolvedStatement},
+    error::Located,
+    interpreter::Interpreter,
+    token::Literal,
+    value::{LoxCallable, Value},
+};
+
+/// Simplifies an already resolved program without changing its behavior.
+///
+/// Scoping is left untouched (blocks stay blocks), so the hops calculated by the resolver remain
+/// valid.
+///
+/// Calls to `pure_natives` (see Interpreter::pure_natives) with literal arguments are done ahead
+/// of time, unless the program itself declares or assigns a global of the same name.
+pub fn optimize(
+    program: Vec<ResolvedStatement>,
+    pure_natives: BTreeMap<String, LoxCallable>,
+) -> Vec<ResolvedStatement> {
+    let mut rebound = BTreeSet::new();
+    for statement in &program {
+        match statement {
+            ResolvedStatement::Var(name, _)
+            | ResolvedStatement::Function(name, ..)
+            | ResolvedStatement::Class(name, ..) => {
+                rebound.insert(name.value.clone());
+            }
+            ResolvedStatement::VarTuple(names, _) => rebound.extend(names.iter().cloned()),
+            _ => {}
+        }
+        assigned_globals_in_statement(statement, &mut rebound);
+    }
+    let mut optimizer = Optimizer {
+        pure_natives: pure_natives
+            .into_iter()
+            .filter(|(name, _)| !rebound.contains(name))
+            .collect(),
+        interpreter: Interpreter::new(),
+    };
+    optimizer.optimize(program)
+}
+
+struct Optimizer {
+    pure_natives: BTreeMap<String, LoxCallable>,
+    /// Only there for the natives to be called with
+    interpreter: Interpreter,
+}
+
+impl Optimizer {
+    fn optimize(&mut self, program: Vec<ResolvedStatement>) -> Vec<ResolvedStatement> {
+        program
+            .into_iter()
+            .filter_map(|s| self.optimize_statement(s))
+            .collect()
+    }
+
+    /// Returns none if the statement can be removed entirely
+    fn optimize_statement(&mut self, statement: ResolvedStatement) -> Option<ResolvedStatement> {
+        Some(match statement {
+            ResolvedStatement::Block(b) => ResolvedStatement::Block(self.optimize(b)),
+            ResolvedStatement::LabeledBlock(l, b) => {
+                ResolvedStatement::LabeledBlock(l, self.optimize(b))
+            }
+            b @ (ResolvedStatement::Break(_) | ResolvedStatement::Continue(_)) => b,
+            ResolvedStatement::TryCatch(body, name, handler) => {
+                ResolvedStatement::TryCatch(self.optimize(body), name, self.optimize(handler))
+            }
+            ResolvedStatement::Function(name, params, body, generator, captures) => {
+                ResolvedStatement::Function(
+                    name,
+                    params,
+                    Rc::new(self.optimize(Rc::unwrap_or_clone(body))),
+                    generator,
+                    captures,
+                )
+            }
+            ResolvedStatement::Class(name, superclass, methods, captures) => {
+                ResolvedStatement::Class(
+                    name,
+                    superclass.map(|s| self.optimize_expression(s)),
+                    self.optimize(methods),
+                    captures,
+                )
+            }
+            // Dead branch elimination. A branch runs in the same environment as the `if` itself,
+            // so inlining it can't change which scope its declarations end up in.
+            ResolvedStatement::If(condition, then_branch, else_branch) => {
+                let condition = self.optimize_expression(condition);
+                match constant_boolean(&condition) {
+                    Some(true) => return self.optimize_statement(*then_branch),
+                    Some(false) => return else_branch.and_then(|e| self.optimize_statement(*e)),
+                    None => ResolvedStatement::If(
+                        condition,
+                        Box::new(self.optimize_body(*then_branch)),
+                        else_branch.map(|e| Box::new(self.optimize_body(*e))),
+                    ),
+                }
+            }
+            ResolvedStatement::While(condition, body, increment) => {
+                let condition = self.optimize_expression(condition);
+                match constant_boolean(&condition) {
+                    Some(false) => return None,
+                    _ => ResolvedStatement::While(
+                        condition,
+                        Box::new(self.optimize_body(*body)),
+                        increment.map(|i| self.optimize_expression(i)),
+                    ),
+                }
+            }
+            ResolvedStatement::DoWhile(body, condition) => ResolvedStatement::DoWhile(
+                Box::new(self.optimize_body(*body)),
+                self.optimize_expression(condition),
+            ),
+            ResolvedStatement::Foreach(name, iterable, body) => ResolvedStatement::Foreach(
+                name,
+                self.optimize_expression(iterable),
+                Rc::new(self.optimize_body(Rc::unwrap_or_clone(body))),
+            ),
+            ResolvedStatement::Yield(e) => ResolvedStatement::Yield(self.optimize_expression(e)),
+            ResolvedStatement::Expression(e) => {
+                ResolvedStatement::Expression(self.optimize_expression(e))
+            }
+            ResolvedStatement::Print(e) => ResolvedStatement::Print(self.optimize_expression(e)),
+            ResolvedStatement::Assert(condition, message) => ResolvedStatement::Assert(
+                self.optimize_expression(condition),
+                message.map(|m| self.optimize_expression(m)),
+            ),
+            ResolvedStatement::Return(Located { location, value }, tail) => {
+                ResolvedStatement::Return(
+                    Located {
+                        location,
+                        value: value.map(|e| self.optimize_expression(e)),
+                    },
+                    tail,
+                )
+            }
+            ResolvedStatement::Throw(e) => ResolvedStatement::Throw(self.optimize_expression(e)),
+            ResolvedStatement::Var(name, e) => {
+                ResolvedStatement::Var(name, e.map(|e| self.optimize_expression(e)))
+            }
+            ResolvedStatement::VarTuple(names, e) => {
+                ResolvedStatement::VarTuple(names, self.optimize_expression(e))
+            }
+        })
+    }
+
+    /// Like optimize_statement, but for places where a statement is required
+    fn optimize_body(&mut self, statement: ResolvedStatement) -> ResolvedStatement {
+        self.optimize_statement(statement)
+            .unwrap_or(ResolvedStatement::Block(Vec::new()))
+    }
+
+    fn optimize_expression(
+        &mut self,
+        expression: ResolvedCodeExpression,
+    ) -> ResolvedCodeExpression {
+        let location = expression.location;
+        let value = match expression.value {
+            Expression::Call(callee, args) => {
+                let args: Vec<_> = args
+                    .into_iter()
+                    .map(|a| self.optimize_expression(a))
+                    .collect();
+                if let Some(literal) = self.fold_call(&callee, &args) {
+                    Expression::Literal(literal)
+                } else {
+                    Expression::Call(Box::new(self.optimize_expression(*callee)), args)
+                }
+            }
+            Expression::Binary(left, operator, right) => Expression::Binary(
+                Box::new(self.optimize_expression(*left)),
+                operator,
+                Box::new(self.optimize_expression(*right)),
+            ),
+            Expression::Get(object, name) => {
+                Expression::Get(Box::new(self.optimize_expression(*object)), name)
+            }
+            Expression::OptionalGet(object, name) => {
+                Expression::OptionalGet(Box::new(self.optimize_expression(*object)), name)
+            }
+            Expression::Set(object, name, value) => Expression::Set(
+                Box::new(self.optimize_expression(*object)),
+                name,
+                Box::new(self.optimize_expression(*value)),
+            ),
+            Expression::Grouping(e) => Expression::Grouping(Box::new(self.optimize_expression(*e))),
+            Expression::Index(target, index) => Expression::Index(
+                Box::new(self.optimize_expression(*target)),
+                Box::new(self.optimize_expression(*index)),
+            ),
+            Expression::List(elements) => Expression::List(
+                elements
+                    .into_iter()
+                    .map(|e| self.optimize_expression(e))
+                    .collect(),
+            ),
+            Expression::Map(entries) => Expression::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (self.optimize_expression(k), self.optimize_expression(v)))
+                    .collect(),
+            ),
+            Expression::Sequence(expressions) => Expression::Sequence(
+                expressions
+                    .into_iter()
+                    .map(|e| self.optimize_expression(e))
+                    .collect(),
+            ),
+            Expression::Tuple(elements) => Expression::Tuple(
+                elements
+                    .into_iter()
+                    .map(|e| self.optimize_expression(e))
+                    .collect(),
+            ),
+            Expression::Unary(operator, e) => {
+                Expression::Unary(operator, Box::new(self.optimize_expression(*e)))
+            }
+            Expression::Lambda(params, body, captures) => Expression::Lambda(
+                params,
+                Rc::new(self.optimize(Rc::unwrap_or_clone(body))),
+                captures,
+            ),
+            Expression::Assign(variable, e) => {
+                Expression::Assign(variable, Box::new(self.optimize_expression(*e)))
+            }
+            e @ (Expression::Literal(_)
+            | Expression::Variable(_)
+            | Expression::This(_)
+            | Expression::Super(..)) => e,
+        };
+        ResolvedCodeExpression { location, value }
+    }
+
+    /// The result of a pure native call with literal arguments, if it has one that can be written
+    /// as a literal. Calls that fail are left for the interpreter to report.
+    fn fold_call(
+        &mut self,
+        callee: &ResolvedCodeExpression,
+        args: &[ResolvedCodeExpression],
+    ) -> Option<Literal> {
+        let Expression::Variable(variable) = &callee.value else {
+            return None;
+        };
+        if variable.hops.is_some() {
+            return None;
+        }
+        let native = self.pure_natives.get(&variable.name)?.clone();
+        let args = args
+            .iter()
+            .map(|a| match &a.value {
+                Expression::Literal(l) => Some(Value::from(l)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if native.arity().is_some_and(|arity| arity != args.len()) {
+            return None;
+        }
+        match native
+            .call(&mut self.interpreter, args, callee.location)
+            .ok()?
+        {
+            Value::String(s) => Some(Literal::String(s)),
+            Value::Number(n) => Some(Literal::Number(n)),
+            Value::Boolean(b) => Some(Literal::Boolean(b)),
+            Value::Nil => Some(Literal::Nil),
+            _ => None,
+        }
+    }
+}
+
+fn constant_boolean(expression: &ResolvedCodeExpression) -> Option<bool> {
+    match &expression.value {
+        Expression::Literal(Literal::Boolean(b)) => Some(*b),
+        Expression::Grouping(e) => constant_boolean(e),
+        _ => None,
+    }
+}
+
+/// Global names that are assigned to somewhere in the statement
+fn assigned_globals_in_statement(statement: &ResolvedStatement, names: &mut BTreeSet<String>) {
+    let mut expression = |e: &ResolvedCodeExpression| assigned_globals_in_expression(e, names);
+    match statement {
+        ResolvedStatement::Expression(e)
+        | ResolvedStatement::Print(e)
+        | ResolvedStatement::Throw(e)
+        | ResolvedStatement::Yield(e)
+        | ResolvedStatement::Var(_, Some(e))
+        | ResolvedStatement::VarTuple(_, e)
+        | ResolvedStatement::Return(Located { value: Some(e), .. }, _) => expression(e),
+        ResolvedStatement::Var(_, None)
+        | ResolvedStatement::Return(Located { value: None, .. }, _)
+        | ResolvedStatement::Break(_)
+        | ResolvedStatement::Continue(_) => {}
+        ResolvedStatement::Assert(condition, message) => {
+            expression(condition);
+            if let Some(m) = message {
+                expression(m);
+            }
+        }
+        ResolvedStatement::While(condition, body, increment) => {
+            expression(condition);
+            if let Some(i) = increment {
+                expression(i);
+            }
+            assigned_globals_in_statement(body, names);
+        }
+        ResolvedStatement::DoWhile(body, condition) => {
+            expression(condition);
+            assigned_globals_in_statement(body, names);
+        }
+        ResolvedStatement::Foreach(_, iterable, body) => {
+            expression(iterable);
+            assigned_globals_in_statement(body, names);
+        }
+        ResolvedStatement::If(condition, then_branch, else_branch) => {
+            expression(condition);
+            assigned_globals_in_statement(then_branch, names);
+            if let Some(e) = else_branch {
+                assigned_globals_in_statement(e, names);
+            }
+        }
+        ResolvedStatement::Block(b) | ResolvedStatement::LabeledBlock(_, b) => {
+            for s in b {
+                assigned_globals_in_statement(s, names);
+            }
+        }
+        ResolvedStatement::Class(_, superclass, methods, _) => {
+            if let Some(s) = superclass {
+                expression(s);
+            }
+            for s in methods {
+                assigned_globals_in_statement(s, names);
+            }
+        }
+        ResolvedStatement::Function(_, _, body, ..) => {
+            for s in body.iter() {
+                assigned_globals_in_statement(s, names);
+            }
+        }
+        ResolvedStatement::TryCatch(body, _, handler) => {
+            for s in body.iter().chain(handler) {
+                assigned_globals_in_statement(s, names);
+            }
+        }
+    }
+}
+
+fn assigned_globals_in_expression(
+    expression: &ResolvedCodeExpression,
+    names: &mut BTreeSet<String>,
+) {
+    match &expression.value {
+        Expression::Assign(variable, e) => {
+            if variable.hops.is_none() {
+                names.insert(variable.name.clone());
+            }
+            assigned_globals_in_expression(e, names);
+        }
+        Expression::Binary(a, _, b) | Expression::Index(a, b) | Expression::Set(a, _, b) => {
+            assigned_globals_in_expression(a, names);
+            assigned_globals_in_expression(b, names);
+        }
+        Expression::Call(callee, args) => {
+            assigned_globals_in_expression(callee, names);
+            for a in args {
+                assigned_globals_in_expression(a, names);
+            }
+        }
+        Expression::Get(e, _)
+        | Expression::OptionalGet(e, _)
+        | Expression::Grouping(e)
+        | Expression::Unary(_, e) => assigned_globals_in_expression(e, names),
+        Expression::List(elements)
+        | Expression::Sequence(elements)
+        | Expression::Tuple(elements) => {
+            for e in elements {
+                assigned_globals_in_expression(e, names);
+            }
+        }
+        Expression::Map(entries) => {
+            for (k, v) in entries {
+                assigned_globals_in_expression(k, names);
+                assigned_globals_in_expression(v, names);
+            }
+        }
+        Expression::Lambda(_, body, _) => {
+            for s in body.iter() {
+                assigned_globals_in_statement(s, names);
+            }
+        }
+        Expression::Literal(_)
+        | Expression::Variable(_)
+        | Expression::This(_)
+        | Expression::Super(..) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, resolver::Resolver, scanner::Scanner};
+
+    fn optimized(source: &str) -> Vec<ResolvedStatement> {
+        let interpreter = Interpreter::new();
+        let tokens = Scanner::new(source.to_string()).tokens().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        let mut resolver = Resolver::new();
+        resolver.define_globals(interpreter.global_names());
+        optimize(resolver.resolve(ast).unwrap(), interpreter.pure_natives())
+    }
+
+    /// The number printed by a `print <number>;` statement
+    fn printed_number(statement: &ResolvedStatement) -> f64 {
+        match statement {
+            ResolvedStatement::Print(ResolvedCodeExpression {
+                value: Expression::Literal(Literal::Number(n)),
+                ..
+            }) => *n,
+            s => panic!("expected a print of a number, got {s:?}"),
+        }
+    }
+
+    #[test]
+    fn if_true_keeps_only_then_branch() {
+        let program = optimized("if (true) print 1; else print 2;");
+        assert_eq!(program.len(), 1);
+        assert_eq!(printed_number(&program[0]), 1.0);
+    }
+
+    #[test]
+    fn if_true_without_else() {
+        let program = optimized("if (true) print 1;");
+        assert_eq!(program.len(), 1);
+        assert_eq!(printed_number(&program[0]), 1.0);
+    }
+
+    #[test]
+    fn if_false_keeps_only_else_branch() {
+        let program = optimized("if (false) print 1; else print 2;");
+        assert_eq!(program.len(), 1);
+        assert_eq!(printed_number(&program[0]), 2.0);
+    }
+
+    #[test]
+    fn if_false_without_else_is_removed() {
+        assert!(optimized("if (false) print 1;").is_empty());
+    }
+
+    #[test]
+    fn while_false_is_removed() {
+        assert!(optimized("while (false) print 1;").is_empty());
+    }
+
+    #[test]
+    fn unknown_condition_is_kept() {
+        let program = optimized("var x = clock(); if (x) print 1; while (x) print 2;");
+        assert!(matches!(program[1], ResolvedStatement::If(..)));
+        assert!(matches!(program[2], ResolvedStatement::While(..)));
+    }
+
+    #[test]
+    fn pure_native_calls_with_literals_are_folded() {
+        let program = optimized("print pow(2, 10); print gcd(12, 18);");
+        assert_eq!(printed_number(&program[0]), 1024.0);
+        assert_eq!(printed_number(&program[1]), 6.0);
+    }
+
+    #[test]
+    fn impure_and_non_literal_calls_are_kept() {
+        let program = optimized("print clock(); var x = 2; print pow(x, 10);");
+        for statement in [&program[0], &program[2]] {
+            assert!(matches!(
+                statement,
+                ResolvedStatement::Print(ResolvedCodeExpression {
+                    value: Expression::Call(..),
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn redefined_natives_are_not_folded() {
+        let program = optimized("fun pow(a, b) { return 0; } print pow(2, 3);");
+        assert!(matches!(
+            program[1],
+            ResolvedStatement::Print(ResolvedCodeExpression {
+                value: Expression::Call(..),
+                ..
+            })
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use crate::{
     ast::{BinaryOperator, CodeExpression, Expression, Statement, UnaryOperator},
     error::{Located, ParseError, ParseErrorKind},
@@ -10,6 +12,13 @@ pub struct Parser {
     current: usize,
     tokens: Vec<CodeToken>,
     errors: Vec<ParseError>,
+    /// Problems that don't stop the program from running
+    warnings: Vec<ParseError>,
+    /// Warn about `if`, `else`, `for` and `while` bodies that aren't in braces
+    pub lint_braces: bool,
+    /// Parse `a, b` as the comma operator, which results in `b`. Without it commas in parentheses
+    /// make a tuple instead.
+    pub comma_operator: bool,
 }
 
 const MAX_FUNCTION_ARGS: usize = 255;
@@ -20,21 +29,32 @@ impl Parser {
             current: 0,
             tokens,
             errors: Vec::new(),
+            warnings: Vec::new(),
+            lint_braces: false,
+            comma_operator: false,
         }
     }
 
-    pub fn parse(mut self) -> Result<Vec<Statement>, Vec<ParseError>> {
+    pub fn parse(self) -> Result<Vec<Statement>, Vec<ParseError>> {
+        self.parse_with_warnings().0
+    }
+
+    /// Like parse, but also returns the warnings, which are there even when parsing fails
+    pub fn parse_with_warnings(
+        mut self,
+    ) -> (Result<Vec<Statement>, Vec<ParseError>>, Vec<ParseError>) {
         let mut statements = Vec::new();
         while !self.is_at_end() {
             if let Some(d) = self.declaration() {
                 statements.push(d);
             }
         }
-        if self.errors.is_empty() {
+        let result = if self.errors.is_empty() {
             Ok(statements)
         } else {
             Err(self.errors)
-        }
+        };
+        (result, self.warnings)
     }
 
     pub fn parse_expression(mut self) -> Result<CodeExpression, Vec<ParseError>> {
@@ -51,14 +71,40 @@ impl Parser {
         }
     }
 
+    /// Parses exactly one declaration or statement, for embedders that feed code piece by piece
+    pub fn parse_statement(mut self) -> Result<Statement, Vec<ParseError>> {
+        let statement = self.declaration();
+        match statement {
+            Some(statement) if self.errors.is_empty() => {
+                if self.is_at_end() {
+                    Ok(statement)
+                } else {
+                    let actual = self.peek();
+                    Err(vec![Located {
+                        location: actual.location,
+                        value: ParseErrorKind::UnexpectedToken(actual.token, Token::Eof),
+                    }])
+                }
+            }
+            _ => Err(self.errors),
+        }
+    }
+
     // Non terminal rules
 
     /// Returns none if there was a parsing error and a synchronization has been run
     fn declaration(&mut self) -> Option<Statement> {
         let statement = if self.matches(Token::Keyword(Keyword::Var)) {
             self.var_declaration()
-        } else if self.matches(Token::Keyword(Keyword::Fun)) {
-            self.function("function")
+        } else if self.check(Token::Keyword(Keyword::Fun))
+            // `fun (` is an anonymous function, which starts an expression statement instead
+            && !self.check_next(Token::Symbol(Symbol::LeftParen))
+        {
+            let fun = self.advance();
+            let generator = self.matches(Token::Symbol(Symbol::Star));
+            self.function(Some(fun), generator)
+        } else if self.matches(Token::Keyword(Keyword::Class)) {
+            self.class_declaration()
         } else {
             self.statement()
         };
@@ -74,7 +120,12 @@ impl Parser {
     }
 
     fn var_declaration(&mut self) -> ParseResult<Statement> {
-        let name = self.consume_identifier()?;
+        // Keyword::Var token is already consumed
+        let location = self.previous().location;
+        if self.matches(Token::Symbol(Symbol::LeftParen)) {
+            return self.var_tuple_declaration();
+        }
+        let name = self.consume_identifier()?.value;
 
         let initializer = if self.matches(Token::Symbol(Symbol::Equal)) {
             Some(self.expression()?)
@@ -83,11 +134,76 @@ impl Parser {
         };
 
         self.consume(Token::Symbol(Symbol::Semicolon))?;
-        Ok(Statement::Var(name.value, initializer))
+        Ok(Statement::Var(
+            Located {
+                location,
+                value: name,
+            },
+            initializer,
+        ))
     }
 
-    fn function(&mut self, _kind: &'static str) -> ParseResult<Statement> {
-        let name = self.consume_identifier()?;
+    /// `var (a, b) = value;`, after the `(`
+    fn var_tuple_declaration(&mut self) -> ParseResult<Statement> {
+        let mut names = vec![self.consume_identifier()?.value];
+        while self.matches(Token::Symbol(Symbol::Comma)) {
+            names.push(self.consume_identifier()?.value);
+        }
+        self.consume(Token::Symbol(Symbol::RightParen))?;
+        self.consume(Token::Symbol(Symbol::Equal))?;
+        let value = self.expression()?;
+        self.consume(Token::Symbol(Symbol::Semicolon))?;
+        Ok(Statement::VarTuple(names, value))
+    }
+
+    fn class_declaration(&mut self) -> ParseResult<Statement> {
+        // Keyword::Class token is already consumed
+        let location = self.previous().location;
+        let name = self.consume_identifier()?.value;
+        let superclass = if self.matches(Token::Symbol(Symbol::Less)) {
+            let superclass = self.consume_identifier()?;
+            Some(CodeExpression {
+                location: superclass.location,
+                value: Expression::Variable(superclass.value),
+            })
+        } else {
+            None
+        };
+        self.consume(Token::Symbol(Symbol::LeftBrace))?;
+        let mut methods = Vec::new();
+        while !self.check(Token::Symbol(Symbol::RightBrace)) && !self.is_at_end() {
+            methods.push(self.function(None, false)?);
+        }
+        self.consume(Token::Symbol(Symbol::RightBrace))?;
+        Ok(Statement::Class(
+            Located {
+                location,
+                value: name,
+            },
+            superclass,
+            methods,
+            Vec::new(),
+        ))
+    }
+
+    /// A function declaration after its `fun` keyword, or a method, which doesn't have one
+    fn function(&mut self, fun: Option<CodeToken>, generator: bool) -> ParseResult<Statement> {
+        let mut name = self.consume_identifier()?;
+        if let Some(fun) = fun {
+            name.location = fun.location;
+        }
+        let (params, body) = self.parameters_and_body()?;
+        Ok(Statement::Function(
+            name,
+            params,
+            Rc::new(body),
+            generator,
+            Vec::new(),
+        ))
+    }
+
+    /// `(a, b) { ... }`, the part of a function after its name
+    fn parameters_and_body(&mut self) -> ParseResult<(Vec<String>, Vec<Statement>)> {
         let left_paren = self.consume(Token::Symbol(Symbol::LeftParen))?;
         let mut params = Vec::new();
         if !self.check(Token::Symbol(Symbol::RightParen)) {
@@ -108,7 +224,7 @@ impl Parser {
         self.consume(Token::Symbol(Symbol::LeftBrace))?;
         let body = self.block()?;
 
-        Ok(Statement::Function(name.value, params, body))
+        Ok((params, body))
     }
 
     fn statement(&mut self) -> ParseResult<Statement> {
@@ -118,12 +234,41 @@ impl Parser {
             self.for_statement()
         } else if self.matches(Token::Keyword(Keyword::While)) {
             self.while_statement()
+        } else if self.matches(Token::Keyword(Keyword::Foreach)) {
+            self.foreach_statement()
+        } else if self.matches(Token::Keyword(Keyword::Do)) {
+            self.do_while_statement()
+        } else if self.matches(Token::Keyword(Keyword::Try)) {
+            self.try_statement()
+        } else if self.matches(Token::Keyword(Keyword::Throw)) {
+            self.throw_statement()
         } else if self.matches(Token::Keyword(Keyword::Return)) {
             self.return_statement()
+        } else if self.matches(Token::Keyword(Keyword::Break)) {
+            self.break_statement()
+        } else if self.matches(Token::Keyword(Keyword::Continue)) {
+            // Keyword::Continue token is already consumed
+            let location = self.previous().location;
+            self.consume(Token::Symbol(Symbol::Semicolon))?;
+            Ok(Statement::Continue(location))
+        } else if self.matches(Token::Keyword(Keyword::Yield)) {
+            // Keyword::Yield token is already consumed
+            let value = self.expression()?;
+            self.consume(Token::Symbol(Symbol::Semicolon))?;
+            Ok(Statement::Yield(value))
         } else if self.matches(Token::Keyword(Keyword::Print)) {
             self.print_statement()
+        } else if self.matches(Token::Keyword(Keyword::Assert)) {
+            self.assert_statement()
         } else if self.matches(Token::Symbol(Symbol::LeftBrace)) {
             self.block().map(Statement::Block)
+        } else if matches!(self.peek().token, Token::Identifier(_))
+            && self.check_next(Token::Symbol(Symbol::Colon))
+        {
+            self.labeled_block()
+        } else if self.matches(Token::Symbol(Symbol::Semicolon)) {
+            // An empty statement does nothing, same as an empty block
+            Ok(Statement::Block(Vec::new()))
         } else {
             self.expression_statement()
         }
@@ -134,9 +279,9 @@ impl Parser {
         let condition = self.expression()?;
         self.consume(Token::Symbol(Symbol::RightParen))?;
 
-        let then_branch = self.statement()?;
+        let then_branch = self.body(Keyword::If)?;
         let else_branch = if self.matches(Token::Keyword(Keyword::Else)) {
-            Some(self.statement()?)
+            Some(self.body(Keyword::Else)?)
         } else {
             None
         };
@@ -178,13 +323,8 @@ impl Parser {
         };
         self.consume(Token::Symbol(Symbol::RightParen))?;
 
-        let mut body = self.statement()?;
-
-        if let Some(increment) = increment {
-            body = Statement::Block(vec![body, Statement::Expression(increment)]);
-        }
-
-        body = Statement::While(condition, Box::new(body));
+        let body = self.body(Keyword::For)?;
+        let mut body = Statement::While(condition, Box::new(body), increment);
 
         if let Some(initializer) = initializer {
             body = Statement::Block(vec![initializer, body]);
@@ -197,20 +337,140 @@ impl Parser {
         self.consume(Token::Symbol(Symbol::LeftParen))?;
         let condition = self.expression()?;
         self.consume(Token::Symbol(Symbol::RightParen))?;
+        let body = self.body(Keyword::While)?;
+
+        Ok(Statement::While(condition, Box::new(body), None))
+    }
+
+    fn foreach_statement(&mut self) -> ParseResult<Statement> {
+        // Keyword::Foreach token is already consumed
+        self.consume(Token::Symbol(Symbol::LeftParen))?;
+        let name = self.consume_identifier()?;
+        self.consume(Token::Keyword(Keyword::In))?;
+        let iterable = self.expression()?;
+        self.consume(Token::Symbol(Symbol::RightParen))?;
+        let body = self.body(Keyword::Foreach)?;
+
+        Ok(Statement::Foreach(name.value, iterable, Rc::new(body)))
+    }
+
+    /// The body of `keyword`, which is a single statement that may or may not be a block
+    fn body(&mut self, keyword: Keyword) -> ParseResult<Statement> {
+        let start = self.peek();
+        self.reject_declaration(keyword.clone())?;
+        let body = self.statement()?;
+        let braced = match start.token {
+            Token::Symbol(Symbol::LeftBrace) => true,
+            // `else if` is how an if chain is written, not a missing brace
+            Token::Keyword(Keyword::If) => keyword == Keyword::Else,
+            _ => false,
+        };
+        if self.lint_braces && !braced {
+            self.warnings.push(Located {
+                location: start.location,
+                value: ParseErrorKind::UnbracedBody(keyword),
+            });
+        }
+        Ok(body)
+    }
+
+    /// Errors if a declaration comes next, where only a statement is allowed
+    fn reject_declaration(&mut self, keyword: Keyword) -> ParseResult<()> {
+        let declaration = match self.peek().token {
+            Token::Keyword(Keyword::Var | Keyword::Class) => true,
+            Token::Keyword(Keyword::Fun) => !self.check_next(Token::Symbol(Symbol::LeftParen)),
+            _ => false,
+        };
+        if declaration {
+            return Err(Located {
+                location: self.peek().location,
+                value: ParseErrorKind::DeclarationNotAllowed(keyword),
+            });
+        }
+        Ok(())
+    }
+
+    fn do_while_statement(&mut self) -> ParseResult<Statement> {
+        // Keyword::Do token is already consumed
+        self.reject_declaration(Keyword::Do)?;
         let body = self.statement()?;
+        self.consume(Token::Keyword(Keyword::While))?;
+        self.consume(Token::Symbol(Symbol::LeftParen))?;
+        let condition = self.expression()?;
+        self.consume(Token::Symbol(Symbol::RightParen))?;
+        self.consume(Token::Symbol(Symbol::Semicolon))?;
 
-        Ok(Statement::While(condition, Box::new(body)))
+        Ok(Statement::DoWhile(Box::new(body), condition))
+    }
+
+    fn try_statement(&mut self) -> ParseResult<Statement> {
+        // Keyword::Try token is already consumed
+        self.consume(Token::Symbol(Symbol::LeftBrace))?;
+        let body = self.block()?;
+        self.consume(Token::Keyword(Keyword::Catch))?;
+        self.consume(Token::Symbol(Symbol::LeftParen))?;
+        let name = self.consume_identifier()?;
+        self.consume(Token::Symbol(Symbol::RightParen))?;
+        self.consume(Token::Symbol(Symbol::LeftBrace))?;
+        let handler = self.block()?;
+
+        Ok(Statement::TryCatch(body, name.value, handler))
+    }
+
+    fn throw_statement(&mut self) -> ParseResult<Statement> {
+        // Keyword::Throw token is already consumed
+        let value = self.expression()?;
+        self.consume(Token::Symbol(Symbol::Semicolon))?;
+        Ok(Statement::Throw(value))
     }
 
     fn return_statement(&mut self) -> ParseResult<Statement> {
+        // Keyword::Return token is already consumed
+        let location = self.previous().location;
         let value = if self.check(Token::Symbol(Symbol::Semicolon)) {
             None
         } else {
-            Some(self.expression()?)
+            // `return a, b;` returns a tuple rather than using the comma operator
+            let first = self.assignment()?;
+            if self.check(Token::Symbol(Symbol::Comma)) {
+                let location = first.location;
+                let mut elements = vec![first];
+                while self.matches(Token::Symbol(Symbol::Comma)) {
+                    elements.push(self.assignment()?);
+                }
+                Some(CodeExpression {
+                    location,
+                    value: Expression::Tuple(elements),
+                })
+            } else {
+                Some(first)
+            }
         };
 
         self.consume(Token::Symbol(Symbol::Semicolon))?;
-        Ok(Statement::Return(value))
+        Ok(Statement::Return(Located { location, value }, false))
+    }
+
+    fn break_statement(&mut self) -> ParseResult<Statement> {
+        // Keyword::Break token is already consumed
+        let location = self.previous().location;
+        let label = match self.peek().token {
+            Token::Identifier(_) => Some(self.consume_identifier()?.value),
+            _ => None,
+        };
+        self.consume(Token::Symbol(Symbol::Semicolon))?;
+        Ok(Statement::Break(Located {
+            location,
+            value: label,
+        }))
+    }
+
+    /// `label: { ... }`
+    fn labeled_block(&mut self) -> ParseResult<Statement> {
+        let label = self.consume_identifier()?;
+        self.consume(Token::Symbol(Symbol::Colon))?;
+        self.consume(Token::Symbol(Symbol::LeftBrace))?;
+        Ok(Statement::LabeledBlock(label.value, self.block()?))
     }
 
     fn print_statement(&mut self) -> ParseResult<Statement> {
@@ -220,6 +480,18 @@ impl Parser {
         Ok(Statement::Print(value))
     }
 
+    fn assert_statement(&mut self) -> ParseResult<Statement> {
+        // Keyword::Assert token is already consumed
+        let condition = self.assignment()?;
+        let message = if self.matches(Token::Symbol(Symbol::Comma)) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(Token::Symbol(Symbol::Semicolon))?;
+        Ok(Statement::Assert(condition, message))
+    }
+
     // Doesn't return a Statement::Block directly for reusability in function parsing
     fn block(&mut self) -> ParseResult<Vec<Statement>> {
         // Left brace already consumed
@@ -240,12 +512,31 @@ impl Parser {
     }
 
     fn expression(&mut self) -> ParseResult<CodeExpression> {
-        self.assignment()
+        self.comma()
+    }
+
+    /// Places where commas separate things (arguments, list elements, ...) parse each one with
+    /// assignment() instead, so they don't get swallowed into a sequence
+    fn comma(&mut self) -> ParseResult<CodeExpression> {
+        let first = self.assignment()?;
+        if !self.comma_operator || !self.check(Token::Symbol(Symbol::Comma)) {
+            return Ok(first);
+        }
+
+        let location = self.peek().location;
+        let mut expressions = vec![first];
+        while self.matches(Token::Symbol(Symbol::Comma)) {
+            expressions.push(self.assignment()?);
+        }
+        Ok(CodeExpression {
+            location,
+            value: Expression::Sequence(expressions),
+        })
     }
 
     fn assignment(&mut self) -> ParseResult<CodeExpression> {
         // Dirty trick: parse lvalue as rvalue
-        let expr = self.or()?;
+        let expr = self.pipe()?;
 
         if self.matches(Token::Symbol(Symbol::Equal)) {
             let equals = self.previous();
@@ -257,6 +548,10 @@ impl Parser {
                     location: equals.location,
                     value: Expression::Assign(v, Box::new(value)),
                 }),
+                Expression::Get(object, name) => Ok(CodeExpression {
+                    location: equals.location,
+                    value: Expression::Set(object, name, Box::new(value)),
+                }),
                 _ => {
                     self.errors.push(ParseError {
                         location: equals.location,
@@ -272,14 +567,63 @@ impl Parser {
         }
     }
 
+    /// `x |> f` is `f(x)`, and `x |> f(y)` is `f(x, y)`
+    fn pipe(&mut self) -> ParseResult<CodeExpression> {
+        let mut expr = self.or()?;
+
+        while self.matches(Token::Symbol(Symbol::PipeGreater)) {
+            let pipe = self.previous();
+            let right = self.call()?;
+            expr = match right {
+                CodeExpression {
+                    location,
+                    value: Expression::Call(callee, mut arguments),
+                } => {
+                    arguments.insert(0, expr);
+                    if arguments.len() > MAX_FUNCTION_ARGS + 1 {
+                        self.errors.push(ParseError {
+                            location,
+                            value: ParseErrorKind::TooManyArguments(MAX_FUNCTION_ARGS),
+                        });
+                    }
+                    CodeExpression {
+                        location,
+                        value: Expression::Call(callee, arguments),
+                    }
+                }
+                CodeExpression {
+                    value:
+                        Expression::Variable(_)
+                        | Expression::Grouping(_)
+                        | Expression::Index(..)
+                        | Expression::Get(..),
+                    ..
+                } => CodeExpression {
+                    location: pipe.location,
+                    value: Expression::Call(Box::new(right), vec![expr]),
+                },
+                _ => {
+                    // Don't throw - we're in a valid state
+                    self.errors.push(ParseError {
+                        location: right.location,
+                        value: ParseErrorKind::PipeIntoNonCallable,
+                    });
+                    expr
+                }
+            };
+        }
+
+        Ok(expr)
+    }
+
     fn or(&mut self) -> ParseResult<CodeExpression> {
         let mut expr = self.and()?;
 
         while self.matches(Token::Keyword(Keyword::Or)) {
-            let operator = self.previous();
+            let location = self.previous().location;
             let right = self.and()?;
             expr = CodeExpression {
-                location: operator.location,
+                location,
                 value: Expression::Binary(Box::new(expr), BinaryOperator::Or, Box::new(right)),
             };
         }
@@ -291,10 +635,10 @@ impl Parser {
         let mut expr = self.equality()?;
 
         while self.matches(Token::Keyword(Keyword::And)) {
-            let operator = self.previous();
+            let location = self.previous().location;
             let right = self.equality()?;
             expr = CodeExpression {
-                location: operator.location,
+                location,
                 value: Expression::Binary(Box::new(expr), BinaryOperator::And, Box::new(right)),
             };
         }
@@ -309,6 +653,7 @@ impl Parser {
         // as one token
         while self.matches(Token::Symbol(Symbol::BangEqual))
             || self.matches(Token::Symbol(Symbol::EqualEqual))
+            || self.matches(Token::Keyword(Keyword::Is))
         {
             let operator = self.previous();
             let right = self.comparison()?;
@@ -373,6 +718,7 @@ impl Parser {
         let mut expr = self.unary()?;
 
         while self.matches(Token::Symbol(Symbol::Slash))
+            || self.matches(Token::Keyword(Keyword::Div))
             || self.matches(Token::Symbol(Symbol::Star))
         {
             let operator = self.previous();
@@ -402,16 +748,54 @@ impl Parser {
                 ),
             })
         } else {
-            Ok(self.call()?)
+            self.power()
         }
     }
 
+    fn power(&mut self) -> ParseResult<CodeExpression> {
+        let base = self.call()?;
+        if !self.matches(Token::Symbol(Symbol::StarStar)) {
+            return Ok(base);
+        }
+        let operator = self.previous();
+        // Going back up to unary makes it right associative, and allows `2 ** -1`
+        let exponent = self.unary()?;
+        Ok(CodeExpression {
+            location: operator.location,
+            value: Expression::Binary(Box::new(base), BinaryOperator::Power, Box::new(exponent)),
+        })
+    }
+
     fn call(&mut self) -> ParseResult<CodeExpression> {
         let mut expr = self.primary()?;
 
-        // match x()()() for example
-        while let Ok(p) = self.consume(Token::Symbol(Symbol::LeftParen)) {
-            expr = self.finish_call(expr, p)?;
+        // match x()[0]() for example
+        loop {
+            if self.matches(Token::Symbol(Symbol::LeftParen)) {
+                expr = self.finish_call(expr, self.previous())?;
+            } else if self.matches(Token::Symbol(Symbol::LeftBracket)) {
+                let left_bracket = self.previous();
+                let index = self.expression()?;
+                self.consume(Token::Symbol(Symbol::RightBracket))?;
+                expr = CodeExpression {
+                    location: left_bracket.location,
+                    value: Expression::Index(Box::new(expr), Box::new(index)),
+                };
+            } else if self.matches(Token::Symbol(Symbol::Dot)) {
+                let name = self.consume_identifier()?;
+                expr = CodeExpression {
+                    location: name.location,
+                    value: Expression::Get(Box::new(expr), name.value),
+                };
+            } else if self.matches(Token::Symbol(Symbol::QuestionDot)) {
+                let name = self.consume_identifier()?;
+                expr = CodeExpression {
+                    location: name.location,
+                    value: Expression::OptionalGet(Box::new(expr), name.value),
+                };
+            } else {
+                break;
+            }
         }
 
         Ok(expr)
@@ -425,7 +809,7 @@ impl Parser {
         let mut arguments = Vec::new();
 
         if !self.check(Token::Symbol(Symbol::RightParen)) {
-            arguments.push(self.expression()?);
+            arguments.push(self.assignment()?);
             while self.matches(Token::Symbol(Symbol::Comma)) {
                 if arguments.len() > MAX_FUNCTION_ARGS {
                     // Don't throw - we're in a valid state
@@ -434,7 +818,7 @@ impl Parser {
                         value: ParseErrorKind::TooManyArguments(MAX_FUNCTION_ARGS),
                     });
                 }
-                arguments.push(self.expression()?);
+                arguments.push(self.assignment()?);
             }
         }
 
@@ -464,11 +848,78 @@ impl Parser {
                 value: Expression::Variable(identifier.value),
             })
         } else if let Ok(left_paren) = self.consume(Token::Symbol(Symbol::LeftParen)) {
-            let expr = self.expression()?;
+            // Without the comma operator, a comma inside of parentheses makes a tuple
+            let first = match self.comma_operator {
+                true => self.expression()?,
+                false => self.assignment()?,
+            };
+            let value = if self.check(Token::Symbol(Symbol::Comma)) {
+                let mut elements = vec![first];
+                while self.matches(Token::Symbol(Symbol::Comma)) {
+                    elements.push(self.assignment()?);
+                }
+                Expression::Tuple(elements)
+            } else {
+                Expression::Grouping(Box::new(first))
+            };
             self.consume(Token::Symbol(Symbol::RightParen))?;
             Ok(CodeExpression {
                 location: left_paren.location,
-                value: Expression::Grouping(Box::new(expr)),
+                value,
+            })
+        } else if let Ok(left_bracket) = self.consume(Token::Symbol(Symbol::LeftBracket)) {
+            let mut elements = Vec::new();
+            if !self.check(Token::Symbol(Symbol::RightBracket)) {
+                elements.push(self.assignment()?);
+                while self.matches(Token::Symbol(Symbol::Comma)) {
+                    elements.push(self.assignment()?);
+                }
+            }
+            self.consume(Token::Symbol(Symbol::RightBracket))?;
+            Ok(CodeExpression {
+                location: left_bracket.location,
+                value: Expression::List(elements),
+            })
+        } else if let Ok(left_brace) = self.consume(Token::Symbol(Symbol::LeftBrace)) {
+            // A brace only starts a block at the start of a statement, here it's always a map
+            let mut entries = Vec::new();
+            if !self.check(Token::Symbol(Symbol::RightBrace)) {
+                entries.push(self.map_entry()?);
+                while self.matches(Token::Symbol(Symbol::Comma)) {
+                    entries.push(self.map_entry()?);
+                }
+            }
+            self.consume(Token::Symbol(Symbol::RightBrace))?;
+            Ok(CodeExpression {
+                location: left_brace.location,
+                value: Expression::Map(entries),
+            })
+        } else if let Ok(this) = self.consume(Token::Keyword(Keyword::This)) {
+            Ok(CodeExpression {
+                location: this.location,
+                value: Expression::This("this".to_string()),
+            })
+        } else if self.check(Token::Keyword(Keyword::Fun))
+            && self.check_next(Token::Symbol(Symbol::LeftParen))
+        {
+            let fun = self.advance();
+            let (params, body) = self.parameters_and_body()?;
+            Ok(CodeExpression {
+                location: fun.location,
+                value: Expression::Lambda(params, Rc::new(body), Vec::new()),
+            })
+        } else if let Ok(keyword) = self.consume(Token::Keyword(Keyword::Super)) {
+            self.consume(Token::Symbol(Symbol::Dot))?;
+            let method = self.consume_identifier()?;
+            Ok(CodeExpression {
+                location: keyword.location,
+                value: Expression::Super("super".to_string(), "this".to_string(), method.value),
+            })
+        } else if matches!(self.peek().token, Token::Keyword(k) if k.is_statement_start()) {
+            let actual = self.peek();
+            Err(ParseError {
+                location: actual.location,
+                value: ParseErrorKind::ExpectedExpressionFoundStatement(actual.token),
             })
         } else {
             Err(ParseError {
@@ -478,6 +929,13 @@ impl Parser {
         }
     }
 
+    fn map_entry(&mut self) -> ParseResult<(CodeExpression, CodeExpression)> {
+        let key = self.assignment()?;
+        self.consume(Token::Symbol(Symbol::Colon))?;
+        let value = self.assignment()?;
+        Ok((key, value))
+    }
+
     fn consume_identifier(&mut self) -> ParseResult<Located<String>> {
         let actual = self.peek();
         match actual.token {
@@ -527,6 +985,13 @@ impl Parser {
         self.peek().token == token
     }
 
+    /// Like check, but for the token after the next one
+    fn check_next(&self, token: Token) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|next| next.token == token)
+    }
+
     fn advance(&mut self) -> CodeToken {
         if !self.is_at_end() {
             self.current += 1;
@@ -539,7 +1004,7 @@ impl Parser {
     }
 
     // Peek doesn't return an option because the last token will
-    // always be Token::Eof, so the code should avoid stepping after it
+    // always be Token::Eof, and advance() never steps after it
     fn peek(&self) -> CodeToken {
         self.tokens
             .get(self.current)
@@ -547,6 +1012,8 @@ impl Parser {
             .clone()
     }
 
+    // Only called right after advancing or matching a token that isn't the first Token::Eof,
+    // so current is always at least 1
     fn previous(&self) -> CodeToken {
         self.tokens
             .get(self.current - 1)
@@ -570,3 +1037,292 @@ impl Parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    fn parser(source: &str) -> Parser {
+        Parser::new(Scanner::new(source.to_string()).tokens().unwrap())
+    }
+
+    fn parse_errors(source: &str) -> Vec<ParseError> {
+        parser(source).parse().expect_err("source shouldn't parse")
+    }
+
+    #[test]
+    fn statement_where_expression_expected() {
+        let errors = parse_errors("print if;");
+        assert!(matches!(
+            &errors[0],
+            Located {
+                location: (1, 7),
+                value: ParseErrorKind::ExpectedExpressionFoundStatement(Token::Keyword(
+                    Keyword::If
+                )),
+            }
+        ));
+        assert_eq!(
+            errors[0].value.to_string(),
+            "expected an expression, found `if` (statements can't be used as expressions)"
+        );
+    }
+
+    #[test]
+    fn unexpected_token_messages() {
+        assert_eq!(
+            parse_errors("(1;")[0].value.to_string(),
+            "unexpected token `;`, expected `)`"
+        );
+        assert_eq!(
+            parse_errors("var 1;")[0].value.to_string(),
+            "unexpected token `1`, expected `identifier`"
+        );
+    }
+
+    #[test]
+    fn single_statements() {
+        match parser("var x = 1;").parse_statement() {
+            Ok(Statement::Var(name, Some(_))) => assert_eq!(name.value, "x"),
+            s => panic!("expected a var declaration, got {s:?}"),
+        }
+        assert!(matches!(
+            parser("print x;").parse_statement(),
+            Ok(Statement::Print(_))
+        ));
+    }
+
+    #[test]
+    fn single_statement_with_trailing_tokens() {
+        let errors = parser("print x; print y;").parse_statement().unwrap_err();
+        assert_eq!(
+            errors[0].value.to_string(),
+            "unexpected token `print`, expected `end of file`"
+        );
+    }
+
+    #[test]
+    fn empty_statements() {
+        let program = parser(";;;").parse().unwrap();
+        assert_eq!(program.len(), 3);
+        assert!(program
+            .iter()
+            .all(|s| matches!(s, Statement::Block(b) if b.is_empty())));
+    }
+
+    #[test]
+    fn loop_with_an_empty_body() {
+        match &parser("while (x) ;").parse().unwrap()[..] {
+            [Statement::While(_, body, None)] => {
+                assert!(matches!(**body, Statement::Block(ref b) if b.is_empty()))
+            }
+            program => panic!("expected a while loop, got {program:?}"),
+        }
+    }
+
+    #[test]
+    fn pipe_becomes_a_call() {
+        let expression = parser("5 |> double").parse_expression().unwrap();
+        let Expression::Call(callee, args) = expression.value else {
+            panic!("expected a call, got {expression:?}");
+        };
+        assert!(matches!(callee.value, Expression::Variable(ref name) if name == "double"));
+        assert!(matches!(
+            args[..],
+            [CodeExpression {
+                value: Expression::Literal(Literal::Number(n)),
+                ..
+            }] if n == 5.0
+        ));
+    }
+
+    #[test]
+    fn fun_with_a_name_is_a_declaration() {
+        assert!(matches!(
+            &parser("fun f(x) { return x; }").parse().unwrap()[..],
+            [Statement::Function(name, ..)] if name.value == "f"
+        ));
+    }
+
+    #[test]
+    fn fun_without_a_name_is_an_expression_statement() {
+        let program = parser("fun (x) { print x; }(7);").parse().unwrap();
+        let [Statement::Expression(CodeExpression {
+            value: Expression::Call(callee, _),
+            ..
+        })] = &program[..]
+        else {
+            panic!("expected a call, got {program:?}");
+        };
+        assert!(matches!(callee.value, Expression::Lambda(..)));
+    }
+
+    /// The warnings from parsing with the brace lint on
+    fn brace_warnings(source: &str) -> Vec<((usize, usize), String)> {
+        let mut parser = parser(source);
+        parser.lint_braces = true;
+        let (result, warnings) = parser.parse_with_warnings();
+        result.unwrap();
+        warnings
+            .into_iter()
+            .map(|w| (w.location, w.value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn braced_bodies_have_no_warnings() {
+        let source = "
+if (a) { b; } else if (c) { d; } else { e; }
+while (a) { b; }
+for (;;) { break; }
+do { a; } while (b);
+";
+        assert!(brace_warnings(source).is_empty());
+    }
+
+    #[test]
+    fn unbraced_bodies_are_warned_about() {
+        let source = "
+if (a) b;
+else c;
+while (a) b;
+for (;;) break;
+";
+        assert_eq!(
+            brace_warnings(source),
+            [
+                ((2, 8), "the body of `if` isn't in braces".to_string()),
+                ((3, 6), "the body of `else` isn't in braces".to_string()),
+                ((4, 11), "the body of `while` isn't in braces".to_string()),
+                ((5, 10), "the body of `for` isn't in braces".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn braces_are_not_linted_by_default() {
+        let (_, warnings) = parser("if (a) b;").parse_with_warnings();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn chained_gets_and_calls_nest_to_the_left() {
+        let expression = parser("obj.a.b().c").parse_expression().unwrap();
+        // Get(Call(Get(Get(obj, a), b)), c)
+        let Expression::Get(call, c) = expression.value else {
+            panic!("expected a get, got {expression:?}");
+        };
+        assert_eq!(c, "c");
+        let Expression::Call(get_b, args) = call.value else {
+            panic!("expected a call, got {call:?}");
+        };
+        assert!(args.is_empty());
+        let Expression::Get(get_a, b) = get_b.value else {
+            panic!("expected a get, got {get_b:?}");
+        };
+        assert_eq!(b, "b");
+        let Expression::Get(obj, a) = get_a.value else {
+            panic!("expected a get, got {get_a:?}");
+        };
+        assert_eq!(a, "a");
+        assert!(matches!(obj.value, Expression::Variable(ref name) if name == "obj"));
+    }
+
+    #[test]
+    fn calls_on_calls_nest_to_the_left() {
+        let expression = parser("f(1)(2).x(3)").parse_expression().unwrap();
+        let Expression::Call(get_x, args) = expression.value else {
+            panic!("expected a call, got {expression:?}");
+        };
+        assert_eq!(args.len(), 1);
+        let Expression::Get(inner, x) = get_x.value else {
+            panic!("expected a get, got {get_x:?}");
+        };
+        assert_eq!(x, "x");
+        let Expression::Call(f_1, _) = inner.value else {
+            panic!("expected a call, got {inner:?}");
+        };
+        assert!(matches!(f_1.value, Expression::Call(ref f, _)
+            if matches!(f.value, Expression::Variable(ref name) if name == "f")));
+    }
+
+    #[test]
+    fn declaration_as_an_unbraced_body() {
+        let errors = parse_errors("if (x) var y = 1;");
+        assert!(matches!(
+            &errors[0],
+            Located {
+                location: (1, 8),
+                value: ParseErrorKind::DeclarationNotAllowed(Keyword::If),
+            }
+        ));
+        assert_eq!(
+            errors[0].value.to_string(),
+            "a declaration can't be the body of `if` without braces"
+        );
+        for (source, keyword) in [
+            ("if (x) print 1; else fun f() {}", Keyword::Else),
+            ("while (x) class A {}", Keyword::While),
+            ("for (;;) var x;", Keyword::For),
+            ("do var x = 1; while (x);", Keyword::Do),
+        ] {
+            assert!(
+                matches!(
+                    parse_errors(source)[0].value,
+                    ParseErrorKind::DeclarationNotAllowed(ref k) if *k == keyword
+                ),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn declarations_in_braced_bodies_and_anonymous_functions() {
+        assert!(parser("if (x) { var y = 1; }").parse().is_ok());
+        assert!(parser("if (x) fun (a) { print a; }(1);").parse().is_ok());
+    }
+
+    fn is_number(expression: &CodeExpression, expected: f64) -> bool {
+        matches!(expression.value, Expression::Literal(Literal::Number(n)) if n == expected)
+    }
+
+    #[test]
+    fn power_binds_tighter_than_unary_minus() {
+        let expression = parser("-2 ** 2").parse_expression().unwrap();
+        let Expression::Unary(UnaryOperator::Minus, power) = expression.value else {
+            panic!("expected a negation, got {expression:?}");
+        };
+        let Expression::Binary(base, BinaryOperator::Power, exponent) = power.value else {
+            panic!("expected a power, got {power:?}");
+        };
+        assert!(is_number(&base, 2.0) && is_number(&exponent, 2.0));
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        let expression = parser("2 ** 3 ** 2").parse_expression().unwrap();
+        let Expression::Binary(base, BinaryOperator::Power, exponent) = expression.value else {
+            panic!("expected a power, got {expression:?}");
+        };
+        assert!(is_number(&base, 2.0));
+        let Expression::Binary(inner_base, BinaryOperator::Power, inner_exponent) = exponent.value
+        else {
+            panic!("expected a power, got {exponent:?}");
+        };
+        assert!(is_number(&inner_base, 3.0) && is_number(&inner_exponent, 2.0));
+    }
+
+    #[test]
+    fn power_binds_tighter_than_multiplication() {
+        let expression = parser("2 * 3 ** 2").parse_expression().unwrap();
+        let Expression::Binary(left, BinaryOperator::Multiply, right) = expression.value else {
+            panic!("expected a multiplication, got {expression:?}");
+        };
+        assert!(is_number(&left, 2.0));
+        assert!(matches!(
+            right.value,
+            Expression::Binary(_, BinaryOperator::Power, _)
+        ));
+    }
+}
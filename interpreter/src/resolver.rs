@@ -1,11 +1,15 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
 
 use crate::{
     ast::{
         CodeExpression, Expression, ResolvedCodeExpression, ResolvedStatement, ResolvedVariable,
-        Statement,
+        Statement, ANONYMOUS,
     },
-    error::{ResolveError, ResolveErrorKind},
+    error::{Located, ResolveError, ResolveErrorKind},
+    token::Keyword,
 };
 
 #[derive(PartialEq)]
@@ -14,15 +18,69 @@ enum ResolverState {
     Defined,
 }
 
+#[derive(PartialEq)]
 enum FunctionType {
     Function,
+    Method,
+    /// The `init` method of a class
+    Initializer,
+    /// A `fun*` function
+    Generator,
+}
+
+enum ClassType {
+    Class,
+    Subclass,
 }
 
 type ResolveResult<T> = Result<T, Vec<ResolveError>>;
 
+/// A function or class being resolved. Its closure holds the local variables from outside of it
+/// that it uses, so that they're shared with the scopes they're declared in.
+struct Closure {
+    /// The index in `scopes` of its outermost scope
+    scope: usize,
+    /// The captured variables in the order they're first used, with the index in `scopes` of the
+    /// scope each one is declared in
+    captures: Vec<(String, usize)>,
+}
+
 pub struct Resolver {
     scopes: Vec<BTreeMap<String, ResolverState>>,
     current_function: Option<FunctionType>,
+    current_class: Option<ClassType>,
+    /// Globals that exist before the program runs, like natives or earlier REPL lines
+    known_globals: BTreeSet<String>,
+    /// Locals that haven't been read yet, with the location they were declared at
+    unused: Vec<BTreeMap<String, (usize, usize)>>,
+    unused_errors: Vec<ResolveError>,
+    /// For every function being resolved, from outermost to innermost: the index of its own
+    /// scope in `scopes`, and its index in `captures`
+    enclosing_functions: Vec<(usize, usize)>,
+    /// Every function and class being resolved, from outermost to innermost
+    closures: Vec<Closure>,
+    /// Labels of the blocks being resolved within the current function, innermost last
+    labels: Vec<String>,
+    /// How many loops the statement being resolved is in, within the current function
+    loop_depth: usize,
+    /// How many `try` blocks the statement being resolved is in, within the current function
+    try_depth: usize,
+
+    /// For every function declaration in the order they appear: its name and the variables from
+    /// scopes outside of it that it refers to (globals aren't included)
+    pub captures: Vec<(String, BTreeSet<String>)>,
+    /// Report globals that are used but never defined
+    pub check_undefined_globals: bool,
+    /// Report an assignment used directly as the condition of an `if` or `while`
+    pub check_assignment_in_condition: bool,
+    /// Report local variables that are never read, unless their name starts with `_`
+    pub check_unused_variables: bool,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Resolver {
@@ -31,62 +89,300 @@ impl Resolver {
         Resolver {
             scopes: Vec::new(),
             current_function: None,
+            current_class: None,
+            known_globals: BTreeSet::new(),
+            unused: Vec::new(),
+            unused_errors: Vec::new(),
+            enclosing_functions: Vec::new(),
+            closures: Vec::new(),
+            labels: Vec::new(),
+            loop_depth: 0,
+            try_depth: 0,
+            captures: Vec::new(),
+            check_undefined_globals: false,
+            check_assignment_in_condition: false,
+            check_unused_variables: false,
         }
     }
 
+    /// Tells the resolver about globals that are defined outside of the resolved code
+    pub fn define_globals(&mut self, names: impl IntoIterator<Item = String>) {
+        self.known_globals.extend(names);
+    }
+
     #[allow(dead_code)]
     pub fn resolve(&mut self, ast: Vec<Statement>) -> ResolveResult<Vec<ResolvedStatement>> {
-        self.resolve_block_statement(false, ast)
+        // Globals can be used before their definition (from inside functions)
+        for statement in &ast {
+            match statement {
+                Statement::Var(name, _)
+                | Statement::Function(name, ..)
+                | Statement::Class(name, ..) => {
+                    self.known_globals.insert(name.value.clone());
+                }
+                Statement::VarTuple(names, _) => self.known_globals.extend(names.iter().cloned()),
+                _ => {}
+            }
+        }
+
+        let res = self.resolve_block_statement(false, ast);
+        let unused_errors = std::mem::take(&mut self.unused_errors);
+        match res {
+            Ok(_) if !unused_errors.is_empty() => Err(unused_errors),
+            Err(mut errors) => {
+                errors.extend(unused_errors);
+                Err(errors)
+            }
+            ok => ok,
+        }
     }
 
     fn resolve_statement(&mut self, statement: Statement) -> ResolveResult<ResolvedStatement> {
         Ok(match statement {
             Statement::Expression(e) => ResolvedStatement::Expression(self.resolve_expr(e)?),
-            Statement::Function(n, p, b) => {
-                self.resolve_function_statement(n, p, b, FunctionType::Function)?
-            }
+            Statement::Function(n, p, b, generator, _) => self.resolve_function_statement(
+                n,
+                p,
+                Rc::unwrap_or_clone(b),
+                if generator {
+                    FunctionType::Generator
+                } else {
+                    FunctionType::Function
+                },
+            )?,
+            Statement::Class(n, s, m, _) => self.resolve_class_statement(n, s, m)?,
             Statement::Print(e) => ResolvedStatement::Print(self.resolve_expr(e)?),
-            Statement::Return(e) => {
+            Statement::Assert(c, m) => ResolvedStatement::Assert(
+                self.resolve_expr(c)?,
+                m.map(|m| self.resolve_expr(m)).transpose()?,
+            ),
+            Statement::Return(Located { location, value: e }, _) => {
                 if self.current_function.is_none() {
                     return Err(vec![ResolveError {
-                        // TODO
-                        location: (0, 0),
+                        location,
                         value: ResolveErrorKind::TopLevelReturn,
                     }]);
                 }
-                ResolvedStatement::Return(e.map(|e| self.resolve_expr(e)).transpose()?)
+                let value_error = match self.current_function {
+                    Some(FunctionType::Initializer) => {
+                        Some(ResolveErrorKind::ReturnValueFromInitializer)
+                    }
+                    Some(FunctionType::Generator) => {
+                        Some(ResolveErrorKind::ReturnValueFromGenerator)
+                    }
+                    _ => None,
+                };
+                if let (Some(e), Some(error)) = (&e, value_error) {
+                    return Err(vec![ResolveError {
+                        location: e.location,
+                        value: error,
+                    }]);
+                }
+                // Errors from a call inside a `try` block must be caught there, so the call can't
+                // wait until the function has returned
+                let tail = self.try_depth == 0
+                    && matches!(
+                        &e,
+                        Some(CodeExpression {
+                            value: Expression::Call(..),
+                            ..
+                        })
+                    );
+                ResolvedStatement::Return(
+                    Located {
+                        location,
+                        value: e.map(|e| self.resolve_expr(e)).transpose()?,
+                    },
+                    tail,
+                )
             }
+            Statement::Throw(e) => ResolvedStatement::Throw(self.resolve_expr(e)?),
             Statement::Var(v, e) => self.resolve_var_statement(v, e)?,
-            Statement::While(c, b) => self.resolve_while_statement(c, *b)?,
+            Statement::VarTuple(v, e) => self.resolve_var_tuple_statement(v, e)?,
+            Statement::While(c, b, i) => self.resolve_while_statement(c, *b, i)?,
+            Statement::DoWhile(b, c) => self.resolve_do_while_statement(*b, c)?,
+            Statement::Foreach(n, i, b) => {
+                self.resolve_foreach_statement(n, i, Rc::unwrap_or_clone(b))?
+            }
+            Statement::Yield(e) => {
+                if self.current_function != Some(FunctionType::Generator) {
+                    return Err(vec![ResolveError {
+                        location: e.location,
+                        value: ResolveErrorKind::YieldOutsideGenerator,
+                    }]);
+                }
+                ResolvedStatement::Yield(self.resolve_expr(e)?)
+            }
+            Statement::TryCatch(b, n, h) => self.resolve_try_statement(b, n, h)?,
             Statement::Block(s) => ResolvedStatement::Block(self.resolve_block_statement(true, s)?),
+            Statement::LabeledBlock(l, s) => {
+                self.labels.push(l.clone());
+                let res = self.resolve_block_statement(true, s);
+                self.labels.pop();
+                ResolvedStatement::LabeledBlock(l, res?)
+            }
+            Statement::Break(b) => {
+                let error = match &b.value {
+                    Some(label) if !self.labels.contains(label) => {
+                        ResolveErrorKind::UndefinedLabel(label.clone())
+                    }
+                    Some(_) => return Ok(ResolvedStatement::Break(b)),
+                    None if self.loop_depth > 0 => return Ok(ResolvedStatement::Break(b)),
+                    None => ResolveErrorKind::JumpOutsideLoop(Keyword::Break),
+                };
+                return Err(vec![ResolveError {
+                    location: b.location,
+                    value: error,
+                }]);
+            }
+            Statement::Continue(location) => {
+                if self.loop_depth == 0 {
+                    return Err(vec![ResolveError {
+                        location,
+                        value: ResolveErrorKind::JumpOutsideLoop(Keyword::Continue),
+                    }]);
+                }
+                ResolvedStatement::Continue(location)
+            }
             Statement::If(c, t, e) => self.resolve_if_statement(c, *t, e.map(|e| *e))?,
         })
     }
 
     fn resolve_function_statement(
         &mut self,
-        name: String,
+        name: Located<String>,
         params: Vec<String>,
         body: Vec<Statement>,
         function_type: FunctionType,
     ) -> ResolveResult<ResolvedStatement> {
-        self.declare(name.clone())?;
-        self.define(name.clone());
+        self.declare(name.value.clone())?;
+        self.define(name.value.clone());
+
+        let generator = function_type == FunctionType::Generator;
+        self.begin_closure();
+        let body = self.resolve_function(name.value.clone(), &params, body, function_type);
+        let captures = self.end_closure();
+        Ok(ResolvedStatement::Function(
+            name,
+            params,
+            Rc::new(body?),
+            generator,
+            captures,
+        ))
+    }
+
+    fn resolve_class_statement(
+        &mut self,
+        name: Located<String>,
+        superclass: Option<CodeExpression>,
+        methods: Vec<Statement>,
+    ) -> ResolveResult<ResolvedStatement> {
+        self.declare(name.value.clone())?;
+        self.define(name.value.clone());
+
+        let superclass = match superclass {
+            Some(CodeExpression {
+                location,
+                value: Expression::Variable(superclass),
+            }) if superclass == name.value => {
+                return Err(vec![ResolveError {
+                    location,
+                    value: ResolveErrorKind::CyclicInheritance(name.value),
+                }]);
+            }
+            Some(superclass) => Some(self.resolve_expr(superclass)?),
+            None => None,
+        };
 
+        let mut previous_class = Some(match superclass {
+            Some(_) => ClassType::Subclass,
+            None => ClassType::Class,
+        });
+        std::mem::swap(&mut previous_class, &mut self.current_class);
+        // The methods share one closure, around the scopes of `super` and `this`
+        self.begin_closure();
+        // Like `this`, `super` gets a scope of its own around the methods
+        if superclass.is_some() {
+            self.begin_scope();
+            self.define("super".to_string());
+        }
+        // Methods are bound to an instance by wrapping them in a scope that only has `this`
+        self.begin_scope();
+        self.define("this".to_string());
+        let mut resolved = Vec::with_capacity(methods.len());
+        let mut errors = Vec::new();
+        for method in methods {
+            let Statement::Function(method_name, params, body, ..) = method else {
+                unreachable!("the parser only puts methods in a class")
+            };
+            let function_type = match method_name.value.as_str() {
+                "init" => FunctionType::Initializer,
+                _ => FunctionType::Method,
+            };
+            let body = self.resolve_function(
+                format!("{}.{}", name.value, method_name.value),
+                &params,
+                Rc::unwrap_or_clone(body),
+                function_type,
+            );
+            match body {
+                Ok(body) => resolved.push(ResolvedStatement::Function(
+                    method_name,
+                    params,
+                    Rc::new(body),
+                    false,
+                    Vec::new(),
+                )),
+                Err(e) => errors.extend(e),
+            }
+        }
+        self.end_scope();
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        let captures = self.end_closure();
+        std::mem::swap(&mut previous_class, &mut self.current_class);
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(ResolvedStatement::Class(
+            name, superclass, resolved, captures,
+        ))
+    }
+
+    /// The parameters and body of a function or method. `name` is only used for `captures`.
+    fn resolve_function(
+        &mut self,
+        name: String,
+        params: &[String],
+        body: Vec<Statement>,
+        function_type: FunctionType,
+    ) -> ResolveResult<Vec<ResolvedStatement>> {
         let mut previous_type = Some(function_type);
         std::mem::swap(&mut previous_type, &mut self.current_function);
         self.begin_scope();
+        self.enclosing_functions
+            .push((self.scopes.len() - 1, self.captures.len()));
+        self.captures.push((name, BTreeSet::new()));
+        // A break can't leave the function it's in
+        let outer_labels = std::mem::take(&mut self.labels);
+        let outer_loop_depth = std::mem::take(&mut self.loop_depth);
+        let outer_try_depth = std::mem::take(&mut self.try_depth);
         let res = (|| {
-            for param in params.clone() {
+            for param in params {
                 self.declare(param.clone())?;
-                self.define(param);
+                self.define(param.clone());
             }
             self.resolve_block_statement(true, body)
         })();
+        self.labels = outer_labels;
+        self.loop_depth = outer_loop_depth;
+        self.try_depth = outer_try_depth;
+        self.enclosing_functions.pop();
         self.end_scope();
         std::mem::swap(&mut previous_type, &mut self.current_function);
-
-        Ok(ResolvedStatement::Function(name, params, res?))
+        res
     }
 
     fn resolve_block_statement(
@@ -123,6 +419,7 @@ impl Resolver {
         then_branch: Statement,
         else_branch: Option<Statement>,
     ) -> ResolveResult<ResolvedStatement> {
+        self.check_condition(&condition)?;
         let condition = self.resolve_expr(condition)?;
         let then_b = self.resolve_statement(then_branch)?;
         let else_b = else_branch.map(|b| self.resolve_statement(b)).transpose()?;
@@ -135,23 +432,117 @@ impl Resolver {
 
     fn resolve_var_statement(
         &mut self,
-        name: String,
+        name: Located<String>,
         expr: Option<CodeExpression>,
     ) -> ResolveResult<ResolvedStatement> {
-        self.declare(name.clone())?;
+        self.declare(name.value.clone())?;
+        if self.check_unused_variables && !name.value.starts_with('_') {
+            if let Some(unused) = self.unused.last_mut() {
+                unused.insert(name.value.clone(), name.location);
+            }
+        }
         let expr = expr.map(|expr| self.resolve_expr(expr)).transpose()?;
-        self.define(name.clone());
+        self.define(name.value.clone());
         Ok(ResolvedStatement::Var(name, expr))
     }
 
+    fn resolve_var_tuple_statement(
+        &mut self,
+        names: Vec<String>,
+        expr: CodeExpression,
+    ) -> ResolveResult<ResolvedStatement> {
+        for name in &names {
+            self.declare(name.clone())?;
+            if self.check_unused_variables && !name.starts_with('_') {
+                if let Some(unused) = self.unused.last_mut() {
+                    unused.insert(name.clone(), expr.location);
+                }
+            }
+        }
+        let expr = self.resolve_expr(expr)?;
+        for name in &names {
+            self.define(name.clone());
+        }
+        Ok(ResolvedStatement::VarTuple(names, expr))
+    }
+
     fn resolve_while_statement(
         &mut self,
         condition: CodeExpression,
         body: Statement,
+        increment: Option<CodeExpression>,
+    ) -> ResolveResult<ResolvedStatement> {
+        self.check_condition(&condition)?;
+        let condition = self.resolve_expr(condition)?;
+        let body = self.resolve_loop_body(body)?;
+        let increment = increment.map(|i| self.resolve_expr(i)).transpose()?;
+        Ok(ResolvedStatement::While(
+            condition,
+            Box::new(body),
+            increment,
+        ))
+    }
+
+    fn resolve_do_while_statement(
+        &mut self,
+        body: Statement,
+        condition: CodeExpression,
     ) -> ResolveResult<ResolvedStatement> {
+        let body = self.resolve_loop_body(body)?;
+        self.check_condition(&condition)?;
         let condition = self.resolve_expr(condition)?;
-        let body = self.resolve_statement(body)?;
-        Ok(ResolvedStatement::While(condition, Box::new(body)))
+        Ok(ResolvedStatement::DoWhile(Box::new(body), condition))
+    }
+
+    /// A statement that `break` and `continue` can jump out of
+    fn resolve_foreach_statement(
+        &mut self,
+        name: String,
+        iterable: CodeExpression,
+        body: Statement,
+    ) -> ResolveResult<ResolvedStatement> {
+        let iterable = self.resolve_expr(iterable)?;
+
+        // Every element gets a fresh scope with just the loop variable in it
+        self.begin_scope();
+        let body = (|| {
+            self.declare(name.clone())?;
+            self.define(name.clone());
+            self.resolve_loop_body(body)
+        })();
+        self.end_scope();
+
+        Ok(ResolvedStatement::Foreach(name, iterable, Rc::new(body?)))
+    }
+
+    fn resolve_loop_body(&mut self, body: Statement) -> ResolveResult<ResolvedStatement> {
+        self.loop_depth += 1;
+        let res = self.resolve_statement(body);
+        self.loop_depth -= 1;
+        res
+    }
+
+    fn resolve_try_statement(
+        &mut self,
+        body: Vec<Statement>,
+        name: String,
+        handler: Vec<Statement>,
+    ) -> ResolveResult<ResolvedStatement> {
+        self.try_depth += 1;
+        let body = self.resolve_block_statement(true, body);
+        self.try_depth -= 1;
+        let body = body?;
+
+        // The caught error and the handler's own declarations share one scope
+        self.begin_scope();
+        let handler = (|| {
+            self.declare(name.clone())?;
+            self.define(name.clone());
+            self.resolve_block_statement(false, handler)
+        })();
+        self.end_scope();
+
+        Ok(ResolvedStatement::TryCatch(body, name, handler?))
     }
 
     pub fn resolve_expr(&mut self, expr: CodeExpression) -> ResolveResult<ResolvedCodeExpression> {
@@ -170,10 +561,112 @@ impl Resolver {
                 location: loc,
                 value: Expression::Grouping(Box::new(self.resolve_expr(*e)?)),
             },
+            Expression::This(n) => {
+                if self.current_class.is_none() {
+                    return Err(vec![ResolveError {
+                        location: loc,
+                        value: ResolveErrorKind::ThisOutsideClass,
+                    }]);
+                }
+                ResolvedCodeExpression {
+                    location: loc,
+                    value: Expression::This(self.resolve_local(loc, n)?),
+                }
+            }
+            Expression::Super(n, this, method) => {
+                let error = match self.current_class {
+                    None => Some(ResolveErrorKind::SuperOutsideClass),
+                    Some(ClassType::Class) => Some(ResolveErrorKind::SuperInClassWithNoSuperclass),
+                    Some(ClassType::Subclass) => None,
+                };
+                if let Some(error) = error {
+                    return Err(vec![ResolveError {
+                        location: loc,
+                        value: error,
+                    }]);
+                }
+                ResolvedCodeExpression {
+                    location: loc,
+                    value: Expression::Super(
+                        self.resolve_local(loc, n)?,
+                        self.resolve_local(loc, this)?,
+                        method,
+                    ),
+                }
+            }
+            Expression::Lambda(params, body, _) => {
+                self.begin_closure();
+                let body = self.resolve_function(
+                    ANONYMOUS.to_string(),
+                    &params,
+                    Rc::unwrap_or_clone(body),
+                    FunctionType::Function,
+                );
+                let captures = self.end_closure();
+                ResolvedCodeExpression {
+                    location: loc,
+                    value: Expression::Lambda(params, Rc::new(body?), captures),
+                }
+            }
+            Expression::OptionalGet(o, n) => ResolvedCodeExpression {
+                location: loc,
+                value: Expression::OptionalGet(Box::new(self.resolve_expr(*o)?), n),
+            },
+            Expression::Get(o, n) => ResolvedCodeExpression {
+                location: loc,
+                value: Expression::Get(Box::new(self.resolve_expr(*o)?), n),
+            },
+            Expression::Set(o, n, v) => ResolvedCodeExpression {
+                location: loc,
+                value: Expression::Set(
+                    Box::new(self.resolve_expr(*o)?),
+                    n,
+                    Box::new(self.resolve_expr(*v)?),
+                ),
+            },
+            Expression::Index(t, i) => ResolvedCodeExpression {
+                location: loc,
+                value: Expression::Index(
+                    Box::new(self.resolve_expr(*t)?),
+                    Box::new(self.resolve_expr(*i)?),
+                ),
+            },
+            Expression::List(e) => ResolvedCodeExpression {
+                location: loc,
+                value: Expression::List(
+                    e.into_iter()
+                        .map(|e| self.resolve_expr(e))
+                        .collect::<Result<_, _>>()?,
+                ),
+            },
+            Expression::Map(e) => ResolvedCodeExpression {
+                location: loc,
+                value: Expression::Map(
+                    e.into_iter()
+                        .map(|(k, v)| Ok((self.resolve_expr(k)?, self.resolve_expr(v)?)))
+                        .collect::<ResolveResult<_>>()?,
+                ),
+            },
             Expression::Literal(l) => ResolvedCodeExpression {
                 location: loc,
                 value: Expression::Literal(l),
             },
+            Expression::Sequence(e) => ResolvedCodeExpression {
+                location: loc,
+                value: Expression::Sequence(
+                    e.into_iter()
+                        .map(|e| self.resolve_expr(e))
+                        .collect::<Result<_, _>>()?,
+                ),
+            },
+            Expression::Tuple(e) => ResolvedCodeExpression {
+                location: loc,
+                value: Expression::Tuple(
+                    e.into_iter()
+                        .map(|e| self.resolve_expr(e))
+                        .collect::<Result<_, _>>()?,
+                ),
+            },
             Expression::Unary(o, r) => ResolvedCodeExpression {
                 location: loc,
                 value: Expression::Unary(o, Box::new(self.resolve_expr(*r)?)),
@@ -208,23 +701,61 @@ impl Resolver {
                 value: ResolveErrorKind::VariableOwnInitializer,
             }])
         } else {
+            if let Some(scope) = self.scopes.iter().rposition(|s| s.contains_key(&name)) {
+                self.unused[scope].remove(&name);
+            }
+            let variable = self.resolve_local(location, name)?;
             Ok(ResolvedCodeExpression {
                 location,
-                value: Expression::Variable(self.resolve_local(name)),
+                value: Expression::Variable(variable),
             })
         }
     }
 
-    fn resolve_local(&mut self, name: String) -> ResolvedVariable {
+    fn resolve_local(
+        &mut self,
+        location: (usize, usize),
+        name: String,
+    ) -> ResolveResult<ResolvedVariable> {
         for i in (0..self.scopes.len()).rev() {
             if self.scopes[i].contains_key(&name) {
-                return ResolvedVariable {
-                    name,
-                    hops: Some(self.scopes.len() - 1 - i),
+                // Every function between the use and the declaration captures it
+                for &(function_scope, captures) in self.enclosing_functions.iter().rev() {
+                    if function_scope <= i {
+                        break;
+                    }
+                    self.captures[captures].1.insert(name.clone());
+                }
+                // So does every closure, and the innermost one has it in its own environment
+                for closure in self.closures.iter_mut().rev() {
+                    if closure.scope <= i {
+                        break;
+                    }
+                    if !closure
+                        .captures
+                        .iter()
+                        .any(|(captured, _)| *captured == name)
+                    {
+                        closure.captures.push((name.clone(), i));
+                    }
+                }
+                let hops = match self.closures.last() {
+                    Some(closure) if closure.scope > i => self.scopes.len() - closure.scope,
+                    _ => self.scopes.len() - 1 - i,
                 };
+                return Ok(ResolvedVariable {
+                    name,
+                    hops: Some(hops),
+                });
             }
         }
-        ResolvedVariable { name, hops: None }
+        if self.check_undefined_globals && !self.known_globals.contains(&name) {
+            return Err(vec![ResolveError {
+                location,
+                value: ResolveErrorKind::UndefinedGlobal(name),
+            }]);
+        }
+        Ok(ResolvedVariable { name, hops: None })
     }
 
     fn resolve_assign_expr(
@@ -234,7 +765,7 @@ impl Resolver {
         expr: CodeExpression,
     ) -> ResolveResult<ResolvedCodeExpression> {
         let expr = self.resolve_expr(expr)?;
-        let var = self.resolve_local(name);
+        let var = self.resolve_local(location, name)?;
         Ok(ResolvedCodeExpression {
             location,
             value: Expression::Assign(var, Box::new(expr)),
@@ -243,6 +774,18 @@ impl Resolver {
 
     // util
 
+    fn check_condition(&self, condition: &CodeExpression) -> ResolveResult<()> {
+        if self.check_assignment_in_condition {
+            if let Expression::Assign(..) = condition.value {
+                return Err(vec![ResolveError {
+                    location: condition.location,
+                    value: ResolveErrorKind::AssignmentInCondition,
+                }]);
+            }
+        }
+        Ok(())
+    }
+
     fn declare(&mut self, name: String) -> ResolveResult<()> {
         if let Some(current) = self.scopes.last_mut() {
             if current.contains_key(&name) {
@@ -263,11 +806,50 @@ impl Resolver {
         }
     }
 
+    /// Starts a function or class, before any of its scopes
+    fn begin_closure(&mut self) {
+        self.closures.push(Closure {
+            scope: self.scopes.len(),
+            captures: Vec::new(),
+        });
+    }
+
+    /// Ends the innermost function or class, resulting in the variables it captures as seen from
+    /// where it's created. Those are either in the scopes it's created in, or in the closure of the
+    /// function or class around it.
+    fn end_closure(&mut self) -> Vec<ResolvedVariable> {
+        let closure = self.closures.pop().expect("a closure was started");
+        let enclosing = self.closures.last().map(|c| c.scope);
+        closure
+            .captures
+            .into_iter()
+            .map(|(name, declared_in)| {
+                // Something declared outside is captured, so this isn't in the global scope
+                let created_in = closure.scope - 1;
+                let hops = match enclosing {
+                    Some(enclosing) if enclosing > declared_in => created_in - enclosing + 1,
+                    _ => created_in - declared_in,
+                };
+                ResolvedVariable {
+                    name,
+                    hops: Some(hops),
+                }
+            })
+            .collect()
+    }
+
     fn begin_scope(&mut self) {
         self.scopes.push(BTreeMap::new());
+        self.unused.push(BTreeMap::new());
     }
 
     fn end_scope(&mut self) {
         self.scopes.pop();
+        let unused = self.unused.pop().unwrap_or_default();
+        self.unused_errors
+            .extend(unused.into_iter().map(|(name, location)| ResolveError {
+                location,
+                value: ResolveErrorKind::UnusedVariable(name),
+            }));
     }
 }
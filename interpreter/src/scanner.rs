@@ -1,3 +1,5 @@
+use std::{collections::BTreeSet, ops::Range, rc::Rc};
+
 use crate::{
     error::{TokenizeError, TokenizeErrorKind},
     token::{CodeToken, Keyword, Literal, Symbol, Token},
@@ -11,6 +13,20 @@ pub struct Scanner {
     lexeme_start: usize,
     lexeme_len: usize,
     line: usize,
+    /// Every string literal scanned so far, so identical ones can share an allocation
+    strings: BTreeSet<Rc<str>>,
+    /// Whether the indentation of the current line has had tabs and spaces so far. None once
+    /// past the indentation, or once the line has been warned about.
+    indentation: Option<(bool, bool)>,
+    warnings: Vec<TokenizeError>,
+
+    /// Scan `print` as an identifier, so it refers to the native function instead of the
+    /// statement
+    pub print_as_function: bool,
+    /// How many columns apart tab stops are. A tab moves a location's column up to the next one.
+    pub tab_width: usize,
+    /// Warn about lines whose indentation mixes tabs and spaces
+    pub lint_indent: bool,
 }
 
 impl Scanner {
@@ -20,13 +36,35 @@ impl Scanner {
             lexeme_start: 0,
             lexeme_len: 0,
             line: 1,
+            strings: BTreeSet::new(),
+            indentation: Some((false, false)),
+            warnings: Vec::new(),
+            print_as_function: false,
+            tab_width: 1,
+            lint_indent: false,
         }
     }
 
     pub fn tokens(&mut self) -> Result<Vec<CodeToken>, Vec<TokenizeError>> {
+        self.tokens_with_warnings().0
+    }
+
+    /// Like tokens, but also returns the warnings, which are there even when scanning fails
+    pub fn tokens_with_warnings(
+        &mut self,
+    ) -> (
+        Result<Vec<CodeToken>, Vec<TokenizeError>>,
+        Vec<TokenizeError>,
+    ) {
+        let result = self.scan_tokens();
+        (result, std::mem::take(&mut self.warnings))
+    }
+
+    fn scan_tokens(&mut self) -> Result<Vec<CodeToken>, Vec<TokenizeError>> {
         if self.source.is_empty() {
             return Ok(vec![CodeToken {
                 location: (0, 0),
+                byte_range: 0..0,
                 token: Token::Eof,
             }]);
         }
@@ -34,6 +72,14 @@ impl Scanner {
         let mut tokens = Vec::new();
         let mut errors = Vec::new();
 
+        // A `#!` first line lets scripts be run directly. Only the line itself is skipped, its line
+        // break is scanned as usual so the lines after it keep their numbers.
+        if self.source.starts_with("#!") {
+            while !matches!(self.peek(), Some('\n' | '\r') | None) {
+                self.advance();
+            }
+        }
+
         while !self.is_at_end() {
             self.lexeme_start += self.lexeme_len;
             self.lexeme_len = 0;
@@ -46,6 +92,7 @@ impl Scanner {
                         tokens.push(CodeToken {
                             token,
                             location: self.location(),
+                            byte_range: self.byte_range(),
                         });
                     }
                 }
@@ -58,6 +105,7 @@ impl Scanner {
         tokens.push(CodeToken {
             token: Token::Eof,
             location: self.location(),
+            byte_range: self.source.len()..self.source.len(),
         });
 
         if errors.is_empty() {
@@ -68,19 +116,36 @@ impl Scanner {
     }
 
     fn scan_token(&mut self) -> TokenizeResult<Option<Token>> {
-        Ok(Some(match self.advance() {
+        let c = self.advance();
+        if !matches!(c, ' ' | '\t' | '\r' | '\n') {
+            self.indentation = None;
+        }
+        Ok(Some(match c {
             '(' => Token::Symbol(Symbol::LeftParen),
             ')' => Token::Symbol(Symbol::RightParen),
             '{' => Token::Symbol(Symbol::LeftBrace),
             '}' => Token::Symbol(Symbol::RightBrace),
+            '[' => Token::Symbol(Symbol::LeftBracket),
+            ']' => Token::Symbol(Symbol::RightBracket),
+            ':' => Token::Symbol(Symbol::Colon),
+            '?' => Token::Symbol(if self.matches('.') {
+                Symbol::QuestionDot
+            } else {
+                Symbol::Question
+            }),
             ',' => Token::Symbol(Symbol::Comma),
             '.' => Token::Symbol(Symbol::Dot),
             '-' => Token::Symbol(Symbol::Minus),
             '+' => Token::Symbol(Symbol::Plus),
             ';' => Token::Symbol(Symbol::Semicolon),
-            '*' => Token::Symbol(Symbol::Star),
+            '*' => Token::Symbol(if self.matches('*') {
+                Symbol::StarStar
+            } else {
+                Symbol::Star
+            }),
 
             // 2-character
+            '|' if self.matches('>') => Token::Symbol(Symbol::PipeGreater),
             '!' => Token::Symbol(if self.matches('=') {
                 Symbol::BangEqual
             } else {
@@ -106,28 +171,44 @@ impl Scanner {
             '/' => {
                 if self.matches('/') {
                     // Comment - go till the end of the line
-                    while self.peek() != Some('\n') && self.peek().is_some() {
+                    while !matches!(self.peek(), Some('\n' | '\r') | None) {
                         self.advance();
                     }
                     return Ok(None);
+                } else if self.matches('*') {
+                    self.block_comment()?;
+                    return Ok(None);
                 } else {
                     Token::Symbol(Symbol::Slash)
                 }
             }
 
             // Whitespace
-            ' ' | '\r' | '\t' => return Ok(None),
+            ' ' | '\t' => {
+                self.indent(c);
+                return Ok(None);
+            }
+            // `\r\n` is one line break, counted at the `\n`
+            '\r' => {
+                if self.peek() != Some('\n') {
+                    self.line += 1;
+                    self.indentation = Some((false, false));
+                }
+                return Ok(None);
+            }
             '\n' => {
                 self.line += 1;
+                self.indentation = Some((false, false));
                 return Ok(None);
             }
 
             // Literals
             '"' => Token::Literal(Literal::String(self.string()?)),
+            '@' if self.matches('"') => Token::Literal(Literal::String(self.raw_string()?)),
 
             c if c.is_ascii_digit() => Token::Literal(Literal::Number(self.number())),
 
-            c if c.is_ascii_alphabetic() || c == '_' => self.word(),
+            c if c.is_alphabetic() || c == '_' => self.word(),
 
             c => {
                 return Err(TokenizeError {
@@ -138,6 +219,45 @@ impl Scanner {
         }))
     }
 
+    /// The rest of a `/* ... */` comment, after the opening `/*`. Comments nest, so every `/*`
+    /// inside needs its own `*/`.
+    fn block_comment(&mut self) -> TokenizeResult<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            let Some(c) = self.peek() else {
+                return Err(TokenizeError {
+                    location: self.location(),
+                    value: TokenizeErrorKind::UnterminatedComment,
+                });
+            };
+            self.advance();
+            match c {
+                '/' if self.matches('*') => depth += 1,
+                '*' if self.matches('/') => depth -= 1,
+                '\n' => self.line += 1,
+                '\r' if self.peek() != Some('\n') => self.line += 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps track of the whitespace at the start of a line, for `lint_indent`
+    fn indent(&mut self, c: char) {
+        let Some((tabs, spaces)) = &mut self.indentation else {
+            return;
+        };
+        *tabs |= c == '\t';
+        *spaces |= c == ' ';
+        if *tabs && *spaces && self.lint_indent {
+            self.warnings.push(TokenizeError {
+                location: (self.line, 1),
+                value: TokenizeErrorKind::MixedIndentation,
+            });
+            self.indentation = None;
+        }
+    }
+
     // General helpers
 
     fn peek(&self) -> Option<char> {
@@ -169,44 +289,177 @@ impl Scanner {
             .nth(self.lexeme_start + self.lexeme_len + 1)
     }
 
+    /// The byte offset of the char at the given index, or the end of the source if it's past it
+    fn byte_offset(&self, char_index: usize) -> usize {
+        self.source
+            .char_indices()
+            .nth(char_index)
+            .map(|(i, _)| i)
+            .unwrap_or(self.source.len())
+    }
+
+    fn byte_range(&self) -> Range<usize> {
+        self.byte_offset(self.lexeme_start)..self.byte_offset(self.lexeme_start + self.lexeme_len)
+    }
+
     fn location(&self) -> (usize, usize) {
-        let lexeme_start_byte = self.source.char_indices().nth(self.lexeme_start).unwrap().0;
-        let before_current = &self.source[0..lexeme_start_byte];
+        self.location_at(self.lexeme_start)
+    }
+
+    /// The location of the character at the given char index
+    fn location_at(&self, index: usize) -> (usize, usize) {
+        let before_current = &self.source[0..self.byte_offset(index)];
         // line is different than self.line in case of multiline lexeme (like a string)
-        let line = before_current.chars().filter(|c| *c == '\n').count();
-        let last_line_start = before_current.rfind('\n').map(|x| x + 1).unwrap_or(0);
+        let mut line = 0;
+        let mut chars = before_current.chars().peekable();
+        while let Some(c) = chars.next() {
+            // A lone `\r` is an old style line break, `\r\n` is counted at the `\n`
+            if c == '\n' || (c == '\r' && chars.peek() != Some(&'\n')) {
+                line += 1;
+            }
+        }
+        let last_line_start = before_current
+            .rfind(['\n', '\r'])
+            .map(|x| x + 1)
+            .unwrap_or(0);
+        // Count chars rather than bytes so multibyte characters take up one column
+        let column = visual_width(&before_current[last_line_start..], self.tab_width);
         // + 1 for 1-indexed
-        (line + 1, self.lexeme_start - last_line_start + 1)
+        (line + 1, column + 1)
     }
 
     // Token helpers
 
-    fn string(&mut self) -> TokenizeResult<String> {
-        while self.peek() != Some('"') && self.peek().is_some() {
-            if self.peek().unwrap() == '\n' {
-                self.line += 1;
+    fn intern(&mut self, string: String) -> Rc<str> {
+        if let Some(interned) = self.strings.get(string.as_str()) {
+            return interned.clone();
+        }
+        let interned: Rc<str> = string.into();
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    fn string(&mut self) -> TokenizeResult<Rc<str>> {
+        let mut value = String::new();
+        // The first bad escape. It's only returned once the closing quote is consumed, so that
+        // scanning picks up again after the string rather than in the middle of it.
+        let mut error = None;
+        loop {
+            match self.peek() {
+                None => {
+                    return Err(TokenizeError {
+                        location: self.location(),
+                        value: TokenizeErrorKind::UnterminatedString,
+                    })
+                }
+                Some('"') => {
+                    self.advance();
+                    break;
+                }
+                Some('\\') => {
+                    let start = self.lexeme_start + self.lexeme_len;
+                    self.advance();
+                    match self.escape() {
+                        Ok(c) => value.push(c),
+                        Err(kind) => {
+                            error.get_or_insert(TokenizeError {
+                                location: self.location_at(start),
+                                value: kind,
+                            });
+                        }
+                    }
+                }
+                Some(c) => {
+                    if c == '\n' || (c == '\r' && self.peek_next() != Some('\n')) {
+                        self.line += 1;
+                    }
+                    value.push(self.advance());
+                }
             }
-            self.advance();
         }
 
-        if self.is_at_end() {
-            Err(TokenizeError {
-                location: self.location(),
-                value: TokenizeErrorKind::UnterminatedString,
-            })
-        } else {
-            // Consume `"`
-            self.advance();
+        match error {
+            Some(error) => Err(error),
+            None => Ok(self.intern(value)),
+        }
+    }
 
-            let value = self
-                .source
-                .chars()
-                .skip(self.lexeme_start + 1)
-                // -1 for starting quote, -1 for ending quote
-                .take(self.lexeme_len - 2)
-                .collect();
-            Ok(value)
+    /// The character an escape sequence stands for, after its `\`
+    fn escape(&mut self) -> Result<char, TokenizeErrorKind> {
+        let c = match self.peek() {
+            // Left for string() to report as an unterminated string
+            None => return Err(TokenizeErrorKind::UnterminatedString),
+            // Left for string() to count the line
+            Some(c @ ('\n' | '\r')) => return Err(TokenizeErrorKind::InvalidEscape(c)),
+            Some(_) => self.advance(),
+        };
+        Ok(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            'x' => {
+                let digits = self.hex_digits(2);
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 && byte.is_ascii() => byte as char,
+                    _ => return Err(TokenizeErrorKind::InvalidHexEscape),
+                }
+            }
+            'u' => {
+                if !self.matches('{') {
+                    return Err(TokenizeErrorKind::InvalidUnicodeEscape);
+                }
+                let digits = self.hex_digits(6);
+                if !self.matches('}') {
+                    return Err(TokenizeErrorKind::InvalidUnicodeEscape);
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(TokenizeErrorKind::InvalidUnicodeEscape)?
+            }
+            c => return Err(TokenizeErrorKind::InvalidEscape(c)),
+        })
+    }
+
+    /// Consumes up to `max` hex digits
+    fn hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::new();
+        while digits.len() < max && self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            digits.push(self.advance());
+        }
+        digits
+    }
+
+    /// A string that's taken as it's written, apart from `""` which stands for one `"`
+    fn raw_string(&mut self) -> TokenizeResult<Rc<str>> {
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None => {
+                    return Err(TokenizeError {
+                        location: self.location(),
+                        value: TokenizeErrorKind::UnterminatedString,
+                    })
+                }
+                Some('"') => {
+                    self.advance();
+                    if !self.matches('"') {
+                        break;
+                    }
+                    value.push('"');
+                }
+                Some(c) => {
+                    if c == '\n' || (c == '\r' && self.peek_next() != Some('\n')) {
+                        self.line += 1;
+                    }
+                    value.push(self.advance());
+                }
+            }
         }
+        Ok(self.intern(value))
     }
 
     fn number(&mut self) -> f64 {
@@ -240,7 +493,7 @@ impl Scanner {
     fn word(&mut self) -> Token {
         while self
             .peek()
-            .map(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.is_alphanumeric() || c == '_')
             .unwrap_or(false)
         {
             self.advance();
@@ -253,7 +506,9 @@ impl Scanner {
             .take(self.lexeme_len)
             .collect();
 
-        if let Some(keyword) = Keyword::from_word(&text) {
+        if self.print_as_function && text == "print" {
+            Token::Identifier(text)
+        } else if let Some(keyword) = Keyword::from_word(&text) {
             Token::Keyword(keyword)
         } else if text == "false" {
             Token::Literal(Literal::Boolean(false))
@@ -266,3 +521,321 @@ impl Scanner {
         }
     }
 }
+
+/// How many columns the text takes up, with tab stops every `tab_width` columns
+fn visual_width(text: &str, tab_width: usize) -> usize {
+    text.chars().fold(0, |column, c| match c {
+        '\t' => next_tab_stop(column, tab_width),
+        _ => column + 1,
+    })
+}
+
+/// The (0-indexed) column a tab at `column` moves to
+pub fn next_tab_stop(column: usize, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    (column / tab_width + 1) * tab_width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<CodeToken> {
+        Scanner::new(source.to_string())
+            .tokens()
+            .expect("source scans")
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let tokens = tokens("var café = 変数 + _ñ1;");
+        let identifiers: Vec<_> = tokens
+            .iter()
+            .filter_map(|t| match &t.token {
+                Token::Identifier(name) => Some((name.as_str(), t.location)),
+                _ => None,
+            })
+            .collect();
+        // Multibyte characters take up one column each
+        assert_eq!(
+            identifiers,
+            [("café", (1, 5)), ("変数", (1, 12)), ("_ñ1", (1, 17))]
+        );
+    }
+
+    #[test]
+    fn identical_string_literals_share_an_allocation() {
+        let strings: Vec<_> = tokens(r#""same" + "other" + "same";"#)
+            .into_iter()
+            .filter_map(|t| match t.token {
+                Token::Literal(Literal::String(s)) => Some(s),
+                _ => None,
+            })
+            .collect();
+        assert!(Rc::ptr_eq(&strings[0], &strings[2]));
+        assert!(!Rc::ptr_eq(&strings[0], &strings[1]));
+    }
+
+    #[test]
+    fn byte_ranges_count_multibyte_characters() {
+        let source = r#""café" + 1"#;
+        let tokens = tokens(source);
+        let ranges: Vec<_> = tokens.iter().map(|t| t.byte_range.clone()).collect();
+        // `é` is two bytes long, so the string literal takes 7 bytes for 6 characters
+        assert_eq!(ranges, [0..7, 8..9, 10..11, 11..11]);
+        assert_eq!(&source[tokens[0].byte_range.clone()], r#""café""#);
+        assert_eq!(tokens[1].location, (1, 8));
+    }
+
+    /// The location of the only error in the source
+    fn error_location(source: &str) -> (usize, usize) {
+        let errors = Scanner::new(source.to_string()).tokens().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        errors[0].location
+    }
+
+    #[test]
+    fn carriage_return_only_line_breaks() {
+        assert_eq!(error_location("var a = 1;\rvar b = 2;\r  @"), (3, 3));
+        // Inside comments and strings too
+        assert_eq!(error_location("// one\r\"two\rthree\"\r@"), (4, 1));
+    }
+
+    #[test]
+    fn carriage_return_line_feed_is_one_line_break() {
+        assert_eq!(error_location("var a = 1;\r\nvar b = 2;\r\n  @"), (3, 3));
+        assert_eq!(error_location("// one\r\n\"two\r\nthree\"\r\n@"), (4, 1));
+        // Mixed in one source
+        assert_eq!(error_location("1;\r2;\r\n3;\n@"), (4, 1));
+    }
+
+    #[test]
+    fn token_locations_after_carriage_returns() {
+        let lines: Vec<_> = tokens("a\rb\r\nc\n\rd")
+            .iter()
+            .map(|t| t.location.0)
+            .collect();
+        // The last one is the end of the source
+        assert_eq!(lines, [1, 2, 3, 5, 5]);
+    }
+
+    /// The value of the only string literal in the source
+    fn string(source: &str) -> String {
+        let strings: Vec<_> = tokens(source)
+            .into_iter()
+            .filter_map(|t| match t.token {
+                Token::Literal(Literal::String(s)) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(strings.len(), 1);
+        strings[0].clone()
+    }
+
+    #[test]
+    fn raw_strings_keep_escapes() {
+        assert_eq!(string(r#"@"a\n""#), "a\\n");
+        assert_eq!(string(r#"@"C:\path\n""#), r"C:\path\n");
+        // A normal string still processes them
+        assert_eq!(string(r#""a\n""#), "a\n");
+    }
+
+    #[test]
+    fn doubled_quote_in_raw_string() {
+        assert_eq!(string(r#"@"say ""hi""""#), r#"say "hi""#);
+        assert_eq!(string(r#"@"""#), "");
+    }
+
+    #[test]
+    fn unterminated_raw_string() {
+        let errors = Scanner::new(r#"@"abc"#.to_string()).tokens().unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [TokenizeError {
+                value: TokenizeErrorKind::UnterminatedString,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn ternary_symbols() {
+        let kinds = |source| -> Vec<_> { tokens(source).into_iter().map(|t| t.token).collect() };
+        assert_eq!(
+            kinds("a ? b : c"),
+            [
+                Token::Identifier("a".to_string()),
+                Token::Symbol(Symbol::Question),
+                Token::Identifier("b".to_string()),
+                Token::Symbol(Symbol::Colon),
+                Token::Identifier("c".to_string()),
+                Token::Eof,
+            ]
+        );
+        // `?.` is one token, `? .` is two
+        let tokens = kinds("a?.b ? .");
+        assert_eq!(tokens[1], Token::Symbol(Symbol::QuestionDot));
+        assert_eq!(tokens[3], Token::Symbol(Symbol::Question));
+        assert_eq!(tokens[4], Token::Symbol(Symbol::Dot));
+    }
+
+    #[test]
+    fn shebang_line_is_skipped() {
+        let tokens = tokens("#!/usr/bin/env rlox\nprint 1;");
+        assert_eq!(tokens[0].token, Token::Keyword(Keyword::Print));
+        assert_eq!(tokens[0].location, (2, 1));
+        // The error is still on the line it's written on
+        assert_eq!(error_location("#!/usr/bin/env rlox\n\n  @"), (3, 3));
+        // Only right at the start
+        assert_eq!(error_location("print 1;\n#!"), (2, 1));
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(string(r#""a\tb\n\\\"\0""#), "a\tb\n\\\"\0");
+        assert_eq!(string(r#""\x41\x7f""#), "A\x7f");
+        assert_eq!(string(r#""\u{1F600}""#), "😀");
+        assert_eq!(string(r#""\u{e9}\u{0}""#), "é\0");
+    }
+
+    /// The kind and location of the only error in the source
+    fn error(source: &str) -> (TokenizeErrorKind, (usize, usize)) {
+        let mut errors = Scanner::new(source.to_string()).tokens().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        let error = errors.remove(0);
+        (error.value, error.location)
+    }
+
+    #[test]
+    fn invalid_unicode_escapes() {
+        for source in [
+            r#""\u{D800}""#,
+            r#""\u{110000}""#,
+            r#""\u{}""#,
+            r#""\u1F600""#,
+            r#""\u{1F600""#,
+            r#""\u{12345678}""#,
+        ] {
+            assert!(
+                matches!(error(source).0, TokenizeErrorKind::InvalidUnicodeEscape),
+                "{source}"
+            );
+        }
+        assert_eq!(error(r#"var s = "ab\u{D800}";"#).1, (1, 12));
+    }
+
+    #[test]
+    fn invalid_hex_escapes() {
+        for source in [r#""\x4""#, r#""\xG1""#, r#""\x80""#, r#""\x""#] {
+            assert!(
+                matches!(error(source).0, TokenizeErrorKind::InvalidHexEscape),
+                "{source}"
+            );
+        }
+        assert!(matches!(
+            error(r#""\q""#).0,
+            TokenizeErrorKind::InvalidEscape('q')
+        ));
+    }
+
+    fn error_location_with_tab_width(source: &str, tab_width: usize) -> (usize, usize) {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.tab_width = tab_width;
+        let errors = scanner.tokens().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        errors[0].location
+    }
+
+    #[test]
+    fn tabs_move_to_the_next_tab_stop() {
+        assert_eq!(error_location_with_tab_width("var a;\n\t@", 4), (2, 5));
+        assert_eq!(error_location_with_tab_width("\t\t@", 4), (1, 9));
+        // Spaces before a tab don't push it past its tab stop
+        assert_eq!(error_location_with_tab_width("  \t@", 4), (1, 5));
+        assert_eq!(error_location_with_tab_width("\t  @", 8), (1, 11));
+    }
+
+    #[test]
+    fn tabs_are_one_column_by_default() {
+        assert_eq!(error_location("\t\t@"), (1, 3));
+    }
+
+    /// The locations of the mixed indentation warnings, with `lint_indent` on
+    fn indent_warnings(source: &str) -> Vec<(usize, usize)> {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.lint_indent = true;
+        let (result, warnings) = scanner.tokens_with_warnings();
+        result.expect("source scans");
+        warnings
+            .into_iter()
+            .map(|w| {
+                assert!(matches!(w.value, TokenizeErrorKind::MixedIndentation));
+                w.location
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mixed_indentation_warns() {
+        assert_eq!(indent_warnings("{\n\t print 1;\n}"), [(2, 1)]);
+        assert_eq!(indent_warnings("{\n  \tprint 1;\n}"), [(2, 1)]);
+        // One warning per line
+        assert_eq!(
+            indent_warnings("{\n \t \tprint 1;\n\tprint 2;\n\t print 3;\n}"),
+            [(2, 1), (4, 1)]
+        );
+    }
+
+    #[test]
+    fn consistent_indentation_has_no_warnings() {
+        assert!(indent_warnings("{\n\tprint 1;\n\t\tprint 2;\n}").is_empty());
+        assert!(indent_warnings("{\n    print 1;\n  print 2;\n}").is_empty());
+        // Only the start of a line counts
+        assert!(indent_warnings("\tprint 1; \t print 2;").is_empty());
+        assert!(indent_warnings("  print \"\t\";").is_empty());
+    }
+
+    #[test]
+    fn mixed_indentation_is_only_linted_when_asked() {
+        let (result, warnings) =
+            Scanner::new("{\n\t print 1;\n}".to_string()).tokens_with_warnings();
+        assert!(result.is_ok());
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn block_comments_are_skipped() {
+        let kinds: Vec<_> = tokens("1 /* two */ + /* a /* nested */ comment */ 3")
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                Token::Literal(Literal::Number(1.0)),
+                Token::Symbol(Symbol::Plus),
+                Token::Literal(Literal::Number(3.0)),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_inside_block_comments_are_counted() {
+        assert_eq!(error_location("/* one\ntwo\r\nthree */ @"), (3, 10));
+        assert_eq!(error_location("/* /*\n*/\n*/\n@"), (4, 1));
+    }
+
+    #[test]
+    fn unterminated_block_comment_points_at_its_start() {
+        let errors = Scanner::new("print 1;\n  /* a /* b */\nc".to_string())
+            .tokens()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].value,
+            TokenizeErrorKind::UnterminatedComment
+        ));
+        assert_eq!(errors[0].location, (2, 3));
+    }
+}
@@ -1,7 +1,11 @@
+use std::{fmt::Display, ops::Range, rc::Rc};
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct CodeToken {
     pub token: Token,
     pub location: (usize, usize),
+    /// Where the lexeme is in the source, in bytes rather than chars
+    pub byte_range: Range<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -15,7 +19,8 @@ pub enum Token {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
-    String(String),
+    /// Interned by the scanner, so identical literals share the same string
+    String(Rc<str>),
     Number(f64),
     Boolean(bool),
     Nil,
@@ -27,10 +32,14 @@ pub enum Symbol {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
     Plus,
+    Question,
     Semicolon,
     Slash,
     Star,
@@ -42,41 +51,68 @@ pub enum Symbol {
     GreaterEqual,
     Less,
     LessEqual,
+    PipeGreater,
+    QuestionDot,
+    StarStar,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Keyword {
     And,
+    Assert,
+    Break,
+    Catch,
     Class,
+    Continue,
+    Div,
+    Do,
     Else,
     Fun,
     For,
+    Foreach,
     If,
+    In,
+    Is,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
+    Try,
     Var,
     While,
+    Yield,
 }
 
 impl Keyword {
     pub fn from_word(word: &str) -> Option<Keyword> {
         Some(match word {
             "and" => Keyword::And,
+            "assert" => Keyword::Assert,
+            "break" => Keyword::Break,
+            "catch" => Keyword::Catch,
             "class" => Keyword::Class,
+            "continue" => Keyword::Continue,
+            "div" => Keyword::Div,
+            "do" => Keyword::Do,
             "else" => Keyword::Else,
             "fun" => Keyword::Fun,
             "for" => Keyword::For,
+            "foreach" => Keyword::Foreach,
             "if" => Keyword::If,
+            "in" => Keyword::In,
+            "is" => Keyword::Is,
             "or" => Keyword::Or,
             "print" => Keyword::Print,
             "return" => Keyword::Return,
             "super" => Keyword::Super,
             "this" => Keyword::This,
+            "throw" => Keyword::Throw,
+            "try" => Keyword::Try,
             "var" => Keyword::Var,
             "while" => Keyword::While,
+            "yield" => Keyword::Yield,
             _ => return None,
         })
     }
@@ -88,10 +124,131 @@ impl Keyword {
                 | Keyword::Fun
                 | Keyword::Var
                 | Keyword::For
+                | Keyword::Foreach
                 | Keyword::If
                 | Keyword::While
+                | Keyword::Do
+                | Keyword::Try
+                | Keyword::Throw
                 | Keyword::Print
+                | Keyword::Assert
                 | Keyword::Return
+                | Keyword::Break
+                | Keyword::Continue
+                | Keyword::Yield
         )
     }
 }
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Literal(l) => write!(f, "{l}"),
+            Token::Symbol(s) => write!(f, "{s}"),
+            // The parser uses an empty identifier to mean "any identifier"
+            Token::Identifier(i) if i.is_empty() => write!(f, "identifier"),
+            Token::Identifier(i) => write!(f, "{i}"),
+            Token::Keyword(k) => write!(f, "{k}"),
+            Token::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::String(s) => write!(f, "\"{s}\""),
+            Literal::Number(n) => write!(f, "{n}"),
+            Literal::Boolean(b) => write!(f, "{b}"),
+            Literal::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            Symbol::LeftParen => "(",
+            Symbol::RightParen => ")",
+            Symbol::LeftBrace => "{",
+            Symbol::RightBrace => "}",
+            Symbol::LeftBracket => "[",
+            Symbol::RightBracket => "]",
+            Symbol::Colon => ":",
+            Symbol::Comma => ",",
+            Symbol::Dot => ".",
+            Symbol::Minus => "-",
+            Symbol::Plus => "+",
+            Symbol::Question => "?",
+            Symbol::Semicolon => ";",
+            Symbol::Slash => "/",
+            Symbol::Star => "*",
+            Symbol::Bang => "!",
+            Symbol::BangEqual => "!=",
+            Symbol::Equal => "=",
+            Symbol::EqualEqual => "==",
+            Symbol::Greater => ">",
+            Symbol::GreaterEqual => ">=",
+            Symbol::Less => "<",
+            Symbol::LessEqual => "<=",
+            Symbol::PipeGreater => "|>",
+            Symbol::StarStar => "**",
+            Symbol::QuestionDot => "?.",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+impl Display for Keyword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let word = match self {
+            Keyword::And => "and",
+            Keyword::Assert => "assert",
+            Keyword::Break => "break",
+            Keyword::Catch => "catch",
+            Keyword::Class => "class",
+            Keyword::Continue => "continue",
+            Keyword::Div => "div",
+            Keyword::Do => "do",
+            Keyword::Else => "else",
+            Keyword::Fun => "fun",
+            Keyword::For => "for",
+            Keyword::Foreach => "foreach",
+            Keyword::If => "if",
+            Keyword::In => "in",
+            Keyword::Is => "is",
+            Keyword::Or => "or",
+            Keyword::Print => "print",
+            Keyword::Return => "return",
+            Keyword::Super => "super",
+            Keyword::This => "this",
+            Keyword::Throw => "throw",
+            Keyword::Try => "try",
+            Keyword::Var => "var",
+            Keyword::While => "while",
+            Keyword::Yield => "yield",
+        };
+        write!(f, "{word}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_display_like_source() {
+        assert_eq!(Token::Symbol(Symbol::Semicolon).to_string(), ";");
+        assert_eq!(Token::Symbol(Symbol::StarStar).to_string(), "**");
+        assert_eq!(Token::Keyword(Keyword::And).to_string(), "and");
+        assert_eq!(
+            Token::Literal(Literal::String("hi".into())).to_string(),
+            "\"hi\""
+        );
+        assert_eq!(Token::Literal(Literal::Number(1.5)).to_string(), "1.5");
+        assert_eq!(Token::Literal(Literal::Nil).to_string(), "nil");
+        assert_eq!(Token::Identifier("x".to_string()).to_string(), "x");
+        assert_eq!(Token::Identifier(String::new()).to_string(), "identifier");
+        assert_eq!(Token::Eof.to_string(), "end of file");
+    }
+}
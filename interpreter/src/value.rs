@@ -1,27 +1,56 @@
-use std::{cell::RefCell, fmt::Debug, fmt::Display, rc::Rc};
+use std::{cell::RefCell, cmp::Ordering, collections::BTreeMap, fmt::Debug, fmt::Display, rc::Rc};
 
 use crate::{
     ast::ResolvedStatement,
     environment::Environment,
     error::{RuntimeError, RuntimeErrorKind, WithLocation},
     interpreter::{Interpreter, RuntimeResult},
+    token::Literal,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+/// One level of indentation in Value::pretty_print
+const PRETTY_INDENT: &str = "  ";
+
+pub type Map = BTreeMap<Rc<str>, Value>;
+
+#[derive(Debug, Clone)]
 pub enum Value {
-    String(String),
+    String(Rc<str>),
+    /// What indexing into a string gives
+    Char(char),
     Number(f64),
     Boolean(bool),
     Callable(LoxCallable),
+    /// Lists are shared, so assigning one to another variable doesn't copy it
+    List(Rc<RefCell<Vec<Value>>>),
+    /// Shared like lists. Keys are strings, kept sorted.
+    Map(Rc<RefCell<Map>>),
+    /// A fixed group of values, like the ones `return a, b;` gives. Can't be changed after it's made.
+    Tuple(Rc<[Value]>),
+    /// Raw binary data, like the contents of a file that isn't text. Can't be changed either.
+    Bytes(Rc<[u8]>),
+    /// Calling a class makes an instance of it
+    Class(Rc<LoxClass>),
+    Instance(Rc<Instance>),
+    /// What calling a `fun*` function gives, only good for running with foreach
+    Generator(Rc<Generator>),
     Nil,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     String,
+    Char,
     Number,
     Boolean,
     Callable,
+    List,
+    Map,
+    Tuple,
+    Bytes,
+    Class,
+    Instance,
+    Generator,
     Nil,
 }
 
@@ -29,30 +58,180 @@ impl Value {
     pub fn into_number(self) -> Result<f64, RuntimeErrorKind> {
         match self {
             Value::Number(s) => Ok(s),
-            v => Err(RuntimeErrorKind::TypeError(Type::Number, v.value_type())),
+            v => Err(RuntimeErrorKind::TypeError(Type::Number, v)),
+        }
+    }
+
+    /// A finite number without a fractional part
+    pub fn into_whole_number(self) -> Result<f64, RuntimeErrorKind> {
+        let n = self.into_number()?;
+        if n.fract() != 0.0 || !n.is_finite() {
+            return Err(RuntimeErrorKind::NotAnInteger(n));
+        }
+        Ok(n)
+    }
+
+    /// A whole number that isn't negative, for counts, sizes and the like
+    pub fn into_usize_index(self) -> Result<usize, RuntimeErrorKind> {
+        let n = self.into_whole_number()?;
+        if n < 0.0 {
+            return Err(RuntimeErrorKind::Negative(n));
+        }
+        Ok(n as usize)
+    }
+
+    pub fn into_string(self) -> Result<Rc<str>, RuntimeErrorKind> {
+        match self {
+            Value::String(s) => Ok(s),
+            v => Err(RuntimeErrorKind::TypeError(Type::String, v)),
+        }
+    }
+
+    pub fn into_list(self) -> Result<Rc<RefCell<Vec<Value>>>, RuntimeErrorKind> {
+        match self {
+            Value::List(l) => Ok(l),
+            v => Err(RuntimeErrorKind::TypeError(Type::List, v)),
+        }
+    }
+
+    pub fn into_class(self) -> Result<Rc<LoxClass>, RuntimeErrorKind> {
+        match self {
+            Value::Class(c) => Ok(c),
+            v => Err(RuntimeErrorKind::TypeError(Type::Class, v)),
+        }
+    }
+
+    pub fn into_map(self) -> Result<Rc<RefCell<Map>>, RuntimeErrorKind> {
+        match self {
+            Value::Map(m) => Ok(m),
+            v => Err(RuntimeErrorKind::TypeError(Type::Map, v)),
         }
     }
 
     pub fn into_boolean(self) -> Result<bool, RuntimeErrorKind> {
         match self {
             Value::Boolean(s) => Ok(s),
-            v => Err(RuntimeErrorKind::TypeError(Type::Boolean, v.value_type())),
+            v => Err(RuntimeErrorKind::TypeError(Type::Boolean, v)),
+        }
+    }
+
+    pub fn into_tuple(self) -> Result<Rc<[Value]>, RuntimeErrorKind> {
+        match self {
+            Value::Tuple(t) => Ok(t),
+            v => Err(RuntimeErrorKind::TypeError(Type::Tuple, v)),
+        }
+    }
+
+    pub fn into_bytes(self) -> Result<Rc<[u8]>, RuntimeErrorKind> {
+        match self {
+            Value::Bytes(b) => Ok(b),
+            v => Err(RuntimeErrorKind::TypeError(Type::Bytes, v)),
         }
     }
 
     pub fn into_callable(self) -> Result<LoxCallable, RuntimeErrorKind> {
         match self {
             Value::Callable(s) => Ok(s),
-            v => Err(RuntimeErrorKind::TypeError(Type::Callable, v.value_type())),
+            Value::Class(c) => Ok(LoxCallable::Class(c)),
+            v => Err(RuntimeErrorKind::TypeError(Type::Callable, v)),
+        }
+    }
+
+    /// `nil` and `false` are falsey, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    /// The order used by `<` and the other ordering operators. Numbers, strings and chars compare
+    /// to their own type, and lists compare element by element, with a prefix being less than the whole
+    /// list. None means unordered, which happens when NaN is involved.
+    pub fn partial_compare(&self, other: &Value) -> Result<Option<Ordering>, RuntimeErrorKind> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => Ok(a.partial_cmp(b)),
+            (Value::String(a), Value::String(b)) => Ok(Some(a.cmp(b))),
+            (Value::Char(a), Value::Char(b)) => Ok(Some(a.cmp(b))),
+            (Value::List(a), Value::List(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                for (a, b) in a.iter().zip(b.iter()) {
+                    match a.partial_compare(b)? {
+                        Some(Ordering::Equal) => {}
+                        ordering => return Ok(ordering),
+                    }
+                }
+                Ok(Some(a.len().cmp(&b.len())))
+            }
+            // Left is fine, right must be wrong
+            (Value::String(_), b) => Err(RuntimeErrorKind::TypeError(Type::String, b.clone())),
+            (Value::Char(_), b) => Err(RuntimeErrorKind::TypeError(Type::Char, b.clone())),
+            (Value::List(_), b) => Err(RuntimeErrorKind::TypeError(Type::List, b.clone())),
+            (Value::Number(_), b) => Err(RuntimeErrorKind::TypeError(Type::Number, b.clone())),
+            (a, _) => Err(RuntimeErrorKind::TypeError(Type::Number, a.clone())),
+        }
+    }
+
+    /// Like `==`, but lists and maps are only identical to themselves rather than to anything that
+    /// looks the same
+    pub fn is_identical(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::List(l1), Value::List(l2)) => Rc::ptr_eq(l1, l2),
+            (Value::Map(m1), Value::Map(m2)) => Rc::ptr_eq(m1, m2),
+            (Value::Tuple(t1), Value::Tuple(t2)) => Rc::ptr_eq(t1, t2),
+            (Value::Bytes(b1), Value::Bytes(b2)) => Rc::ptr_eq(b1, b2),
+            // `==` already compares functions by identity
+            (a, b) => a == b,
+        }
+    }
+
+    /// The string form of a value, used everywhere a value is turned into a string (printing,
+    /// concatenation, error messages) so they all agree
+    pub fn to_display_string(&self) -> String {
+        self.to_string()
+    }
+
+    /// Like Display, but nested lists are spread over multiple lines with one element per line.
+    /// `indent` is the nesting level the value itself starts at.
+    pub fn pretty_print(&self, indent: usize) -> String {
+        match self {
+            Value::List(l) if !l.borrow().is_empty() => {
+                let mut out = String::from("[\n");
+                for value in l.borrow().iter() {
+                    out += &PRETTY_INDENT.repeat(indent + 1);
+                    out += &value.pretty_print(indent + 1);
+                    out += ",\n";
+                }
+                out += &PRETTY_INDENT.repeat(indent);
+                out += "]";
+                out
+            }
+            Value::Map(m) if !m.borrow().is_empty() => {
+                let mut out = String::from("{\n");
+                for (key, value) in m.borrow().iter() {
+                    out += &PRETTY_INDENT.repeat(indent + 1);
+                    out += &format!("{key}: {}", value.pretty_print(indent + 1));
+                    out += ",\n";
+                }
+                out += &PRETTY_INDENT.repeat(indent);
+                out += "}";
+                out
+            }
+            v => v.to_display_string(),
         }
     }
 
     pub fn value_type(&self) -> Type {
         match self {
             Value::String(_) => Type::String,
+            Value::Char(_) => Type::Char,
             Value::Number(_) => Type::Number,
             Value::Boolean(_) => Type::Boolean,
             Value::Callable(_) => Type::Callable,
+            Value::List(_) => Type::List,
+            Value::Map(_) => Type::Map,
+            Value::Tuple(_) => Type::Tuple,
+            Value::Bytes(_) => Type::Bytes,
+            Value::Class(_) => Type::Class,
+            Value::Instance(_) => Type::Instance,
+            Value::Generator(_) => Type::Generator,
             Value::Nil => Type::Nil,
         }
     }
@@ -65,10 +244,114 @@ impl Value {
     // }
 }
 
+/// `==` in Lox. Values of different types are never equal. Numbers follow IEEE 754, so
+/// `NaN != NaN` and `-0 == 0`. Strings, lists, maps, tuples and bytes compare by content, classes,
+/// instances and generators by identity.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Callable(a), Value::Callable(b)) => a == b,
+            (Value::List(a), Value::List(b)) => *a.borrow() == *b.borrow(),
+            (Value::Map(a), Value::Map(b)) => *a.borrow() == *b.borrow(),
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::Generator(a), Value::Generator(b)) => Rc::ptr_eq(a, b),
+            (Value::Nil, Value::Nil) => true,
+            // Listed out so that a new variant has to decide how it compares
+            (
+                Value::String(_)
+                | Value::Char(_)
+                | Value::Number(_)
+                | Value::Boolean(_)
+                | Value::Callable(_)
+                | Value::List(_)
+                | Value::Map(_)
+                | Value::Tuple(_)
+                | Value::Bytes(_)
+                | Value::Class(_)
+                | Value::Instance(_)
+                | Value::Generator(_)
+                | Value::Nil,
+                _,
+            ) => false,
+        }
+    }
+}
+
+impl From<&Literal> for Value {
+    fn from(literal: &Literal) -> Self {
+        match literal {
+            Literal::String(s) => Value::String(s.clone()),
+            Literal::Number(n) => Value::Number(*n),
+            Literal::Boolean(b) => Value::Boolean(*b),
+            Literal::Nil => Value::Nil,
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = RuntimeErrorKind;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        value.into_number()
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = RuntimeErrorKind;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        value.into_boolean()
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = RuntimeErrorKind;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        value.into_string().map(|s| s.to_string())
+    }
+}
+
+/// `{}` shows strings as their raw text, the way `print` does. The alternate `{:#}` quotes and
+/// escapes them (including inside lists and maps), so they can't be mistaken for other values.
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Value::String(s) if f.alternate() => write!(f, "{s:?}"),
             Value::String(s) => write!(f, "{s}"),
+            Value::Char(c) if f.alternate() => write!(f, "{c:?}"),
+            Value::Char(c) => write!(f, "{c}"),
             Value::Number(n) => {
                 let n = *n;
                 if n.is_nan() {
@@ -77,9 +360,9 @@ impl Display for Value {
                     write!(f, "Inf")
                 } else if n.is_infinite() && n.is_sign_negative() {
                     write!(f, "-Inf")
-                } else if n.floor() == n {
-                    write!(f, "{}", n as i32)
                 } else {
+                    // Display for f64 already leaves out the `.0` of whole numbers, and keeps the
+                    // sign of -0
                     write!(f, "{n}")
                 }
             }
@@ -90,6 +373,66 @@ impl Display for Value {
             Value::Callable(LoxCallable::LoxFunction { name, .. }) => {
                 write!(f, "<function {name}>")
             }
+            Value::Callable(LoxCallable::GeneratorFunction(function)) => {
+                write!(f, "<generator function {}>", function.name())
+            }
+            Value::Generator(generator) => {
+                write!(f, "<generator {}>", generator.function.name())
+            }
+            Value::Callable(LoxCallable::Composed { outer, inner }) => {
+                write!(f, "<function compose({}, {})>", outer.name(), inner.name())
+            }
+            Value::Class(class) | Value::Callable(LoxCallable::Class(class)) => {
+                write!(f, "<class {}>", class.name)
+            }
+            Value::Instance(instance) => write!(f, "<{} instance>", instance.class.name),
+            Value::List(l) => {
+                write!(f, "[")?;
+                for (i, value) in l.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match f.alternate() {
+                        true => write!(f, "{value:#}")?,
+                        false => write!(f, "{value}")?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Map(m) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in m.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match f.alternate() {
+                        true => write!(f, "{key:?}: {value:#}")?,
+                        false => write!(f, "{key}: {value}")?,
+                    }
+                }
+                write!(f, "}}")
+            }
+            Value::Tuple(t) => {
+                write!(f, "(")?;
+                for (i, value) in t.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match f.alternate() {
+                        true => write!(f, "{value:#}")?,
+                        false => write!(f, "{value}")?,
+                    }
+                }
+                write!(f, ")")
+            }
+            // A hex dump, like `<bytes 4c 6f 78>`
+            Value::Bytes(b) => {
+                write!(f, "<bytes")?;
+                for byte in b.iter() {
+                    write!(f, " {byte:02x}")?;
+                }
+                write!(f, ">")
+            }
             Value::Nil => write!(f, "nil"),
         }
     }
@@ -102,10 +445,54 @@ pub enum LoxCallable {
     LoxFunction {
         name: String,
         params: Vec<String>,
-        body: Vec<ResolvedStatement>,
+        body: Rc<Vec<ResolvedStatement>>,
+        /// A scope inside the globals that holds only the variables the function captures. Each
+        /// one is a cell shared with the scope it was declared in, so assignments on either side
+        /// are seen by the other.
         closure: Rc<RefCell<Environment>>,
     },
-    NativeFunction(String, usize, Function),
+    /// The arity is none for natives that take any number of arguments
+    NativeFunction(String, Option<usize>, Function),
+    /// Made by `compose`. Takes the arguments of inner, and passes its result on to outer.
+    Composed {
+        outer: Rc<LoxCallable>,
+        inner: Rc<LoxCallable>,
+    },
+    /// Calling a class makes a new instance and runs its `init` method on it
+    Class(Rc<LoxClass>),
+    /// A `fun*` function. Calling it doesn't run the body yet, it makes a Value::Generator.
+    GeneratorFunction(Rc<LoxCallable>),
+}
+
+#[derive(Debug)]
+pub struct LoxClass {
+    pub name: String,
+    pub superclass: Option<Rc<LoxClass>>,
+    /// Not bound to any instance yet, see LoxCallable::bind
+    pub methods: BTreeMap<String, LoxCallable>,
+}
+
+impl LoxClass {
+    /// Looks in the superclasses too, nearest first
+    pub fn find_method(&self, name: &str) -> Option<&LoxCallable> {
+        self.methods
+            .get(name)
+            .or_else(|| self.superclass.as_ref()?.find_method(name))
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: Rc<LoxClass>,
+    pub fields: RefCell<Map>,
+}
+
+/// A call to a generator function that hasn't run yet. Every foreach over it runs the body again
+/// from the start, with these arguments.
+#[derive(Debug)]
+pub struct Generator {
+    pub function: LoxCallable,
+    pub args: Vec<Value>,
 }
 
 impl Debug for LoxCallable {
@@ -123,20 +510,52 @@ impl Debug for LoxCallable {
                 .field(arg1)
                 .field(&"<native code>")
                 .finish(),
+            Self::Composed { outer, inner } => f
+                .debug_struct("Composed")
+                .field("outer", outer)
+                .field("inner", inner)
+                .finish(),
+            Self::Class(class) => f.debug_tuple("Class").field(&class.name).finish(),
+            Self::GeneratorFunction(function) => {
+                f.debug_tuple("GeneratorFunction").field(function).finish()
+            }
         }
     }
 }
 
+/// Functions are only equal to themselves. A function declaration creates a new function every
+/// time it runs (with a new closure), so two functions with the same name aren't necessarily the
+/// same function. Compositions have no state of their own, so they're equal when their parts are.
 impl PartialEq for LoxCallable {
     fn eq(&self, other: &LoxCallable) -> bool {
         match (self, other) {
             (
-                LoxCallable::LoxFunction { name: name1, .. },
-                LoxCallable::LoxFunction { name: name2, .. },
-            ) => name1 == name2,
+                LoxCallable::LoxFunction {
+                    body: body1,
+                    closure: closure1,
+                    ..
+                },
+                LoxCallable::LoxFunction {
+                    body: body2,
+                    closure: closure2,
+                    ..
+                },
+            ) => Rc::ptr_eq(body1, body2) && Rc::ptr_eq(closure1, closure2),
             (LoxCallable::NativeFunction(_, _, f1), LoxCallable::NativeFunction(_, _, f2)) => {
                 Rc::ptr_eq(f1, f2)
             }
+            (
+                LoxCallable::Composed {
+                    outer: outer1,
+                    inner: inner1,
+                },
+                LoxCallable::Composed {
+                    outer: outer2,
+                    inner: inner2,
+                },
+            ) => outer1 == outer2 && inner1 == inner2,
+            (LoxCallable::Class(c1), LoxCallable::Class(c2)) => Rc::ptr_eq(c1, c2),
+            (LoxCallable::GeneratorFunction(f1), LoxCallable::GeneratorFunction(f2)) => f1 == f2,
             _ => false,
         }
     }
@@ -149,39 +568,225 @@ impl LoxCallable {
         args: Vec<Value>,
         call_location: (usize, usize),
     ) -> RuntimeResult<Value> {
+        let (mut callee, mut args, mut call_location) = (self, args, call_location);
+        // A tail call replaces the current call, so it loops here instead of recursing
+        loop {
+            match callee {
+                LoxCallable::LoxFunction {
+                    params,
+                    body,
+                    closure,
+                    ..
+                } => {
+                    let mut old_env = Environment::new_inside(closure);
+                    std::mem::swap(&mut old_env, &mut interpreter.environment);
+                    for (param, arg) in params.into_iter().zip(args) {
+                        interpreter.environment.borrow_mut().define(param, arg)
+                    }
+                    let ans = interpreter.execute_block_statement(&body);
+                    std::mem::swap(&mut old_env, &mut interpreter.environment);
+                    match ans {
+                        Ok(()) => return Ok(Value::Nil),
+                        Err(RuntimeError {
+                            value: RuntimeErrorKind::Returning(v),
+                            ..
+                        }) => return Ok(v),
+                        Err(RuntimeError {
+                            location,
+                            value: RuntimeErrorKind::TailCall(next, next_args),
+                        }) => {
+                            callee = next;
+                            args = next_args;
+                            call_location = location;
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                LoxCallable::NativeFunction(_, _, fun) => {
+                    return fun(interpreter, args).with_location(call_location)
+                }
+                LoxCallable::Class(class) => {
+                    let instance = Value::Instance(Rc::new(Instance {
+                        class: class.clone(),
+                        fields: RefCell::new(Map::new()),
+                    }));
+                    if let Some(init) = class.find_method("init") {
+                        init.bind(instance.clone())
+                            .call(interpreter, args, call_location)?;
+                    }
+                    return Ok(instance);
+                }
+                LoxCallable::GeneratorFunction(function) => {
+                    return Ok(Value::Generator(Rc::new(Generator {
+                        function: Rc::unwrap_or_clone(function),
+                        args,
+                    })));
+                }
+                LoxCallable::Composed { outer, inner } => {
+                    let value =
+                        Rc::unwrap_or_clone(inner).call(interpreter, args, call_location)?;
+                    callee = Rc::unwrap_or_clone(outer);
+                    args = vec![value];
+                }
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            LoxCallable::LoxFunction { name, .. } => name,
+            LoxCallable::NativeFunction(name, ..) => name,
+            LoxCallable::Composed { .. } => "compose",
+            LoxCallable::Class(class) => &class.name,
+            LoxCallable::GeneratorFunction(function) => function.name(),
+        }
+    }
+
+    pub fn arity(&self) -> Option<usize> {
+        match self {
+            LoxCallable::LoxFunction { params, .. } => Some(params.len()),
+            LoxCallable::NativeFunction(_, a, _) => *a,
+            LoxCallable::Composed { inner, .. } => inner.arity(),
+            LoxCallable::Class(class) => match class.find_method("init") {
+                Some(init) => init.arity(),
+                None => Some(0),
+            },
+            LoxCallable::GeneratorFunction(function) => function.arity(),
+        }
+    }
+
+    /// A method with `this` set to the given instance
+    pub fn bind(&self, instance: Value) -> LoxCallable {
         match self {
             LoxCallable::LoxFunction {
+                name,
                 params,
                 body,
                 closure,
-                ..
             } => {
-                let mut old_env = Environment::new_inside(closure);
-                std::mem::swap(&mut old_env, &mut interpreter.environment);
-                for (param, arg) in params.into_iter().zip(args.into_iter()) {
-                    interpreter.environment.borrow_mut().define(param, arg)
-                }
-                let ans = interpreter.execute_block_statement(body);
-                std::mem::swap(&mut old_env, &mut interpreter.environment);
-                match ans {
-                    Ok(()) => Ok(Value::Nil),
-                    Err(RuntimeError {
-                        value: RuntimeErrorKind::Returning(v),
-                        ..
-                    }) => Ok(v),
-                    Err(e) => Err(e),
+                let closure = Environment::new_inside(closure.clone());
+                closure.borrow_mut().define("this".to_string(), instance);
+                LoxCallable::LoxFunction {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure,
                 }
             }
-            LoxCallable::NativeFunction(_, _, fun) => {
-                fun(interpreter, args).with_location(call_location)
-            }
+            // Only functions declared in a class are methods
+            callable => callable.clone(),
         }
     }
+}
 
-    pub fn arity(&self) -> usize {
-        match self {
-            LoxCallable::LoxFunction { params, .. } => params.len(),
-            LoxCallable::NativeFunction(_, a, _) => *a,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_round_trip() {
+        assert_eq!(f64::try_from(Value::from(1.5)).unwrap(), 1.5);
+        assert!(bool::try_from(Value::from(true)).unwrap());
+        assert_eq!(String::try_from(Value::from("text")).unwrap(), "text");
+        assert_eq!(
+            String::try_from(Value::from("owned".to_string())).unwrap(),
+            "owned"
+        );
+    }
+
+    #[test]
+    fn conversion_of_the_wrong_type_is_a_type_error() {
+        assert!(matches!(
+            f64::try_from(Value::from("1")),
+            Err(RuntimeErrorKind::TypeError(Type::Number, Value::String(_)))
+        ));
+        assert!(matches!(
+            bool::try_from(Value::Nil),
+            Err(RuntimeErrorKind::TypeError(Type::Boolean, Value::Nil))
+        ));
+        assert!(matches!(
+            String::try_from(Value::from(1.0)),
+            Err(RuntimeErrorKind::TypeError(Type::String, Value::Number(_)))
+        ));
+    }
+
+    #[test]
+    fn usize_index_requires_a_non_negative_whole_number() {
+        assert_eq!(Value::Number(3.0).into_usize_index().unwrap(), 3);
+        assert_eq!(Value::Number(-0.0).into_usize_index().unwrap(), 0);
+        assert!(matches!(
+            Value::Number(1.5).into_usize_index(),
+            Err(RuntimeErrorKind::NotAnInteger(n)) if n == 1.5
+        ));
+        assert!(matches!(
+            Value::Number(-1.0).into_usize_index(),
+            Err(RuntimeErrorKind::Negative(n)) if n == -1.0
+        ));
+        assert!(matches!(
+            Value::Number(f64::NAN).into_usize_index(),
+            Err(RuntimeErrorKind::NotAnInteger(n)) if n.is_nan()
+        ));
+        assert!(matches!(
+            Value::Number(f64::INFINITY).into_usize_index(),
+            Err(RuntimeErrorKind::NotAnInteger(n)) if n == f64::INFINITY
+        ));
+        assert!(matches!(
+            Value::from("3").into_usize_index(),
+            Err(RuntimeErrorKind::TypeError(Type::Number, _))
+        ));
+    }
+
+    #[test]
+    fn display_strings() {
+        for (value, expected) in [
+            (Value::Number(3.0), "3"),
+            (Value::Number(0.5), "0.5"),
+            (Value::Number(f64::NAN), "NaN"),
+            (Value::Number(f64::INFINITY), "Inf"),
+            (Value::Number(f64::NEG_INFINITY), "-Inf"),
+            (Value::Number(-0.0), "-0"),
+            (Value::Number(0.0), "0"),
+            (Value::Boolean(true), "true"),
+            (Value::Nil, "nil"),
+        ] {
+            assert_eq!(value.to_display_string(), expected);
         }
     }
+
+    #[test]
+    fn plain_display_leaves_strings_unquoted() {
+        let list = Value::List(Rc::new(RefCell::new(vec![
+            Value::from("a"),
+            Value::Char('b'),
+        ])));
+        let map = Value::Map(Rc::new(RefCell::new(Map::from([(
+            Rc::from("key"),
+            Value::from("value"),
+        )]))));
+        assert_eq!(format!("{}", Value::from("hi")), "hi");
+        assert_eq!(format!("{}", Value::Char('c')), "c");
+        assert_eq!(format!("{list}"), "[a, b]");
+        assert_eq!(format!("{map}"), "{key: value}");
+    }
+
+    #[test]
+    fn alternate_display_quotes_and_escapes_strings() {
+        let list = Value::List(Rc::new(RefCell::new(vec![
+            Value::from("a"),
+            Value::Char('b'),
+        ])));
+        let map = Value::Map(Rc::new(RefCell::new(Map::from([(
+            Rc::from("key"),
+            Value::from("value"),
+        )]))));
+        let tuple = Value::Tuple(Rc::from([Value::from("x"), Value::Number(1.0)]));
+        assert_eq!(format!("{:#}", Value::from("hi")), r#""hi""#);
+        assert_eq!(format!("{:#}", Value::from("a\"b\n")), r#""a\"b\n""#);
+        assert_eq!(format!("{:#}", Value::Char('c')), "'c'");
+        assert_eq!(format!("{list:#}"), r#"["a", 'b']"#);
+        assert_eq!(format!("{map:#}"), r#"{"key": "value"}"#);
+        assert_eq!(format!("{tuple:#}"), r#"("x", 1)"#);
+        assert_eq!(format!("{:#}", Value::Number(2.5)), "2.5");
+        assert_eq!(format!("{:#}", Value::Nil), "nil");
+    }
 }
@@ -0,0 +1,40 @@
+mod common;
+
+use common::{run, run_err};
+use rlox::interpreter::Interpreter;
+
+#[test]
+fn passing_assertion_continues() {
+    assert_eq!(
+        run("assert 1 < 2, \"math is broken\"; print \"after\";"),
+        "after\n"
+    );
+}
+
+#[test]
+fn failing_assertion_reports_its_message() {
+    let error = run_err("var x = 3;\nassert x == 4, \"x is \" + x;");
+    assert!(error.contains("assertion failed: x is 3"), "{error}");
+}
+
+#[test]
+fn failing_assertion_without_message() {
+    let error = run_err("assert nil;");
+    assert!(error.ends_with("assertion failed"), "{error}");
+}
+
+#[test]
+fn disabled_assertions_are_not_evaluated() {
+    let mut interpreter = Interpreter::new();
+    interpreter.skip_assertions = true;
+    let source = "
+var evaluated = false;
+fun mark() { evaluated = true; return false; }
+assert mark(), mark();
+evaluated;
+";
+    assert_eq!(
+        common::interpret(source, &mut interpreter),
+        Ok(false.into())
+    );
+}
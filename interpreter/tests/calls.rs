@@ -0,0 +1,117 @@
+mod common;
+
+use common::{run, run_err, Options};
+use rlox::{
+    ast::{Expression, ResolvedStatement},
+    interpreter::Interpreter,
+};
+
+#[test]
+fn wrong_argument_count_names_the_function() {
+    assert!(run_err("fun add(a, b) { return a + b; } add(1);")
+        .contains("wrong number of arguments to `add`: got 1, expected 2"));
+    assert!(
+        run_err("clock(1);").contains("wrong number of arguments to `clock`: got 1, expected 0")
+    );
+}
+
+/// The return statements in the body of the first statement, a function
+fn returns(source: &str) -> Vec<(bool, bool)> {
+    let program = common::compile(source, &Interpreter::new(), &Options::default()).unwrap();
+    let [ResolvedStatement::Function(_, _, body, ..)] = program.as_slice() else {
+        panic!("expected a single function");
+    };
+    let mut found = Vec::new();
+    collect_returns(body, &mut found);
+    found
+}
+
+/// Whether each return is a call, and whether it's marked as a tail call
+fn collect_returns(statements: &[ResolvedStatement], found: &mut Vec<(bool, bool)>) {
+    for statement in statements {
+        match statement {
+            ResolvedStatement::Return(value, tail) => found.push((
+                matches!(&value.value, Some(e) if matches!(e.value, Expression::Call(..))),
+                *tail,
+            )),
+            ResolvedStatement::Block(body) => collect_returns(body, found),
+            ResolvedStatement::TryCatch(body, _, handler) => {
+                collect_returns(body, found);
+                collect_returns(handler, found);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn only_calls_outside_of_try_blocks_are_tail_calls() {
+    let source = "
+fun f(n) {
+  try {
+    return f(n - 1);
+  } catch (e) {
+    return f(n - 2);
+  }
+  return 1 + f(n);
+  return f(n);
+}
+";
+    assert_eq!(
+        returns(source),
+        [(true, false), (true, true), (false, false), (true, true)]
+    );
+}
+
+#[test]
+fn tail_recursion_does_not_overflow() {
+    let source = "
+fun countdown(n) {
+  if (n == 0) return \"done\";
+  return countdown(n - 1);
+}
+print countdown(1000000);
+";
+    assert_eq!(run(source), "done\n");
+}
+
+#[test]
+fn error_from_returned_call_is_caught_in_try_block() {
+    let source = "
+fun fail() { throw \"oops\"; }
+fun f() {
+  try {
+    return fail();
+  } catch (e) {
+    return \"caught \" + e;
+  }
+}
+print f();
+";
+    assert_eq!(run(source), "caught oops\n");
+}
+
+#[test]
+fn call_that_is_not_in_tail_position_still_nests() {
+    let source = "
+fun sum(n) {
+  if (n == 0) return 0;
+  return n + sum(n - 1);
+}
+print sum(100);
+";
+    assert_eq!(run(source), "5050\n");
+}
+
+#[test]
+fn named_functions_and_lambdas() {
+    let source = "
+fun named(x) { return x; }
+print named(1);
+print (fun (x) { return x; })(5);
+var double = fun (x) { return x * 2; };
+print double(3);
+fun (x) { print x; }(7);
+";
+    assert_eq!(run(source), "1\n5\n6\n7\n");
+}
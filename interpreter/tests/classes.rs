@@ -0,0 +1,158 @@
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn this_at_top_level_is_a_clear_error() {
+    let error = run_err("print this;");
+    assert!(
+        error.contains("can't use `this` outside of a class"),
+        "{error}"
+    );
+    assert!(!error.contains("invalid expression"), "{error}");
+}
+
+#[test]
+fn this_in_a_function_outside_of_a_class() {
+    let error = run_err("fun f() { return this; }");
+    assert!(
+        error.contains("can't use `this` outside of a class"),
+        "{error}"
+    );
+}
+
+#[test]
+fn super_at_top_level_is_a_clear_error() {
+    let error = run_err("super.method();");
+    assert!(
+        error.contains("can't use `super` outside of a class"),
+        "{error}"
+    );
+}
+
+#[test]
+fn super_without_a_superclass() {
+    let error = run_err("class A { m() { return super.m(); } }");
+    assert!(
+        error.contains("can't use `super` in a class with no superclass"),
+        "{error}"
+    );
+}
+
+#[test]
+fn this_and_super_inside_methods() {
+    let source = "
+class A { name() { return \"A\"; } }
+class B < A {
+  init() { this.prefix = \"B of \"; }
+  name() { return this.prefix + super.name(); }
+}
+print B().name();
+";
+    assert_eq!(run(source), "B of A\n");
+}
+
+#[test]
+fn instances_have_fields_and_methods() {
+    let source = "
+class Point {
+  init(x) { this.x = x; }
+  get() { return this.x; }
+}
+var p = Point(3);
+print p.get();
+p.x = 4;
+print p.get();
+print p;
+print Point;
+";
+    assert_eq!(run(source), "3\n4\n<Point instance>\n<class Point>\n");
+}
+
+#[test]
+fn each_instance_has_its_own_fields() {
+    let error = run_err("class A {} var a = A(); a.f = 1; var b = A(); print b.f;");
+    assert!(error.contains("instance has no property `f`"), "{error}");
+}
+
+#[test]
+fn method_bodies_are_scopes() {
+    let source = "
+class A {
+  m() {
+    var x = 1;
+    { var x = 2; return x; }
+  }
+}
+var x = 0;
+print A().m();
+print x;
+";
+    assert_eq!(run(source), "2\n0\n");
+}
+
+#[test]
+fn calling_a_class_checks_the_initializer_arity() {
+    let error = run_err("class A { init(a) {} } A();");
+    assert!(
+        error.contains("wrong number of arguments to `A`: got 0, expected 1"),
+        "{error}"
+    );
+    let error = run_err("class A {} A(1);");
+    assert!(
+        error.contains("wrong number of arguments to `A`: got 1, expected 0"),
+        "{error}"
+    );
+}
+
+#[test]
+fn methods_are_inherited() {
+    let source = "
+class A { hi() { return \"A\"; } }
+class B < A {}
+print B().hi();
+";
+    assert_eq!(run(source), "A\n");
+}
+
+#[test]
+fn super_starts_at_the_superclass_of_the_method() {
+    let source = "
+class A { hi() { return \"A\"; } }
+class B < A { hi() { return \"B\" + super.hi(); } }
+class C < B { hi() { return \"C\" + super.hi(); } }
+print C().hi();
+";
+    assert_eq!(run(source), "CBA\n");
+}
+
+#[test]
+fn super_binds_the_current_instance() {
+    let source = "
+class A {
+  init(n) { this.n = n; }
+  describe() { return this.name + \" \" + this.n; }
+}
+class B < A {
+  init() { this.name = \"b\"; super.init(7); }
+  describe() { var f = super.describe; return f(); }
+}
+print B().describe();
+";
+    assert_eq!(run(source), "b 7\n");
+}
+
+#[test]
+fn class_cant_inherit_from_itself() {
+    let error = run_err("class A < A {}");
+    assert!(
+        error.contains("class `A` can't inherit from itself"),
+        "{error}"
+    );
+}
+
+#[test]
+fn superclass_must_be_a_class() {
+    let error = run_err("var x = 1; class A < x {}");
+    assert!(error.contains("expected type Class, got Number"), "{error}");
+}
@@ -0,0 +1,193 @@
+//! Runs the `rlox` binary itself, for behavior that lives in main.rs
+
+use std::process::{Command, Output};
+
+fn rlox(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("run rlox")
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn error_without_location_is_reported_without_source_line() {
+    let output = rlox(&["--eval", "{ var a; var a; }"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output),
+        "[0:0] Error: already a variable with this name in this scope\n"
+    );
+}
+
+#[test]
+fn error_at_end_of_source_points_at_it() {
+    let output = rlox(&["--eval", "var a = 1;\n\"abc"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output),
+        "[2:1] Error: unterminated string\n\"abc\n^\n"
+    );
+}
+
+#[test]
+fn strict_checks_only_apply_under_strict() {
+    let output = rlox(&["--eval", "print 1 / 0;"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Inf\n");
+    assert_eq!(stderr(&output), "");
+
+    let output = rlox(&["--strict", "--eval", "print 1 / 0;"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert!(stderr(&output).contains("Error: division by zero"));
+}
+
+#[test]
+fn no_assertions_skips_failing_assertions() {
+    let output = rlox(&["--no-assertions", "--eval", "assert false; print \"ran\";"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ran\n");
+    assert_eq!(stderr(&output), "");
+    let output = rlox(&["--eval", "assert false; print \"ran\";"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert!(stderr(&output).contains("assertion failed"));
+}
+
+#[test]
+fn color_never_has_no_escape_codes() {
+    let output = rlox(&["--color", "never", "--eval", "print 1 +;"]);
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Error:"), "{stderr}");
+    assert!(!stderr.contains('\x1b'), "{stderr:?}");
+}
+
+#[test]
+fn color_always_colors_the_label_location_and_caret() {
+    // Always wins over NO_COLOR, which rlox() sets
+    let output = rlox(&["--color", "always", "--eval", "print 1 +;"]);
+    assert_eq!(
+        stderr(&output),
+        "\x1b[1m[1:10]\x1b[0m \x1b[31mError:\x1b[0m invalid expression\nprint 1 +;\n         \x1b[31m^\x1b[0m\n"
+    );
+}
+
+#[test]
+fn color_auto_respects_no_color() {
+    let output = rlox(&["--color", "auto", "--eval", "print 1 +;"]);
+    assert!(!stderr(&output).contains('\x1b'));
+}
+
+#[test]
+fn color_expects_a_known_value() {
+    let output = rlox(&["--color", "sometimes", "--eval", "1;"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("`--color` expects `auto`, `always` or `never`"));
+}
+
+#[test]
+fn comma_operator_flag() {
+    let output = rlox(&["--comma-operator", "--eval", "print (1, 2);"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    let output = rlox(&["--eval", "print (1, 2);"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "(1, 2)\n");
+}
+
+const CLOSURE: &str = "
+fun outer() {
+  var a = 1;
+  var b = 2;
+  fun inner() { return a; }
+  return inner() + b;
+}
+print outer();
+";
+
+#[test]
+fn dump_captures_reports_the_captured_variable() {
+    let output = rlox(&["--dump-captures", "--eval", CLOSURE]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    assert_eq!(
+        stderr(&output),
+        "[captures] outer: (none)\n[captures] inner: a\n"
+    );
+}
+
+#[test]
+fn dump_captures_is_not_skipped_by_the_cache() {
+    let dir = std::env::temp_dir().join(format!("rlox-captures-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("closure.lox");
+    std::fs::write(&script, CLOSURE).unwrap();
+    let script = script.to_str().unwrap();
+    // The first run would save a cache that the second run loads instead of resolving
+    for _ in 0..2 {
+        let output = rlox(&["--cache", "--dump-captures", script]);
+        assert!(stderr(&output).contains("[captures] inner: a"));
+    }
+    assert!(!dir.join("closure.lox.cache").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn eval_runs_the_given_code() {
+    let output = rlox(&["--eval", "print 1 + 2;"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn eval_exits_the_same_way_as_a_file() {
+    let dir = std::env::temp_dir().join(format!("rlox-eval-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("error.lox");
+    let source = "print 1;\nprint nil();";
+    std::fs::write(&script, source).unwrap();
+    let from_file = rlox(&[script.to_str().unwrap()]);
+    let from_eval = rlox(&["--eval", source]);
+    assert_eq!(from_eval.status.code(), from_file.status.code());
+    assert_eq!(from_eval.stdout, from_file.stdout);
+    assert_eq!(stderr(&from_eval), stderr(&from_file));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn eval_with_a_file_is_an_error() {
+    let output = rlox(&["--eval", "print 1;", "script.lox"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        stderr(&output),
+        "Error: `--eval` can't be used together with a file\n"
+    );
+    let output = rlox(&["--eval"]);
+    assert!(!output.status.success());
+    assert_eq!(stderr(&output), "Error: `--eval` expects the code to run\n");
+}
+
+#[test]
+fn tab_width_lines_the_caret_up_with_the_expanded_line() {
+    let output = rlox(&["--tab-width", "4", "--eval", "var a = 1;\n\t  @"]);
+    assert_eq!(
+        stderr(&output),
+        "[2:7] Error: unexpected start of token: `@`\n      @\n      ^\n"
+    );
+}
+
+#[test]
+fn tab_width_must_be_positive() {
+    let output = rlox(&["--tab-width", "0", "--eval", "1;"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("`--tab-width` expects a positive number"));
+}
+
+#[test]
+fn lint_indent_warns_without_stopping_the_program() {
+    let source = "{\n\t print 1;\n}";
+    let output = rlox(&["--lint-indent", "--eval", source]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(stderr(&output).contains("[2:1] Warning: indentation mixes tabs and spaces"));
+    let output = rlox(&["--eval", source]);
+    assert_eq!(stderr(&output), "");
+}
@@ -0,0 +1,106 @@
+//! Runs Lox source through the whole pipeline the way the binary does
+#![allow(dead_code)]
+
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use rlox::{
+    ast::ResolvedStatement, error::LoxError, interpreter::Interpreter, optimizer, parser::Parser,
+    resolver::Resolver, scanner::Scanner, value::Value,
+};
+
+/// Flags that change how a program is read, like the command line ones
+#[derive(Default)]
+pub struct Options {
+    pub print_as_function: bool,
+    pub comma_operator: bool,
+}
+
+/// An output that can still be read after it's been given to an interpreter
+#[derive(Clone, Default)]
+pub struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).expect("output is utf-8")
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn errors<E: Into<LoxError>>(errors: Vec<E>) -> String {
+    errors
+        .into_iter()
+        .map(|e| e.into().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Scans, parses, resolves and optimizes a program for the given interpreter
+pub fn compile(
+    source: &str,
+    interpreter: &Interpreter,
+    options: &Options,
+) -> Result<Vec<ResolvedStatement>, String> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.print_as_function = options.print_as_function;
+    let tokens = scanner.tokens().map_err(errors)?;
+    let mut parser = Parser::new(tokens);
+    parser.comma_operator = options.comma_operator;
+    let ast = parser.parse().map_err(errors)?;
+    let mut resolver = Resolver::new();
+    resolver.define_globals(interpreter.global_names());
+    let ast = resolver.resolve(ast).map_err(errors)?;
+    Ok(optimizer::optimize(ast, interpreter.pure_natives()))
+}
+
+/// Runs a program with an existing interpreter, resulting in the value of its last statement
+pub fn interpret(source: &str, interpreter: &mut Interpreter) -> Result<Value, String> {
+    let program = compile(source, interpreter, &Options::default())?;
+    interpreter
+        .interpret(program)
+        .map_err(|e| LoxError::from(e).to_string())
+}
+
+/// Runs a program, resulting in what it printed
+pub fn run_result(source: &str, options: &Options) -> Result<String, String> {
+    let output = Output::default();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Box::new(output.clone()));
+    let program = compile(source, &interpreter, options)?;
+    interpreter
+        .interpret(program)
+        .map_err(|e| LoxError::from(e).to_string())?;
+    Ok(output.text())
+}
+
+/// Runs a program that should succeed, returning what it printed
+pub fn run(source: &str) -> String {
+    run_with(source, &Options::default())
+}
+
+pub fn run_with(source: &str, options: &Options) -> String {
+    run_result(source, options).unwrap_or_else(|e| panic!("program failed: {e}"))
+}
+
+/// Runs a program that should fail, returning the error it failed with
+pub fn run_err(source: &str) -> String {
+    match run_result(source, &Options::default()) {
+        Ok(output) => panic!("program succeeded, printing {output:?}"),
+        Err(e) => e,
+    }
+}
+
+/// The value of a single expression
+pub fn eval(source: &str) -> Value {
+    interpret(&format!("{source};"), &mut Interpreter::new())
+        .unwrap_or_else(|e| panic!("program failed: {e}"))
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn catch_a_type_error() {
+    let source = "
+try {
+  print 1 + nil;
+  print \"not reached\";
+} catch (e) {
+  print \"caught: \" + e;
+}
+";
+    assert_eq!(
+        run(source),
+        "caught: expected types [Number, String], got Nil\n"
+    );
+}
+
+#[test]
+fn return_is_not_caught() {
+    let source = "
+fun f() {
+  try {
+    return \"returned\";
+  } catch (e) {
+    return \"caught\";
+  }
+}
+print f();
+";
+    assert_eq!(run(source), "returned\n");
+}
+
+#[test]
+fn break_is_not_caught() {
+    let source = "
+while (true) {
+  try {
+    break;
+  } catch (e) {
+    print \"caught\";
+  }
+}
+print \"after\";
+";
+    assert_eq!(run(source), "after\n");
+}
+
+#[test]
+fn catch_variable_is_scoped_to_the_handler() {
+    let source = "
+var e = \"outer\";
+try { nil(); } catch (e) { print \"inner\"; }
+print e;
+";
+    assert_eq!(run(source), "inner\nouter\n");
+}
+
+#[test]
+fn throw_and_catch_a_string() {
+    let source = "
+try {
+  throw \"oops\";
+} catch (e) {
+  print e + \"!\";
+}
+";
+    assert_eq!(run(source), "oops!\n");
+}
+
+#[test]
+fn throw_and_catch_a_number() {
+    let source = "
+fun fail() { throw 42; }
+try {
+  fail();
+} catch (e) {
+  print e + 1;
+}
+";
+    assert_eq!(run(source), "43\n");
+}
+
+#[test]
+fn uncaught_throw_is_reported_as_an_error() {
+    assert_eq!(run_err("var a;\nthrow \"oops\";"), "[2:7] Error: oops");
+}
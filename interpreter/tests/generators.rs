@@ -0,0 +1,135 @@
+mod common;
+
+use common::{run, run_err};
+
+const COUNT: &str = "
+fun* count() {
+  yield 1;
+  yield 2;
+  yield 3;
+}
+";
+
+#[test]
+fn generator_yields_1_2_3() {
+    let source = format!("{COUNT} foreach (x in count()) print x;");
+    assert_eq!(run(&source), "1\n2\n3\n");
+}
+
+#[test]
+fn calling_a_generator_doesnt_run_it() {
+    let source =
+        format!("{COUNT} var g = count(); print count; print g; foreach (x in g) print x;");
+    assert_eq!(
+        run(&source),
+        "<generator function count>\n<generator count>\n1\n2\n3\n"
+    );
+}
+
+#[test]
+fn break_and_continue_in_the_foreach_body() {
+    let source = "
+fun* up_to(n) {
+  var i = 0;
+  while (i < n) { yield i; i = i + 1; }
+}
+foreach (x in up_to(10)) {
+  if (x == 1) continue;
+  if (x == 4) break;
+  print x;
+}
+";
+    assert_eq!(run(source), "0\n2\n3\n");
+}
+
+#[test]
+fn return_from_the_foreach_body() {
+    let source = "
+fun* up_to(n) {
+  var i = 0;
+  while (i < n) { yield i; i = i + 1; }
+}
+fun first_over(limit) {
+  foreach (x in up_to(100)) {
+    if (x > limit) return x;
+  }
+}
+print first_over(41);
+";
+    assert_eq!(run(source), "42\n");
+}
+
+#[test]
+fn generator_cant_catch_errors_from_the_foreach_body() {
+    let source = "
+fun* guarded() {
+  try { yield 1; yield 2; } catch (e) { print \"generator caught\"; }
+  print \"not reached\";
+}
+try {
+  foreach (x in guarded()) { throw \"boom\"; }
+} catch (e) { print \"outside caught \" + e; }
+";
+    assert_eq!(run(source), "outside caught boom\n");
+}
+
+#[test]
+fn generator_can_yield_from_its_own_foreach() {
+    let source = format!(
+        "{COUNT}
+fun* tens() {{ foreach (x in count()) {{ yield x * 10; }} }}
+foreach (y in tens()) print y;
+"
+    );
+    assert_eq!(run(&source), "10\n20\n30\n");
+}
+
+#[test]
+fn labeled_break_out_of_nested_foreach() {
+    let source = format!(
+        "{COUNT}
+outer: {{
+  foreach (x in count()) {{
+    foreach (y in count()) {{
+      if (y == 2) break outer;
+      print (x, y);
+    }}
+  }}
+}}
+"
+    );
+    assert_eq!(run(&source), "(1, 1)\n");
+}
+
+#[test]
+fn foreach_over_lists_and_tuples() {
+    assert_eq!(
+        run("foreach (x in [1, 2]) print x; foreach (x in (3, 4)) print x;"),
+        "1\n2\n3\n4\n"
+    );
+    let error = run_err("foreach (x in 5) print x;");
+    assert!(
+        error.contains("expected types [List, Tuple, Generator], got Number"),
+        "{error}"
+    );
+}
+
+#[test]
+fn yield_outside_of_a_generator() {
+    for source in ["yield 1;", "fun f() { yield 1; }"] {
+        let error = run_err(source);
+        assert!(
+            error.contains("can't use `yield` outside of a generator"),
+            "{error}"
+        );
+    }
+}
+
+#[test]
+fn generator_cant_return_a_value() {
+    let error = run_err("fun* g() { return 1; }");
+    assert!(
+        error.contains("can't return a value from a generator"),
+        "{error}"
+    );
+}
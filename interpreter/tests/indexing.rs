@@ -0,0 +1,60 @@
+mod common;
+
+use common::{eval, run, run_err};
+use rlox::value::Value;
+
+#[test]
+fn string_index_is_a_char() {
+    assert_eq!(eval(r#""hello"[1]"#), Value::Char('e'));
+    assert_eq!(run(r#"print "hello"[1]; print "hello"[0];"#), "e\nh\n");
+}
+
+#[test]
+fn string_index_counts_characters_not_bytes() {
+    assert_eq!(run(r#"print "héllo"[1]; print "héllo"[2];"#), "é\nl\n");
+}
+
+#[test]
+fn string_index_out_of_range() {
+    assert!(run_err(r#""hello"[5];"#).contains("index 5 is out of bounds for length 5"));
+}
+
+#[test]
+fn negative_string_index_counts_from_the_end() {
+    assert_eq!(run(r#"print "hello"[-1]; print "hello"[-5];"#), "o\nh\n");
+    assert!(run_err(r#""hello"[-6];"#).contains("index -6 is out of bounds for length 5"));
+}
+
+#[test]
+fn fractional_string_index() {
+    assert!(run_err(r#""hello"[1.5];"#).contains("expected a whole number, got 1.5"));
+}
+
+#[test]
+fn chars_concatenate_into_strings() {
+    assert_eq!(eval(r#""ab"[0] + "ab"[1]"#), Value::String("ab".into()));
+    assert_eq!(eval(r#""ab"[0] + "cd""#), Value::String("acd".into()));
+    assert_eq!(eval(r#""ab" + "cd"[1]"#), Value::String("abd".into()));
+    assert!(run_err(r#""ab"[0] + 1;"#).contains("expected types [String, Char], got Number (1)"));
+}
+
+#[test]
+fn chars_compare_in_char_order() {
+    assert_eq!(eval(r#""ab"[0] < "ab"[1]"#), Value::Boolean(true));
+    assert_eq!(eval(r#""ab"[1] > "ab"[0]"#), Value::Boolean(true));
+    assert_eq!(eval(r#""Za"[0] < "Za"[1]"#), Value::Boolean(true));
+    assert_eq!(eval(r#""aa"[0] == "aa"[1]"#), Value::Boolean(true));
+    // A char isn't the same value as a one character string
+    assert_eq!(eval(r#""a"[0] == "a""#), Value::Boolean(false));
+    assert!(run_err(r#""a"[0] < "b";"#).contains("expected type Char, got String (\"b\")"));
+}
+
+#[test]
+fn every_integer_context_rejects_fractions() {
+    for source in [r#"[1, 2][1.5];"#, r#""ab" * 1.5;"#, "range(1.5, 3);"] {
+        assert!(
+            run_err(source).contains("expected a whole number, got 1.5"),
+            "{source}"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::interpret;
+use rlox::{interpreter::Interpreter, value::Value};
+
+#[test]
+fn value_of_the_last_expression_statement() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(
+        interpret("var x = 1; x + 2;", &mut interpreter),
+        Ok(Value::Number(3.0))
+    );
+}
+
+#[test]
+fn program_not_ending_in_an_expression_is_nil() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(
+        interpret("1 + 2; var x = 1;", &mut interpreter),
+        Ok(Value::Nil)
+    );
+    assert_eq!(interpret("", &mut interpreter), Ok(Value::Nil));
+}
+
+#[test]
+fn globals_persist_between_programs() {
+    let mut interpreter = Interpreter::new();
+    interpret("var x = 1;", &mut interpreter).unwrap();
+    assert_eq!(
+        interpret("x * 10;", &mut interpreter),
+        Ok(Value::Number(10.0))
+    );
+}
+
+#[test]
+fn failed_statement_leaves_no_partially_assigned_globals() {
+    let mut interpreter = Interpreter::new();
+    interpreter.rollback_failed_statements = true;
+    interpret("var x = 1; var y = 1;", &mut interpreter).unwrap();
+    let source = "
+x = 2;
+fun change() { y = 10; x = 10; return nil + 1; }
+change();
+";
+    assert!(interpret(source, &mut interpreter).is_err());
+    // Statements before the failed one keep their effects
+    assert_eq!(interpret("x;", &mut interpreter), Ok(Value::Number(2.0)));
+    assert_eq!(interpret("y;", &mut interpreter), Ok(Value::Number(1.0)));
+    assert!(interpret("change;", &mut interpreter).is_ok());
+}
+
+#[test]
+fn failed_declaration_is_not_defined() {
+    let mut interpreter = Interpreter::new();
+    interpreter.rollback_failed_statements = true;
+    assert!(interpret("var half = nil + 1;", &mut interpreter).is_err());
+    let error = interpret("half;", &mut interpreter).unwrap_err();
+    assert!(error.contains("undefined variable `half`"), "{error}");
+}
+
+#[test]
+fn failed_statements_keep_their_changes_by_default() {
+    let mut interpreter = Interpreter::new();
+    interpret("var x = 1;", &mut interpreter).unwrap();
+    assert!(interpret("fun f() { x = 2; nil + 1; } f();", &mut interpreter).is_err());
+    assert_eq!(interpret("x;", &mut interpreter), Ok(Value::Number(2.0)));
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use rlox::interpreter::Interpreter;
+
+fn limited(bytes: usize) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.memory_limit = Some(bytes);
+    interpreter
+}
+
+#[test]
+fn concatenation_loop_stops_at_the_limit() {
+    let source = "
+var s = \"x\";
+while (true) s = s + s;
+";
+    assert_eq!(
+        common::interpret(source, &mut limited(1 << 20)),
+        Err("[3:20] Error: memory limit of 1048576 bytes exceeded".to_string())
+    );
+}
+
+#[test]
+fn repetition_and_lists_count_toward_the_limit() {
+    let error = common::interpret("\"ab\" * 1000;", &mut limited(1000)).unwrap_err();
+    assert!(
+        error.ends_with("memory limit of 1000 bytes exceeded"),
+        "{error}"
+    );
+    let error = common::interpret("range(0, 1000);", &mut limited(1000)).unwrap_err();
+    assert!(
+        error.ends_with("memory limit of 1000 bytes exceeded"),
+        "{error}"
+    );
+}
+
+#[test]
+fn programs_under_the_limit_run() {
+    let mut interpreter = limited(1000);
+    assert!(common::interpret("var s = \"ab\" * 10; [1, 2, 3];", &mut interpreter).is_ok());
+}
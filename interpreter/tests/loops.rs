@@ -0,0 +1,173 @@
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn do_while_runs_once_when_the_condition_is_false() {
+    assert_eq!(run("do print \"once\"; while (false);"), "once\n");
+}
+
+#[test]
+fn do_while_checks_the_condition_after_each_run() {
+    let source = "
+var i = 0;
+do {
+  print i;
+  i = i + 1;
+} while (i < 3);
+print \"end \" + i;
+";
+    assert_eq!(run(source), "0\n1\n2\nend 3\n");
+}
+
+#[test]
+fn break_and_continue_in_do_while() {
+    let source = "
+var i = 0;
+do {
+  i = i + 1;
+  if (i == 2) continue;
+  if (i == 4) break;
+  print i;
+} while (true);
+";
+    assert_eq!(run(source), "1\n3\n");
+}
+
+#[test]
+fn continue_in_do_while_still_checks_the_condition() {
+    let source = "
+var i = 0;
+do {
+  i = i + 1;
+  continue;
+} while (i < 5);
+print i;
+";
+    assert_eq!(run(source), "5\n");
+}
+
+#[test]
+fn while_with_an_empty_body() {
+    assert_eq!(
+        run(";;; var i = 0; while ((i = i + 1) < 3) ; print i;"),
+        "3\n"
+    );
+}
+
+#[test]
+fn break_skips_the_rest_of_a_labeled_block() {
+    let source = "
+outer: {
+  print 1;
+  if (true) break outer;
+  print 2;
+}
+print 3;
+";
+    assert_eq!(run(source), "1\n3\n");
+}
+
+#[test]
+fn break_leaves_every_block_up_to_its_label() {
+    let source = "
+outer: {
+  inner: {
+    break outer;
+    print 1;
+  }
+  print 2;
+}
+print 3;
+";
+    assert_eq!(run(source), "3\n");
+}
+
+#[test]
+fn break_passes_through_try() {
+    let source = "
+block: {
+  try { break block; } catch (e) { print \"caught\"; }
+  print 1;
+}
+print 2;
+";
+    assert_eq!(run(source), "2\n");
+}
+
+#[test]
+fn unlabeled_break_in_a_plain_block() {
+    let error = run_err("{ break; }");
+    assert!(
+        error.contains("can't use `break` outside of a loop"),
+        "{error}"
+    );
+}
+
+#[test]
+fn break_to_a_label_outside_the_function() {
+    let error = run_err("outer: { fun f() { break outer; } }");
+    assert!(
+        error.contains("no enclosing block is labeled `outer`"),
+        "{error}"
+    );
+    let error = run_err("break missing;");
+    assert!(
+        error.contains("no enclosing block is labeled `missing`"),
+        "{error}"
+    );
+}
+
+#[test]
+fn break_and_continue_in_while() {
+    let source = "
+var i = 0;
+while (true) {
+  i = i + 1;
+  if (i < 3) continue;
+  print i;
+  break;
+}
+";
+    assert_eq!(run(source), "3\n");
+}
+
+#[test]
+fn continue_in_for_still_runs_the_increment() {
+    let source = "
+for (var i = 0; i < 5; i = i + 1) {
+  if (i == 1) continue;
+  if (i == 3) break;
+  print i;
+}
+";
+    assert_eq!(run(source), "0\n2\n");
+}
+
+#[test]
+fn break_only_leaves_the_innermost_loop() {
+    let source = "
+for (var i = 0; i < 2; i = i + 1) {
+  for (var j = 0; j < 5; j = j + 1) {
+    if (j == 1) break;
+    print i + j;
+  }
+}
+";
+    assert_eq!(run(source), "0\n1\n");
+}
+
+#[test]
+fn jumps_outside_of_a_loop() {
+    let error = run_err("continue;");
+    assert!(
+        error.contains("can't use `continue` outside of a loop"),
+        "{error}"
+    );
+    // A function body starts outside of any loop, even when it's declared in one
+    let error = run_err("while (true) { fun f() { break; } }");
+    assert!(
+        error.contains("can't use `break` outside of a loop"),
+        "{error}"
+    );
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{eval, run, run_err};
+use rlox::value::Value;
+
+#[test]
+fn counter_object_with_a_method() {
+    let source = r#"
+var counter = {"count": 0, "inc": fun (self) { self.count = self.count + 1; }};
+counter.inc();
+counter.inc();
+print counter.count;
+"#;
+    assert_eq!(run(source), "2\n");
+}
+
+#[test]
+fn method_gets_its_own_map() {
+    let source = r#"
+fun make(name) {
+  return {"name": name, "greet": fun (self, greeting) { return greeting + " " + self.name; }};
+}
+var a = make("a");
+var b = make("b");
+print a.greet("hi");
+print b.greet("bye");
+"#;
+    assert_eq!(run(source), "hi a\nbye b\n");
+}
+
+#[test]
+fn method_is_only_bound_when_called_right_away() {
+    let source = r#"
+var o = {"f": fun (self) { return self; }};
+var f = o.f;
+f();
+"#;
+    assert!(run_err(source).contains("wrong number of arguments to `anonymous`: got 0, expected 1"));
+}
+
+#[test]
+fn missing_field() {
+    assert!(run_err(r#"var o = {"a": 1}; o.b;"#).contains("key `b` is not in the map"));
+}
+
+#[test]
+fn optional_get_on_nil_is_nil() {
+    assert_eq!(eval("nil?.x"), Value::Nil);
+    assert_eq!(eval("var obj = nil; obj?.a?.b"), Value::Nil);
+}
+
+#[test]
+fn optional_get_on_an_object_gets_the_field() {
+    assert_eq!(eval("var obj = {\"x\": 1}; obj?.x"), Value::Number(1.0));
+    assert_eq!(
+        eval("var obj = {\"a\": {\"b\": 2}}; obj?.a?.b"),
+        Value::Number(2.0)
+    );
+}
+
+#[test]
+fn optional_get_of_a_missing_field() {
+    assert!(run_err(r#"var o = {"a": 1}; o?.b;"#).contains("key `b` is not in the map"));
+}
@@ -0,0 +1,334 @@
+mod common;
+
+use common::{run, run_err};
+use rlox::{interpreter::Interpreter, value::Value};
+
+#[test]
+fn bool_follows_truthiness() {
+    assert_eq!(
+        run(r#"print bool(0); print bool(nil); print bool(""); print bool(false);"#),
+        "true\nfalse\ntrue\nfalse\n"
+    );
+}
+
+#[test]
+fn range_builds_a_list() {
+    assert_eq!(run("print range(0, 3);"), "[0, 1, 2]\n");
+    assert_eq!(run("print range(-2, 1);"), "[-2, -1, 0]\n");
+}
+
+#[test]
+fn empty_range() {
+    assert_eq!(run("print range(3, 3); print range(5, 1);"), "[]\n[]\n");
+}
+
+#[test]
+fn fractional_range_bound() {
+    assert!(run_err("range(0, 1.5);").contains("expected a whole number, got 1.5"));
+}
+
+#[test]
+fn pretty_indents_nested_collections() {
+    let expected = "\
+[
+  {
+    a: [
+      1,
+      2,
+    ],
+  },
+  3,
+  {},
+]
+";
+    assert_eq!(run(r#"print pretty([{"a": [1, 2]}, 3, {}]);"#), expected);
+    assert_eq!(
+        run(r#"print pretty("inline"); print pretty([]);"#),
+        "inline\n[]\n"
+    );
+}
+
+fn with_io(source: &str) -> Result<Value, String> {
+    let mut interpreter = Interpreter::new();
+    interpreter.define_io_natives();
+    common::interpret(source, &mut interpreter)
+}
+
+#[test]
+fn sleep_needs_allow_io() {
+    assert!(run_err("sleep(1);").contains("undefined variable `sleep`"));
+    assert_eq!(with_io("sleep(1);").unwrap(), Value::Nil);
+}
+
+#[test]
+fn sleep_checks_its_argument() {
+    let error = |source| with_io(source).unwrap_err();
+    assert!(error("sleep(-1);").contains("expected a non-negative number, got -1"));
+    assert!(error("sleep(1.5);").contains("expected a whole number, got 1.5"));
+    assert!(error(r#"sleep("a");"#).contains("expected type Number, got String"));
+}
+
+#[test]
+fn group_digits_groups_the_whole_part() {
+    let source = "
+print group_digits(1234567);
+print group_digits(-1000);
+print group_digits(1234.5);
+print group_digits(999);
+";
+    assert_eq!(run(source), "1,234,567\n-1,000\n1,234.5\n999\n");
+}
+
+#[test]
+fn keys_and_values_are_in_key_order() {
+    let source = r#"
+var m = {"b": 2, "a": 1, "c": [3]};
+print keys(m);
+print values(m);
+print keys({});
+"#;
+    assert_eq!(run(source), "[a, b, c]\n[1, 2, [3]]\n[]\n");
+}
+
+#[test]
+fn keys_and_values_need_a_map() {
+    assert!(run_err("keys([1]);").contains("expected type Map, got List ([1])"));
+    assert!(run_err("values(nil);").contains("expected type Map, got Nil"));
+}
+
+#[test]
+fn sort_returns_a_sorted_copy() {
+    let source = r#"
+var l = [3, 1, 2];
+print sort(l);
+print l;
+print sort(["b", "c", "a"]);
+print sort([]);
+"#;
+    assert_eq!(run(source), "[1, 2, 3]\n[3, 1, 2]\n[a, b, c]\n[]\n");
+}
+
+#[test]
+fn sort_of_incomparable_elements() {
+    assert!(run_err(r#"sort([1, "a"]);"#).contains("expected type String, got Number (1)"));
+}
+
+#[test]
+fn sort_by_descending() {
+    let source = "print sort_by([3, 1, 2], fun (a, b) { return b - a; });";
+    assert_eq!(run(source), "[3, 2, 1]\n");
+}
+
+#[test]
+fn sort_by_comparator_must_return_a_number() {
+    let source = r#"sort_by([1, 2], fun (a, b) { return "x"; });"#;
+    assert!(run_err(source).contains("expected type Number, got String (\"x\")"));
+}
+
+#[test]
+fn sort_by_passes_errors_from_the_comparator() {
+    let source = r#"sort_by([1, 2], fun (a, b) { throw "from comparator"; });"#;
+    assert!(run_err(source).contains("from comparator"));
+}
+
+const INC_AND_DOUBLE: &str = "
+fun inc(x) { return x + 1; }
+fun double(x) { return x * 2; }
+";
+
+#[test]
+fn compose_calls_the_inner_function_first() {
+    let source =
+        format!("{INC_AND_DOUBLE} print compose(inc, double)(5); print compose(double, inc)(5);");
+    assert_eq!(run(&source), "11\n12\n");
+}
+
+#[test]
+fn composed_function_takes_the_inner_functions_arguments() {
+    let source = format!(
+        "{INC_AND_DOUBLE} fun add(a, b) {{ return a + b; }} print compose(inc, add)(1, 2);"
+    );
+    assert_eq!(run(&source), "4\n");
+    let source =
+        format!("{INC_AND_DOUBLE} fun add(a, b) {{ return a + b; }} compose(inc, add)(1);");
+    assert!(run_err(&source).contains("got 1, expected 2"));
+}
+
+#[test]
+fn compose_needs_callables() {
+    let source = format!("{INC_AND_DOUBLE} compose(1, inc);");
+    assert!(run_err(&source).contains("expected type Callable, got Number (1)"));
+}
+
+fn random_sequence(interpreter: &mut Interpreter) -> Vec<Value> {
+    (0..5)
+        .map(|_| common::interpret("random();", interpreter).unwrap())
+        .collect()
+}
+
+#[test]
+fn the_same_seed_gives_the_same_sequence() {
+    let mut first = Interpreter::new();
+    let mut second = Interpreter::new();
+    common::interpret("seed(42);", &mut first).unwrap();
+    common::interpret("seed(42);", &mut second).unwrap();
+    assert_eq!(random_sequence(&mut first), random_sequence(&mut second));
+}
+
+#[test]
+fn different_seeds_give_different_sequences() {
+    let mut first = Interpreter::new();
+    let mut second = Interpreter::new();
+    first.seed_random(1);
+    second.seed_random(2);
+    assert_ne!(random_sequence(&mut first), random_sequence(&mut second));
+}
+
+#[test]
+fn random_numbers_are_in_the_unit_interval() {
+    let mut interpreter = Interpreter::new();
+    interpreter.seed_random(0);
+    for _ in 0..1000 {
+        let Value::Number(n) = common::interpret("random();", &mut interpreter).unwrap() else {
+            panic!("random() should give a number");
+        };
+        assert!((0.0..1.0).contains(&n), "{n}");
+    }
+}
+
+#[test]
+fn seed_needs_a_whole_number() {
+    assert!(run_err("seed(1.5);").contains("whole number"));
+    assert!(run_err("seed();").contains("seed"));
+}
+
+#[test]
+fn pow_of_whole_and_fractional_exponents() {
+    assert_eq!(run("print pow(2, 10);"), "1024\n");
+    assert_eq!(run("print pow(2, -1);"), "0.5\n");
+    assert_eq!(run("print pow(4, 0.5);"), "2\n");
+}
+
+#[test]
+fn gcd_and_lcm() {
+    assert_eq!(run("print gcd(12, 18);"), "6\n");
+    assert_eq!(run("print gcd(-12, 18);"), "6\n");
+    assert_eq!(run("print lcm(4, 6);"), "12\n");
+    assert_eq!(run("print lcm(0, 5);"), "0\n");
+}
+
+#[test]
+fn gcd_needs_whole_numbers() {
+    let error = run_err("gcd(1.5, 3);");
+    assert!(
+        error.contains("expected a whole number, got 1.5"),
+        "{error}"
+    );
+    let error = run_err("lcm(4, 0.5);");
+    assert!(
+        error.contains("expected a whole number, got 0.5"),
+        "{error}"
+    );
+}
+
+#[test]
+fn pow_needs_numbers() {
+    let error = run_err(r#"pow("a", 2);"#);
+    assert!(error.contains("expected type Number"), "{error}");
+}
+
+#[test]
+fn globals_contains_defined_globals() {
+    let source = r#"
+var a = 1;
+var b = "two";
+var g = globals();
+print g.a;
+print g.b;
+"#;
+    assert_eq!(run(source), "1\ntwo\n");
+}
+
+#[test]
+fn globals_is_a_snapshot() {
+    let source = r#"
+var a = 1;
+var g = globals();
+g.a = 5;
+var later = 2;
+print a;
+print is_callable(g.clock);
+print keys(g);
+"#;
+    let output = run(source);
+    assert!(output.starts_with("1\ntrue\n"), "{output}");
+    // Neither `g` nor `later` existed when the snapshot was taken
+    assert!(!output.contains("later"), "{output}");
+    assert!(!output.contains(", g,"), "{output}");
+}
+
+#[test]
+fn is_callable_and_is_native() {
+    let source = "
+fun f() {}
+class A {}
+print is_callable(f);
+print is_native(f);
+print is_callable(clock);
+print is_native(clock);
+print is_callable(1);
+print is_native(1);
+print is_callable(A);
+print is_native(fun () {});
+";
+    assert_eq!(
+        run(source),
+        "true\nfalse\ntrue\ntrue\nfalse\nfalse\ntrue\nfalse\n"
+    );
+}
+
+#[test]
+fn bytes_round_trip_through_a_file() {
+    let path = std::env::temp_dir().join(format!("rlox-bytes-{}.bin", std::process::id()));
+    let path = format!("{:?}", path.to_str().expect("temp path is utf-8"));
+    let source = format!(
+        "
+write_bytes({path}, bytes([0, 255, 128, 10, 0]));
+var read = read_bytes({path});
+[read.len(), read[0], read[1], read[2], read[3], read[4]];
+"
+    );
+    let result = with_io(&source);
+    let _ = std::fs::remove_file(path.trim_matches('"'));
+    let expected = Value::List(std::rc::Rc::new(std::cell::RefCell::new(
+        [5.0, 0.0, 255.0, 128.0, 10.0, 0.0]
+            .map(Value::Number)
+            .to_vec(),
+    )));
+    assert_eq!(result.unwrap(), expected);
+}
+
+#[test]
+fn bytes_display_as_hex() {
+    assert_eq!(run("print bytes([0, 255, 16]);"), "<bytes 00 ff 10>\n");
+    assert_eq!(run("print bytes([]);"), "<bytes>\n");
+}
+
+#[test]
+fn bytes_out_of_range() {
+    let error = run_err("bytes([256]);");
+    assert!(
+        error.contains("256 doesn't fit in a byte (0 to 255)"),
+        "{error}"
+    );
+    let error = run_err("bytes([1])[3];");
+    assert!(
+        error.contains("index 3 is out of bounds for length 1"),
+        "{error}"
+    );
+}
+
+#[test]
+fn read_bytes_needs_allow_io() {
+    assert!(run_err(r#"read_bytes("x");"#).contains("undefined variable `read_bytes`"));
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use common::{eval, run, run_err};
+use rlox::value::Value;
+
+#[test]
+fn is_compares_identity_of_heap_values() {
+    assert_eq!(run("print [1] is [1]; print [1] == [1];"), "false\ntrue\n");
+    assert_eq!(
+        run("var a = [1]; var b = a; print a is a; print a is b;"),
+        "true\ntrue\n"
+    );
+    assert_eq!(
+        run("fun f() {} fun g() {} print f is f; print f is g;"),
+        "true\nfalse\n"
+    );
+}
+
+#[test]
+fn is_compares_primitives_by_value() {
+    assert_eq!(
+        run("print 1 is 1; print nil is nil; print 1 is \"1\";"),
+        "true\ntrue\nfalse\n"
+    );
+}
+
+#[test]
+fn string_repetition() {
+    assert_eq!(
+        run(r#"print "ab" * 3; print 3 * "ab"; print "ab" * 0;"#),
+        "ababab\nababab\n\n"
+    );
+}
+
+#[test]
+fn string_repetition_errors() {
+    assert!(run_err(r#""a" * "b";"#).contains("expected type Number, got String"));
+    assert!(run_err(r#""a" * -1;"#).contains("expected a non-negative number, got -1"));
+    assert!(run_err(r#""a" * 1.5;"#).contains("expected a whole number, got 1.5"));
+}
+
+#[test]
+fn equality_matrix() {
+    // Values in the same group are equal to each other, and unequal to everything else. NaN has
+    // no group since it isn't even equal to itself.
+    let values = [
+        ("1", Some(0)),
+        ("1.0", Some(0)),
+        ("0", Some(1)),
+        ("-0", Some(1)),
+        ("0 / 0", None),
+        ("\"1\"", Some(2)),
+        ("\"\" + \"1\"", Some(2)),
+        ("\"a\"", Some(3)),
+        ("true", Some(4)),
+        ("1 == 1", Some(4)),
+        ("false", Some(5)),
+        ("nil", Some(6)),
+        ("[1]", Some(7)),
+        ("[1.0]", Some(7)),
+        ("[]", Some(8)),
+    ];
+    for (a, group_a) in values {
+        for (b, group_b) in values {
+            let equal = group_a.is_some() && group_a == group_b;
+            assert_eq!(
+                eval(&format!("({a}) == ({b})")),
+                Value::Boolean(equal),
+                "{a} == {b}"
+            );
+            assert_eq!(
+                eval(&format!("({a}) != ({b})")),
+                Value::Boolean(!equal),
+                "{a} != {b}"
+            );
+        }
+    }
+}
+
+#[test]
+fn floor_division() {
+    assert_eq!(eval("7 div 2"), Value::Number(3.0));
+    assert_eq!(eval("-7 div 2"), Value::Number(-4.0));
+    assert_eq!(eval("7.5 div 2"), Value::Number(3.0));
+    // Same precedence as `*`, grouping to the left
+    assert_eq!(eval("1 + 7 div 2 * 2"), Value::Number(7.0));
+    assert_eq!(eval("1 div 0"), Value::Number(f64::INFINITY));
+    assert!(run_err(r#""a" div 2;"#).contains("expected type Number, got String"));
+}
+
+#[test]
+fn div_is_still_a_keyword_where_a_name_would_go() {
+    assert!(run_err("var div = 1;").contains("unexpected token `div`, expected `identifier`"));
+}
+
+const PIPE_FUNCTIONS: &str = "
+fun double(x) { return x * 2; }
+fun inc(x) { return x + 1; }
+";
+
+#[test]
+fn pipe_calls_the_right_side() {
+    assert_eq!(run(&format!("{PIPE_FUNCTIONS} print 5 |> double;")), "10\n");
+}
+
+#[test]
+fn pipes_chain_from_left_to_right() {
+    let source = format!("{PIPE_FUNCTIONS} print 5 |> double |> inc; print 5 |> inc |> double;");
+    assert_eq!(run(&source), "11\n12\n");
+    // Lower precedence than arithmetic
+    assert_eq!(
+        run(&format!("{PIPE_FUNCTIONS} print 1 + 2 |> double;")),
+        "6\n"
+    );
+}
+
+#[test]
+fn pipe_into_something_that_cant_be_called() {
+    assert!(
+        run_err("5 |> 3;").contains("the right side of `|>` must be something that can be called")
+    );
+}
+
+#[test]
+fn lists_compare_element_by_element() {
+    assert_eq!(eval("[1, 2] < [1, 3]"), Value::Boolean(true));
+    assert_eq!(eval("[2] > [1, 5]"), Value::Boolean(true));
+    assert_eq!(eval("[[1, 2]] < [[1, 3]]"), Value::Boolean(true));
+    assert_eq!(eval("[1, 2] <= [1, 2]"), Value::Boolean(true));
+    assert_eq!(eval("[1, 2] < [1, 2]"), Value::Boolean(false));
+}
+
+#[test]
+fn list_prefix_is_less() {
+    assert_eq!(eval("[1] < [1, 0]"), Value::Boolean(true));
+    assert_eq!(eval("[] < [0]"), Value::Boolean(true));
+    assert_eq!(eval("[1, 0] > [1]"), Value::Boolean(true));
+}
+
+#[test]
+fn lists_with_mixed_elements() {
+    assert!(run_err(r#"[1, "a"] < [1, 2];"#).contains("expected type String, got Number (2)"));
+    // Elements after the first difference aren't compared
+    assert_eq!(eval(r#"[1, "a"] < [2, 2]"#), Value::Boolean(true));
+}
+
+#[test]
+fn functions_equal_themselves() {
+    let source = "
+fun f() {}
+fun h() {}
+var g = f;
+print f == g;
+print f == h;
+print [f] == [g];
+print {\"k\": f} == {\"k\": g};
+print clock == clock;
+";
+    assert_eq!(run(source), "true\nfalse\ntrue\ntrue\ntrue\n");
+}
+
+#[test]
+fn type_errors_show_the_value() {
+    assert!(run_err(r#"1 < "hello";"#).ends_with(r#"expected type Number, got String ("hello")"#));
+    assert!(run_err("-[1, 2];").ends_with("expected type Number, got List ([1, 2])"));
+    // Values without anything more to show only name their type
+    assert!(run_err("nil();").ends_with("expected type Callable, got Nil"));
+}
+
+#[test]
+fn long_values_are_truncated_in_type_errors() {
+    let error = run_err(r#"-("a" * 100);"#);
+    assert!(
+        error.ends_with(&format!(r#"got String ("{}...")"#, "a".repeat(20))),
+        "{error}"
+    );
+}
+
+#[test]
+fn power_precedence_and_associativity() {
+    assert_eq!(eval("-2 ** 2"), Value::Number(-4.0));
+    assert_eq!(eval("(-2) ** 2"), Value::Number(4.0));
+    assert_eq!(eval("2 ** 3 ** 2"), Value::Number(512.0));
+    assert_eq!(eval("2 * 3 ** 2"), Value::Number(18.0));
+    assert_eq!(eval("2 ** -1"), Value::Number(0.5));
+}
+
+#[test]
+fn power_needs_numbers() {
+    let error = run_err(r#"print "a" ** 2;"#);
+    assert!(error.contains("expected type Number"), "{error}");
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use common::{run, run_with, Options, Output};
+use rlox::{interpreter::Interpreter, value::Value};
+
+fn print_as_function() -> Options {
+    Options {
+        print_as_function: true,
+        ..Options::default()
+    }
+}
+
+#[test]
+fn print_statement_adds_a_newline() {
+    assert_eq!(run(r#"print "a"; print 1 + 2;"#), "a\n3\n");
+}
+
+#[test]
+fn print_statement_without_the_flag() {
+    assert_eq!(run(r#"print ("hi");"#), "hi\n");
+}
+
+#[test]
+fn print_as_a_call() {
+    let options = print_as_function();
+    assert_eq!(run_with(r#"print("hi");"#, &options), "hi\n");
+    assert_eq!(run_with(r#"print("a", 1, nil);"#, &options), "a 1 nil\n");
+    assert_eq!(
+        run_with("var p = print; p(); print(p(2));", &options),
+        "\n2\nnil\n"
+    );
+}
+
+#[test]
+fn print_hook_collects_printed_values() {
+    let printed = Rc::new(RefCell::new(Vec::new()));
+    let hook = printed.clone();
+    let output = Output::default();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Box::new(output.clone()));
+    interpreter.set_print_hook(Box::new(move |value| hook.borrow_mut().push(value.clone())));
+    common::interpret(r#"print 1; print "a" + "b"; print nil;"#, &mut interpreter).unwrap();
+    assert_eq!(
+        *printed.borrow(),
+        [Value::Number(1.0), Value::String("ab".into()), Value::Nil]
+    );
+    // The hook replaces writing to the output
+    assert_eq!(output.text(), "");
+}
+
+#[test]
+fn printing_concatenation_and_errors_agree() {
+    let source = r#"
+var values = [3.0, 0 / 0, 1 / 0];
+for (var i = 0; i < 3; i = i + 1) {
+  print values[i];
+  print "" + values[i];
+}
+"#;
+    assert_eq!(run(source), "3\n3\nNaN\nNaN\nInf\nInf\n");
+    assert!(common::run_err(r#""" + true;"#).ends_with("got Boolean (true)"));
+}
+
+#[test]
+fn puts_adds_no_newline() {
+    assert_eq!(run(r#"puts("a"); puts("a");"#), "aa");
+    assert_eq!(run(r#"puts("a"); print "a"; puts(1);"#), "aa\n1");
+}
+
+#[test]
+fn every_way_of_printing_goes_to_the_print_hook() {
+    let printed = Rc::new(RefCell::new(Vec::new()));
+    let hook = printed.clone();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_print_hook(Box::new(move |value| hook.borrow_mut().push(value.clone())));
+    common::interpret(r#"print 1; puts("a"); print [2];"#, &mut interpreter).unwrap();
+    let printed = printed.borrow();
+    assert_eq!(
+        printed[..2],
+        [Value::Number(1.0), Value::String("a".into())]
+    );
+    assert_eq!(printed[2].to_display_string(), "[2]");
+}
+
+#[test]
+fn negative_zero_keeps_its_sign() {
+    assert_eq!(
+        run("print -0.0; print 0 * -1; print [-0]; print 0;"),
+        "-0\n-0\n[-0]\n0\n"
+    );
+}
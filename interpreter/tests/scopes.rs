@@ -0,0 +1,86 @@
+mod common;
+
+use common::run;
+use rlox::{
+    ast::ResolvedStatement,
+    error::{Located, RuntimeErrorKind},
+    interpreter::Interpreter,
+};
+
+#[test]
+fn nested_variables_are_found_where_they_were_resolved() {
+    let source = "
+var a = \"global\";
+fun f() {
+  var b = \"outer\";
+  {
+    var c = \"inner\";
+    {
+      b = b + \"!\";
+      print a + \" \" + b + \" \" + c;
+    }
+  }
+}
+f();
+";
+    assert_eq!(run(source), "global outer! inner\n");
+}
+
+#[test]
+fn return_from_nested_blocks_leaves_the_global_scope_current() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_print_hook(Box::new(|_| {}));
+    let source = "
+fun f() {
+  { { { return 1; } } }
+}
+for (var i = 0; i < 3; i = i + 1) {
+  { f(); }
+  if (i == 1) break;
+}
+";
+    common::interpret(source, &mut interpreter).unwrap();
+    // Only the global scope is left, with no scopes inside it
+    assert!(interpreter
+        .environment
+        .borrow()
+        .dump()
+        .starts_with("scope 0 (global):"));
+    // A later top level declaration is still global
+    common::interpret(
+        "var late = 1; fun g() { return late; } print g();",
+        &mut interpreter,
+    )
+    .unwrap();
+}
+
+/// `var <name>;` at the given column, built by hand since the resolver would reject redefinitions
+fn declare(name: &str, column: usize) -> ResolvedStatement {
+    ResolvedStatement::Var(
+        Located {
+            location: (1, column),
+            value: name.to_string(),
+        },
+        None,
+    )
+}
+
+#[test]
+fn redefinition_in_a_block_errors_without_the_resolver() {
+    let program = vec![ResolvedStatement::Block(vec![
+        declare("a", 3),
+        declare("a", 10),
+    ])];
+    let error = Interpreter::new().interpret(program).unwrap_err();
+    assert_eq!(error.location, (1, 10));
+    assert!(matches!(
+        error.value,
+        RuntimeErrorKind::Redefinition(name) if name == "a"
+    ));
+}
+
+#[test]
+fn redefinition_of_a_global_is_allowed() {
+    let program = vec![declare("a", 1), declare("a", 8)];
+    assert!(Interpreter::new().interpret(program).is_ok());
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::{eval, run_err};
+use rlox::value::Value;
+
+#[test]
+fn trim_then_lower() {
+    assert_eq!(eval(r#""  Hi ".trim().lower()"#), Value::from("hi"));
+}
+
+#[test]
+fn upper_and_lower() {
+    assert_eq!(eval(r#""MiXed 1".upper()"#), Value::from("MIXED 1"));
+    assert_eq!(eval(r#""MiXed 1".lower()"#), Value::from("mixed 1"));
+    assert_eq!(eval(r#"var s = "ß"; s.upper()"#), Value::from("SS"));
+}
+
+#[test]
+fn len_counts_characters() {
+    assert_eq!(eval(r#""héllo".len()"#), Value::Number(5.0));
+    assert_eq!(eval(r#""".len()"#), Value::Number(0.0));
+}
+
+#[test]
+fn unknown_string_method() {
+    let error = run_err(r#""x".nope();"#);
+    assert!(error.contains("String has no method `nope`"), "{error}");
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use rlox::interpreter::Interpreter;
+
+/// The locations the trace hook is called with while running a program
+fn trace(source: &str) -> Vec<(usize, usize)> {
+    let traced = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    let hook = traced.clone();
+    interpreter.set_trace(Box::new(move |location| hook.borrow_mut().push(location)));
+    interpreter.set_print_hook(Box::new(|_| {}));
+    common::interpret(source, &mut interpreter).unwrap();
+    let traced = traced.borrow().clone();
+    traced
+}
+
+#[test]
+fn every_executed_statement_is_traced() {
+    let source = "\
+var a;
+fun f() {
+  return;
+}
+class C {}
+f();
+print a;
+";
+    // Declarations and `return` are located at their keyword, other statements at their first
+    // expression
+    assert_eq!(
+        trace(source),
+        [(1, 1), (2, 1), (5, 1), (6, 2), (3, 3), (7, 7)]
+    );
+}
+
+#[test]
+fn statements_run_repeatedly_are_traced_each_time() {
+    let source = "for (var i = 0; i < 3; i = i + 1) print i;";
+    let prints = trace(source).into_iter().filter(|l| *l == (1, 41)).count();
+    assert_eq!(prints, 3);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{eval, run, run_err, run_result, run_with, Options};
+use rlox::value::Value;
+
+fn comma_operator() -> Options {
+    Options {
+        comma_operator: true,
+        ..Options::default()
+    }
+}
+
+#[test]
+fn comma_operator_results_in_the_last_value() {
+    let source = "
+fun f(x) { print x; return x; }
+print (f(1), f(2), f(3));
+";
+    assert_eq!(run_with(source, &comma_operator()), "1\n2\n3\n3\n");
+}
+
+#[test]
+fn comma_operator_as_a_statement() {
+    let source = "
+var a;
+var b;
+a = 1, b = a + 1;
+print b;
+";
+    assert_eq!(run_with(source, &comma_operator()), "2\n");
+}
+
+#[test]
+fn comma_operator_keeps_arguments_and_elements_apart() {
+    let source = "
+fun add(a, b) { return a + b; }
+print add(1, 2);
+print [1, 2];
+print add((1, 2), 3);
+";
+    assert_eq!(run_with(source, &comma_operator()), "3\n[1, 2]\n5\n");
+}
+
+#[test]
+fn commas_in_parentheses_make_a_tuple_without_the_flag() {
+    let Value::Tuple(elements) = eval("(1, 2, 3)") else {
+        panic!("expected a tuple");
+    };
+    assert_eq!(elements[..], [1.0.into(), 2.0.into(), 3.0.into()]);
+    // With nothing around it, a comma isn't an operator
+    assert!(run_result("1, 2;", &Options::default()).is_err());
+}
+
+#[test]
+fn return_and_destructure_a_pair() {
+    let source = "
+fun divide(a, b) { return a div b, a - b * (a div b); }
+var (quotient, remainder) = divide(7, 2);
+print quotient;
+print remainder;
+";
+    assert_eq!(run(source), "3\n1\n");
+}
+
+#[test]
+fn destructuring_the_wrong_number_of_elements() {
+    let error = run_err("fun pair() { return 1, 2; } var (a, b, c) = pair();");
+    assert!(
+        error.contains("can't unpack a tuple of 2 elements into 3 variables"),
+        "{error}"
+    );
+}